@@ -0,0 +1,137 @@
+/// Battery
+///
+/// The battery rail is sampled through a 1:2 voltage divider (e.g. two 100kΩ resistors) on
+/// GPIO3, ADC1 channel 3. With 11dB attenuation the ADC reads up to ~2.5V, enough for a fully
+/// charged LiPo (4.2V) after the divider. The ADC calibration curve from eFuse converts the
+/// reading to millivolts.
+///
+/// The battery is checked periodically while not measuring: after [`LOW_BATTERY_READINGS`]
+/// consecutive readings below `LOW_BATTERY_MV` the device sends a `LowPowerWarning` and powers
+/// off before the LiPo is over-discharged. The readings also have to agree with each other, as
+/// GPIO3 floats anywhere between `NO_BATTERY_MV` and `LOW_BATTERY_MV` on boards without the
+/// divider.
+#[cfg(target_arch = "riscv32")]
+use esp_hal::{
+    analog::adc::{Adc, AdcCalCurve, AdcConfig, AdcPin, Attenuation},
+    peripherals::{ADC1, GPIO3},
+    Async,
+};
+
+/// Ratio of the battery voltage to the voltage at the ADC pin
+#[cfg(target_arch = "riscv32")]
+const BATTERY_DIVIDER_RATIO: u32 = 2;
+/// Battery voltage below which the device warns and powers off, in millivolts
+pub const LOW_BATTERY_MV: u32 = 3300;
+/// Voltage below which no battery is considered connected, e.g. when powered over USB without
+/// the divider, in millivolts
+const NO_BATTERY_MV: u32 = 1000;
+/// Interval between battery checks, in seconds
+pub const BATTERY_CHECK_INTERVAL_S: u64 = 60;
+/// Number of consecutive low readings after which the battery is considered low
+pub const LOW_BATTERY_READINGS: u8 = 3;
+/// Maximum difference between consecutive low readings, in millivolts
+///
+/// A discharging battery drops by a few millivolts between checks, a floating pin jumps around.
+const LOW_BATTERY_SPREAD_MV: u32 = 100;
+
+/// Check whether a battery voltage is too low to keep running
+pub fn is_low(voltage_mv: u32) -> bool {
+    (NO_BATTERY_MV..LOW_BATTERY_MV).contains(&voltage_mv)
+}
+
+/// Tells a low battery apart from noise and floating readings, see [`LOW_BATTERY_READINGS`]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LowBatteryDetector {
+    /// Number of consecutive low readings
+    low_readings: u8,
+    /// Last reading, in millivolts
+    last_mv: u32,
+}
+
+impl LowBatteryDetector {
+    /// Create a detector without readings
+    pub const fn new() -> Self {
+        Self {
+            low_readings: 0,
+            last_mv: 0,
+        }
+    }
+
+    /// Add a reading in millivolts, returns whether the battery is low
+    pub fn update(&mut self, voltage_mv: u32) -> bool {
+        if !is_low(voltage_mv) {
+            self.low_readings = 0;
+        } else if self.low_readings > 0 && voltage_mv.abs_diff(self.last_mv) > LOW_BATTERY_SPREAD_MV
+        {
+            self.low_readings = 1;
+        } else {
+            self.low_readings = self.low_readings.saturating_add(1);
+        }
+        self.last_mv = voltage_mv;
+        self.low_readings >= LOW_BATTERY_READINGS
+    }
+}
+
+/// Battery voltage monitor
+#[cfg(target_arch = "riscv32")]
+pub struct Battery {
+    /// ADC sampling the divider
+    adc: Adc<'static, ADC1<'static>, Async>,
+    /// Pin connected to the divider midpoint
+    pin: AdcPin<GPIO3<'static>, ADC1<'static>, AdcCalCurve<ADC1<'static>>>,
+}
+
+#[cfg(target_arch = "riscv32")]
+impl Battery {
+    /// Set up the ADC on the divider pin
+    pub fn new(adc: ADC1<'static>, pin: GPIO3<'static>) -> Self {
+        let mut config = AdcConfig::new();
+        let pin =
+            config.enable_pin_with_cal::<_, AdcCalCurve<ADC1<'static>>>(pin, Attenuation::_11dB);
+        Self {
+            adc: Adc::new(adc, config).into_async(),
+            pin,
+        }
+    }
+
+    /// Sample the battery voltage, in millivolts
+    pub async fn voltage_mv(&mut self) -> u32 {
+        let pin_mv = self.adc.read_oneshot(&mut self.pin).await;
+        pin_mv as u32 * BATTERY_DIVIDER_RATIO
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_battery_is_not_low() {
+        assert!(!is_low(0));
+        assert!(!is_low(NO_BATTERY_MV - 1));
+        assert!(is_low(NO_BATTERY_MV));
+        assert!(is_low(LOW_BATTERY_MV - 1));
+        assert!(!is_low(LOW_BATTERY_MV));
+    }
+
+    #[test]
+    fn low_battery_needs_consecutive_readings() {
+        let mut detector = LowBatteryDetector::new();
+
+        assert!(!detector.update(3250));
+        assert!(!detector.update(3240));
+        assert!(!detector.update(3700));
+        assert!(!detector.update(3230));
+        assert!(!detector.update(3220));
+        assert!(detector.update(3210));
+    }
+
+    #[test]
+    fn floating_readings_are_not_low() {
+        let mut detector = LowBatteryDetector::new();
+
+        for voltage_mv in [1200, 2900, 1800, 3100, 1500, 2400] {
+            assert!(!detector.update(voltage_mv));
+        }
+    }
+}
@@ -2,14 +2,15 @@
 ///
 /// This module provides the BLE functionality for the Progressor.
 /// It includes the BLE advertising data, the GATT server, and the BLE connection.
-use arrayvec::ArrayVec;
-use defmt::{debug, info};
-use trouble_host::{
-    advertise::{AD_FLAG_LE_LIMITED_DISCOVERABLE, SIMUL_LE_BR_HOST},
-    prelude::*,
-};
+use defmt::{debug, info, warn};
+use embassy_time::Duration;
+use trouble_host::prelude::*;
 
-use crate::progressor::{DataPoint, MAX_PAYLOAD_SIZE};
+use crate::{
+    device_name::MAX_NAME_LEN,
+    progressor::DataPoint,
+    watchdog::{self, BleOperation},
+};
 
 /// Max number of connections
 pub const CONNECTIONS_MAX: usize = 1;
@@ -17,29 +18,99 @@ pub const CONNECTIONS_MAX: usize = 1;
 pub const L2CAP_CHANNELS_MAX: usize = 2; // Signal + att
 /// Size of L2CAP packets
 pub const L2CAP_MTU: usize = 255;
+/// Size of the control point buffer, enough for an op code followed by a device name
+///
+/// Writes longer than 20 bytes need a larger ATT MTU than the default.
+pub const CONTROL_POINT_SIZE: usize = 1 + MAX_NAME_LEN;
+
+/// Maximum size of the advertising data and of the scan response, in bytes
+const ADVERTISING_DATA_SIZE: usize = 31;
 
-/// Progressor BLE Scanning Response
-const SCAN_RESPONSE_DATA: &[u8] = &[
-    AD_FLAG_LE_LIMITED_DISCOVERABLE | SIMUL_LE_BR_HOST,
-    7_u8, // BLE_GAP_AD_TYPE_128BIT_SERVICE_UUID_COMPLETE
-    0x57,
-    0xad,
-    0xfe,
-    0x4f,
-    0xd3,
-    0x13,
-    0xcc,
-    0x9d,
-    0xc9,
-    0x40,
-    0xa6,
-    0x1e,
-    0x01,
-    0x17,
-    0x4e,
-    0x7e, //UUID
+/// Minimum advertising interval in milliseconds.
+///
+/// Longer intervals reduce power consumption while waiting for a connection, shorter ones
+/// make the device show up (and reconnect) faster. The BLE spec allows 20ms to 10.24s.
+pub const ADVERTISING_INTERVAL_MIN_MS: u64 = 100;
+/// Maximum advertising interval in milliseconds.
+pub const ADVERTISING_INTERVAL_MAX_MS: u64 = 200;
+
+/// Minimum connection interval requested after connecting, in milliseconds.
+///
+/// Many centrals ignore the preferred connection parameters and pick slow intervals, which
+/// throttles the 80Hz notification stream, so a faster interval is requested explicitly.
+pub const CONNECTION_INTERVAL_MIN_MS: u64 = 15;
+/// Maximum connection interval requested after connecting, in milliseconds.
+pub const CONNECTION_INTERVAL_MAX_MS: u64 = 30;
+/// Peripheral latency (number of connection events that can be skipped) requested.
+pub const CONNECTION_MAX_LATENCY: u16 = 0;
+/// Supervision timeout requested after connecting, in milliseconds.
+pub const CONNECTION_SUPERVISION_TIMEOUT_MS: u64 = 4000;
+
+/// Default Progressor base UUID (`7e4e0000-1ea6-40c9-9dcc-13d34ffead57`), little endian
+const DEFAULT_PROGRESSOR_BASE_UUID: [u8; 16] = [
+    0x57, 0xad, 0xfe, 0x4f, 0xd3, 0x13, 0xcc, 0x9d, 0xc9, 0x40, 0xa6, 0x1e, 0x00, 0x00, 0x4e, 0x7e,
 ];
 
+/// Progressor base UUID, little endian
+///
+/// Can be overridden at build time with the `PROGRESSOR_UUID` environment variable (e.g.
+/// `7e4e0000-1ea6-40c9-9dcc-13d34ffead57`) to avoid clashing with genuine Progressors. Bits
+/// 96 to 111 (`0000` in the example) are replaced by the service and characteristic ids.
+const PROGRESSOR_BASE_UUID: [u8; 16] = match option_env!("PROGRESSOR_UUID") {
+    Some(uuid) => parse_uuid(uuid),
+    None => DEFAULT_PROGRESSOR_BASE_UUID,
+};
+
+/// Progressor service UUID
+pub const PROGRESSOR_SERVICE_UUID: Uuid = Uuid::new_long(progressor_uuid(0x1701));
+/// Data point characteristic UUID
+pub const DATA_POINT_UUID: Uuid = Uuid::new_long(progressor_uuid(0x1702));
+/// Control point characteristic UUID
+pub const CONTROL_POINT_UUID: Uuid = Uuid::new_long(progressor_uuid(0x1703));
+
+/// Builds a Progressor UUID from the base UUID and a 16-bit id, little endian
+const fn progressor_uuid(id: u16) -> [u8; 16] {
+    let mut uuid = PROGRESSOR_BASE_UUID;
+    let id = id.to_le_bytes();
+    uuid[12] = id[0];
+    uuid[13] = id[1];
+    uuid
+}
+
+/// Parses a UUID string (hex digits, dashes ignored) into little endian bytes
+///
+/// Fails the build if the string is not a valid UUID.
+const fn parse_uuid(uuid: &str) -> [u8; 16] {
+    let chars = uuid.as_bytes();
+    let mut bytes = [0u8; 16];
+    let mut nibbles = 0;
+    let mut i = 0;
+    while i < chars.len() {
+        let nibble = match chars[i] {
+            b'0'..=b'9' => chars[i] - b'0',
+            b'a'..=b'f' => chars[i] - b'a' + 10,
+            b'A'..=b'F' => chars[i] - b'A' + 10,
+            b'-' => {
+                i += 1;
+                continue;
+            }
+            _ => panic!("Invalid character in PROGRESSOR_UUID"),
+        };
+        assert!(nibbles < 32, "PROGRESSOR_UUID is too long");
+        // The string is big endian, fill the bytes from the end
+        let byte = 15 - nibbles / 2;
+        if nibbles % 2 == 0 {
+            bytes[byte] = nibble << 4;
+        } else {
+            bytes[byte] |= nibble;
+        }
+        nibbles += 1;
+        i += 1;
+    }
+    assert!(nibbles == 32, "PROGRESSOR_UUID is too short");
+    bytes
+}
+
 // GATT Server definition
 #[gatt_server]
 pub struct Server {
@@ -47,19 +118,21 @@ pub struct Server {
 }
 
 /// Tindeq Progressor service
-#[gatt_service(uuid = "7e4e1701-1ea6-40c9-9dcc-13d34ffead57")]
+#[gatt_service(uuid = PROGRESSOR_SERVICE_UUID)]
 pub struct ProgressorService {
     /// Data Point - for receiving data from the Progressor
-    #[characteristic(uuid = "7e4e1702-1ea6-40c9-9dcc-13d34ffead57", notify)]
+    ///
+    /// Clients reading instead of subscribing get the latest weight measurement.
+    #[characteristic(uuid = DATA_POINT_UUID, read, notify)]
     pub data_point: DataPoint,
 
     /// Control Point - for sending commands to the Progressor
     #[characteristic(
-        uuid = "7e4e1703-1ea6-40c9-9dcc-13d34ffead57",
+        uuid = CONTROL_POINT_UUID,
         write,
         write_without_response
     )]
-    pub control_point: [u8; MAX_PAYLOAD_SIZE], // Buffer for command data
+    pub control_point: [u8; CONTROL_POINT_SIZE], // Buffer for command data
 }
 
 /// Create an advertiser to use to connect to a BLE Central, and wait for it to connect.
@@ -68,47 +141,79 @@ pub async fn advertise<'values, 'server, C: Controller>(
     peripheral: &mut Peripheral<'values, C, DefaultPacketPool>,
     server: &'server Server<'values>,
 ) -> Result<GattConnection<'values, 'server, DefaultPacketPool>, BleHostError<C::Error>> {
-    let advertising_data = advertising_data(name.as_bytes()).expect("Valid advertising data");
+    let mut adv_data = [0u8; ADVERTISING_DATA_SIZE];
+    let adv_len = advertising_data(name, &mut adv_data).expect("Valid advertising data");
+    let mut scan_data = [0u8; ADVERTISING_DATA_SIZE];
+    let scan_len = scan_response_data(&mut scan_data).expect("Valid scan response data");
+
+    let advertising_params = AdvertisementParameters {
+        interval_min: Duration::from_millis(ADVERTISING_INTERVAL_MIN_MS),
+        interval_max: Duration::from_millis(ADVERTISING_INTERVAL_MAX_MS),
+        ..Default::default()
+    };
 
     debug!("Advertising BLE");
+    let pending = watchdog::ble_operation(BleOperation::Advertise);
     let advertiser = peripheral
         .advertise(
-            &Default::default(),
+            &advertising_params,
             Advertisement::ConnectableScannableUndirected {
-                adv_data: advertising_data.as_slice(),
-                scan_data: SCAN_RESPONSE_DATA,
+                adv_data: &adv_data[..adv_len],
+                scan_data: &scan_data[..scan_len],
             },
         )
         .await?;
+    // Waiting for a central is idle time
+    drop(pending);
     let conn = advertiser.accept().await?.with_attribute_server(server)?;
     info!("BLE connection established");
     Ok(conn)
 }
 
-fn advertising_data(name: &[u8]) -> Result<ArrayVec<u8, 27>, ()> {
-    // BLE AD type and flag constants
-    const AD_TYPE_FLAGS: u8 = 0x01;
-    const AD_TYPE_COMPLETE_LOCAL_NAME: u8 = 0x09;
-    const FLAG_LE_GENERAL_DISC_MODE: u8 = 0x02;
-    const FLAG_BR_EDR_NOT_SUPPORTED: u8 = 0x04;
-
-    // Validate name length
-    if name.len() > 24 {
-        // Max allowed (27 - 3 bytes for flags)
-        return Err(());
-    }
-
-    let mut adv_data: ArrayVec<u8, 27> = ArrayVec::new();
+/// Request faster connection parameters from the central.
+///
+/// The central may reject or adjust the request, in which case the connection keeps its
+/// current parameters.
+pub async fn request_connection_params<C: Controller, P: PacketPool>(
+    stack: &Stack<'_, C, P>,
+    conn: &GattConnection<'_, '_, P>,
+) {
+    let params = ConnectParams {
+        min_connection_interval: Duration::from_millis(CONNECTION_INTERVAL_MIN_MS),
+        max_connection_interval: Duration::from_millis(CONNECTION_INTERVAL_MAX_MS),
+        max_latency: CONNECTION_MAX_LATENCY,
+        supervision_timeout: Duration::from_millis(CONNECTION_SUPERVISION_TIMEOUT_MS),
+        ..Default::default()
+    };
 
-    // Add flags (length=2, type, flags)
-    adv_data.push(2);
-    adv_data.push(AD_TYPE_FLAGS);
-    adv_data.push(FLAG_LE_GENERAL_DISC_MODE | FLAG_BR_EDR_NOT_SUPPORTED);
+    match conn.raw().update_connection_params(stack, &params).await {
+        Ok(()) => info!("Connection parameters updated"),
+        Err(e) => warn!(
+            "Connection parameters update rejected: {:?}",
+            defmt::Debug2Format(&e)
+        ),
+    }
+}
 
-    // Add name (length=name.len()+1, type, name bytes)
-    adv_data.push(name.len() as u8 + 1);
-    adv_data.push(AD_TYPE_COMPLETE_LOCAL_NAME);
-    adv_data.try_extend_from_slice(name).map_err(|_| ())?;
+/// Encodes the advertising data (flags and complete local name), returns its length
+///
+/// Fails if the name doesn't fit, i.e. it is longer than `MAX_NAME_LEN`.
+fn advertising_data(name: &str, buffer: &mut [u8]) -> Option<usize> {
+    AdStructure::encode_slice(
+        &[
+            AdStructure::Flags(LE_GENERAL_DISCOVERABLE | BR_EDR_NOT_SUPPORTED),
+            AdStructure::CompleteLocalName(name.as_bytes()),
+        ],
+        buffer,
+    )
+    .ok()
+}
 
-    Ok(adv_data)
+/// Encodes the scan response (the Progressor service UUID), returns its length
+fn scan_response_data(buffer: &mut [u8]) -> Option<usize> {
+    AdStructure::encode_slice(
+        &[AdStructure::ServiceUuids128(&[progressor_uuid(0x1701)])],
+        buffer,
+    )
+    .ok()
 }
@@ -0,0 +1,78 @@
+/// Demo mode
+///
+/// With the `demo` feature, the load cell readings are replaced by a canned force profile
+/// played back in a loop, so apps can be tested against a known signal without a load cell.
+/// Everything downstream of the raw readings (tare, calibration, filters, protocol) runs as
+/// usual, and timestamps come from the device clock.
+///
+/// The profile is a 7.5s hang sampled every 100ms, in kg:
+///
+/// | Time      | Phase   | Force                                      |
+/// |-----------|---------|--------------------------------------------|
+/// | 0-1s      | Rest    | 0kg                                        |
+/// | 1-1.5s    | Onset   | Ramps up to 32kg, overshooting the plateau |
+/// | 1.5-5.5s  | Plateau | Fades from ~30kg to ~28kg with some wobble |
+/// | 5.5-6s    | Release | Drops back to 0kg                          |
+/// | 6-7.5s    | Rest    | 0kg                                        |
+///
+/// Samples in between are linearly interpolated to the 80Hz sample rate of the HX711.
+use embassy_time::Duration;
+
+/// Time between demo samples, matching the 80Hz sample rate of the HX711
+pub const DEMO_SAMPLE_PERIOD: Duration = Duration::from_micros(12_500);
+
+/// Number of demo samples between two points of the profile
+const SAMPLES_PER_POINT: usize = 8;
+
+/// Force profile of a hang, in kg, one point every 100ms
+const HANG_PROFILE_KG: [f32; 75] = [
+    // Rest
+    0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, //
+    // Onset
+    4.0, 12.0, 22.0, 29.0, 32.0, //
+    // Plateau
+    30.5, 30.7, 30.8, 30.5, 30.1, 29.8, 29.8, 30.1, 30.3, 30.3, //
+    30.0, 29.6, 29.3, 29.4, 29.6, 29.9, 29.9, 29.6, 29.2, 28.9, //
+    28.9, 29.2, 29.4, 29.4, 29.1, 28.7, 28.4, 28.5, 28.7, 29.0, //
+    29.0, 28.7, 28.2, 28.0, 28.0, 28.3, 28.5, 28.5, 28.2, 27.8, //
+    // Release
+    24.0, 15.0, 7.0, 2.0, 0.5, //
+    // Rest
+    0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0,
+];
+
+/// Plays back the hang profile as raw readings
+#[derive(Debug, Clone, Copy)]
+pub struct ForceProfile {
+    /// Raw reading of the profile at rest
+    zero_raw: i32,
+    /// Index of the next sample
+    index: usize,
+}
+
+impl ForceProfile {
+    /// Create a playback starting at rest, reading `zero_raw` when unloaded
+    pub const fn new(zero_raw: i32) -> Self {
+        Self { zero_raw, index: 0 }
+    }
+
+    /// Get the next sample of the profile, in kg
+    pub fn next_weight(&mut self) -> f32 {
+        let point = self.index / SAMPLES_PER_POINT;
+        let fraction = (self.index % SAMPLES_PER_POINT) as f32 / SAMPLES_PER_POINT as f32;
+        let from = HANG_PROFILE_KG[point];
+        let to = HANG_PROFILE_KG[(point + 1) % HANG_PROFILE_KG.len()];
+
+        self.index = (self.index + 1) % (HANG_PROFILE_KG.len() * SAMPLES_PER_POINT);
+        from + (to - from) * fraction
+    }
+
+    /// Get the next sample of the profile as a raw reading, given a scale in kg per raw count
+    ///
+    /// A count of dither is added, as a real load cell is never perfectly still (and the self
+    /// test would report it as stuck).
+    pub fn next_raw(&mut self, scale: f32) -> i32 {
+        let dither = (self.index % 3) as i32 - 1;
+        self.zero_raw + (self.next_weight() / scale) as i32 + dither
+    }
+}
@@ -0,0 +1,48 @@
+/// Device ID
+///
+/// Progressor ID set by the user from the app, reported instead of the `DEVICE_ID` the firmware
+/// was built with, so one image can serve several units. It is stored in flash, in the NVS
+/// partition after the overload counter.
+use defmt::error;
+use embedded_storage::{ReadStorage, Storage};
+
+use crate::{error::Error, flash::Flash, hx711::NVS_ADDR};
+
+/// Number of bytes in the device ID
+pub const DEVICE_ID_SIZE: usize = 6;
+/// Address of the ID in flash, after the overload counter
+const ID_FLASH_ADDR: u32 = NVS_ADDR + 0x300;
+/// Marker stored before the ID in flash
+const ID_MAGIC: u32 = 0x4449_5644; // "DVID"
+/// Size in bytes of the ID stored in flash: magic and ID
+const ID_FLASH_SIZE: usize = 4 + DEVICE_ID_SIZE;
+
+/// Read the ID stored in flash, if any
+pub fn load() -> Option<[u8; DEVICE_ID_SIZE]> {
+    let mut flash = Flash::new();
+    let mut bytes = [0u8; ID_FLASH_SIZE];
+    if flash.read(ID_FLASH_ADDR, &mut bytes).is_err() {
+        error!("Failed to read the device ID from flash");
+        return None;
+    }
+
+    if u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) != ID_MAGIC {
+        return None;
+    }
+    let mut id = [0u8; DEVICE_ID_SIZE];
+    id.copy_from_slice(&bytes[4..]);
+    Some(id)
+}
+
+/// Store the ID in flash
+pub fn store(id: [u8; DEVICE_ID_SIZE]) -> Result<(), Error> {
+    let mut flash = Flash::new();
+    let mut bytes = [0u8; ID_FLASH_SIZE];
+    bytes[0..4].copy_from_slice(&ID_MAGIC.to_le_bytes());
+    bytes[4..].copy_from_slice(&id);
+
+    flash.write(ID_FLASH_ADDR, &bytes).map_err(|_| {
+        error!("Failed to write the device ID to flash");
+        Error::Flash
+    })
+}
@@ -0,0 +1,110 @@
+/// Device name
+///
+/// Name set by the user from the app, advertised and exposed as the GAP device name instead of
+/// the `DEVICE_NAME` the firmware was built with. It is stored in flash, in the NVS partition
+/// after the calibration, and applied on the next boot.
+use defmt::{error, Format};
+use embedded_storage::{ReadStorage, Storage};
+
+use crate::{error::Error, flash::Flash, hx711::NVS_ADDR};
+
+/// Maximum length of the name in bytes
+///
+/// Legacy advertising data holds 31 bytes: 3 for the flags and 2 for the name header.
+pub const MAX_NAME_LEN: usize = 26;
+/// Address of the name in flash, after the calibration
+const NAME_FLASH_ADDR: u32 = NVS_ADDR + 0x100;
+/// Marker stored before the name in flash
+const NAME_MAGIC: u32 = 0x454D_414E; // "NAME"
+/// Size in bytes of the name stored in flash: magic, length and name
+const NAME_FLASH_SIZE: usize = 5 + MAX_NAME_LEN;
+
+/// A valid device name: non-empty UTF-8 of up to [`MAX_NAME_LEN`] bytes
+#[derive(Debug, Clone, Copy, PartialEq, Format)]
+pub struct DeviceName {
+    /// Name bytes, only the first `len` are used
+    bytes: [u8; MAX_NAME_LEN],
+    /// Length of the name in bytes
+    len: u8,
+}
+
+impl DeviceName {
+    /// Validate a name
+    pub fn new(name: &[u8]) -> Result<Self, Error> {
+        if name.is_empty() || name.len() > MAX_NAME_LEN || core::str::from_utf8(name).is_err() {
+            return Err(Error::Config);
+        }
+
+        let mut bytes = [0u8; MAX_NAME_LEN];
+        bytes[..name.len()].copy_from_slice(name);
+        Ok(Self {
+            bytes,
+            len: name.len() as u8,
+        })
+    }
+
+    /// Get the name as a string
+    pub fn as_str(&self) -> &str {
+        // Validated on creation
+        core::str::from_utf8(&self.bytes[..self.len as usize]).unwrap_or_default()
+    }
+
+    /// Read the name stored in flash, if any
+    pub fn load() -> Option<Self> {
+        let mut flash = Flash::new();
+        let mut bytes = [0u8; NAME_FLASH_SIZE];
+        if flash.read(NAME_FLASH_ADDR, &mut bytes).is_err() {
+            error!("Failed to read the device name from flash");
+            return None;
+        }
+
+        if u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) != NAME_MAGIC {
+            return None;
+        }
+        let len = (bytes[4] as usize).min(MAX_NAME_LEN);
+        Self::new(&bytes[5..5 + len]).ok()
+    }
+
+    /// Store the name in flash
+    pub fn store(&self) -> Result<(), Error> {
+        let mut flash = Flash::new();
+        let mut bytes = [0u8; NAME_FLASH_SIZE];
+        bytes[0..4].copy_from_slice(&NAME_MAGIC.to_le_bytes());
+        bytes[4] = self.len;
+        bytes[5..].copy_from_slice(&self.bytes);
+
+        flash.write(NAME_FLASH_ADDR, &bytes).map_err(|_| {
+            error!("Failed to write the device name to flash");
+            Error::Flash
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn name_must_be_short_utf8() {
+        assert_eq!(DeviceName::new(b"Crimpdeq").unwrap().as_str(), "Crimpdeq");
+        assert_eq!(
+            DeviceName::new("Regleta ñ".as_bytes()).unwrap().as_str(),
+            "Regleta ñ"
+        );
+        assert!(DeviceName::new(&[b'a'; MAX_NAME_LEN]).is_ok());
+
+        assert!(DeviceName::new(b"").is_err());
+        assert!(DeviceName::new(&[b'a'; MAX_NAME_LEN + 1]).is_err());
+        assert!(DeviceName::new(&[0xFF, 0xFE]).is_err());
+    }
+
+    #[test]
+    fn name_roundtrips_through_flash() {
+        assert_eq!(DeviceName::load(), None);
+
+        let name = DeviceName::new(b"Hangboard").unwrap();
+        name.store().unwrap();
+
+        assert_eq!(DeviceName::load(), Some(name));
+    }
+}
@@ -0,0 +1,458 @@
+/// Diagnostics
+///
+/// Helpers used to inspect the device behavior remotely.
+use defmt::{error, Format};
+use embassy_time::{Duration, Instant};
+use embedded_storage::{ReadStorage, Storage};
+#[cfg(target_arch = "riscv32")]
+use esp_hal::rtc_cntl::SocResetReason;
+
+use crate::{error::Error, flash::Flash, hx711::NVS_ADDR};
+
+/// Number of raw readings kept for diagnostic dumps
+pub const RAW_SAMPLE_BUFFER_SIZE: usize = 100;
+/// Number of raw readings streamed per measurement loop iteration during a dump
+pub const RAW_DUMP_CHUNK_SIZE: usize = 10;
+
+/// Ring buffer with the most recent raw readings
+#[derive(Debug, Clone, Copy)]
+pub struct RawSampleBuffer {
+    /// Raw readings
+    samples: [i32; RAW_SAMPLE_BUFFER_SIZE],
+    /// Index where the next reading will be written
+    head: usize,
+    /// Number of valid readings
+    len: usize,
+}
+
+impl Default for RawSampleBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RawSampleBuffer {
+    /// Create an empty buffer
+    pub const fn new() -> Self {
+        Self {
+            samples: [0; RAW_SAMPLE_BUFFER_SIZE],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    /// Add a reading, overwriting the oldest one when full
+    pub fn push(&mut self, raw: i32) {
+        self.samples[self.head] = raw;
+        self.head = (self.head + 1) % RAW_SAMPLE_BUFFER_SIZE;
+        self.len = (self.len + 1).min(RAW_SAMPLE_BUFFER_SIZE);
+    }
+
+    /// Number of readings stored
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Check if the buffer has no readings
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Get the reading at `index`, where 0 is the oldest one
+    pub fn get(&self, index: usize) -> Option<i32> {
+        if index >= self.len {
+            return None;
+        }
+        let start = (self.head + RAW_SAMPLE_BUFFER_SIZE - self.len) % RAW_SAMPLE_BUFFER_SIZE;
+        Some(self.samples[(start + index) % RAW_SAMPLE_BUFFER_SIZE])
+    }
+}
+
+/// Raw buffer dump in progress
+#[derive(Debug, Clone, Copy)]
+pub struct RawDump {
+    /// Snapshot of the buffer when the dump was requested
+    buffer: RawSampleBuffer,
+    /// Index of the next reading to send
+    next: usize,
+}
+
+impl RawDump {
+    /// Start a dump of the given buffer
+    pub fn new(buffer: RawSampleBuffer) -> Self {
+        Self { buffer, next: 0 }
+    }
+
+    /// Send the next chunk of readings, at most [`RAW_DUMP_CHUNK_SIZE`]
+    ///
+    /// Stops at the first reading `send` doesn't accept (e.g. the channel is full), which is
+    /// sent first on the next call.
+    pub fn send_chunk(&mut self, mut send: impl FnMut(i32) -> bool) {
+        let end = (self.next + RAW_DUMP_CHUNK_SIZE).min(self.buffer.len());
+        while self.next < end {
+            if let Some(raw) = self.buffer.get(self.next) {
+                if !send(raw) {
+                    return;
+                }
+            }
+            self.next += 1;
+        }
+    }
+
+    /// Check if all the readings have been sent
+    pub fn is_done(&self) -> bool {
+        self.next >= self.buffer.len()
+    }
+}
+
+/// Weight in kg below which a reading is considered unloaded by the drift estimator
+pub const DRIFT_UNLOADED_THRESHOLD_KG: f32 = 1.0;
+/// Time constant of the drift estimator in seconds
+const DRIFT_TIME_CONSTANT_S: f32 = 60.0;
+
+/// Long-term drift of the unloaded reading, for quality checks of load cells and mounts
+///
+/// Very slow EMA of the readings below [`DRIFT_UNLOADED_THRESHOLD_KG`]. It is only meaningful
+/// when the load cell is left unloaded for several minutes after a tare: a good assembly stays
+/// close to zero, one that creeps drifts away.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DriftEstimator {
+    /// Current estimate in kg, `None` until an unloaded reading is seen
+    estimate: Option<f32>,
+}
+
+impl DriftEstimator {
+    /// Create an estimator without readings
+    pub const fn new() -> Self {
+        Self { estimate: None }
+    }
+
+    /// Discard the estimate, used when the zero reference changes
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Add a reading in kg, loaded readings are ignored
+    ///
+    /// The smoothing factor follows `sample_rate_hz`, so the time constant is the same for
+    /// every ADC and averaging setting.
+    pub fn add_sample(&mut self, weight: f32, sample_rate_hz: f32) {
+        if weight.abs() >= DRIFT_UNLOADED_THRESHOLD_KG {
+            return;
+        }
+        let alpha = (1.0 / (DRIFT_TIME_CONSTANT_S * sample_rate_hz)).min(1.0);
+        self.estimate = Some(match self.estimate {
+            Some(estimate) => estimate + alpha * (weight - estimate),
+            None => weight,
+        });
+    }
+
+    /// Get the drift estimate in kg, `None` until an unloaded reading is seen
+    pub fn estimate(&self) -> Option<f32> {
+        self.estimate
+    }
+}
+
+/// Nominal HX711 sample rate in Hz, with the RATE pin high
+pub const NOMINAL_SAMPLE_RATE_HZ: f32 = 80.0;
+/// Length of the windows the measurement rate is computed over, in microseconds
+const RATE_WINDOW_US: u32 = 1_000_000;
+/// Fraction of the expected rate below which a window is slow
+///
+/// At 80Hz, windows under 72Hz are slow.
+const RATE_WARNING_RATIO: f32 = 0.9;
+/// Fraction of the expected rate above which the rate is considered recovered
+const RATE_RECOVERY_RATIO: f32 = 0.95;
+/// Number of consecutive slow windows before warning
+const RATE_WARNING_WINDOWS: u8 = 2;
+
+/// Detects sustained drops of the measurement rate, e.g. when BLE congestion stalls the loop
+///
+/// Warns once after [`RATE_WARNING_WINDOWS`] consecutive slow windows, and only warns again
+/// after the rate has recovered above [`RATE_RECOVERY_RATIO`] of the expected rate, so short
+/// blips don't spam warnings.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RateMonitor {
+    /// Timestamp in microseconds when the current window started
+    window_start: Option<u32>,
+    /// Number of samples in the current window
+    samples: u32,
+    /// Number of consecutive slow windows
+    slow_windows: u8,
+    /// A warning was sent and the rate hasn't recovered yet
+    warned: bool,
+}
+
+impl RateMonitor {
+    /// Create a monitor without samples
+    pub const fn new() -> Self {
+        Self {
+            window_start: None,
+            samples: 0,
+            slow_windows: 0,
+            warned: false,
+        }
+    }
+
+    /// Start over, used when a measurement starts or is paused
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Add a sample taken at `timestamp` microseconds
+    ///
+    /// Returns the actual rate in Hz when it has been below `expected_hz` for a sustained
+    /// period.
+    pub fn add_sample(&mut self, timestamp: u32, expected_hz: f32) -> Option<f32> {
+        let Some(window_start) = self.window_start else {
+            self.window_start = Some(timestamp);
+            return None;
+        };
+
+        self.samples += 1;
+        let elapsed = timestamp.wrapping_sub(window_start);
+        if elapsed < RATE_WINDOW_US {
+            return None;
+        }
+
+        let actual_hz = self.samples as f32 * 1_000_000.0 / elapsed as f32;
+        self.window_start = Some(timestamp);
+        self.samples = 0;
+
+        if actual_hz >= expected_hz * RATE_RECOVERY_RATIO {
+            self.slow_windows = 0;
+            self.warned = false;
+        } else if actual_hz < expected_hz * RATE_WARNING_RATIO {
+            self.slow_windows = self.slow_windows.saturating_add(1);
+            if self.slow_windows >= RATE_WARNING_WINDOWS && !self.warned {
+                self.warned = true;
+                return Some(actual_hz);
+            }
+        }
+
+        None
+    }
+}
+
+/// Address of the overload counter in flash, after the device name
+const OVERLOAD_FLASH_ADDR: u32 = NVS_ADDR + 0x200;
+/// Marker stored before the overload counter in flash
+const OVERLOAD_MAGIC: u32 = 0x4C56_4F43; // "COVL"
+/// Minimum time between two writes of the overload counter to flash
+const OVERLOAD_PERSIST_INTERVAL: Duration = Duration::from_secs(600);
+
+/// Number of overload events over the lifetime of the device
+///
+/// An overload event is a reading starting to be clamped to the plausible range. Cells that
+/// keep being overloaded may need replacement.
+///
+/// To limit flash wear, events are only counted in RAM. The count is written to flash when
+/// [`Self::persist_due`], at most once every [`OVERLOAD_PERSIST_INTERVAL`], and only while no
+/// measurement is running, as flash writes stall the CPU. Events since the last write are lost
+/// on power loss; a hard reset after an abuse session loses at most one session worth.
+#[derive(Debug, Clone, Copy)]
+pub struct OverloadCounter {
+    /// Number of overload events
+    count: u32,
+    /// Number of overload events stored in flash
+    stored: u32,
+    /// Time of the last write to flash
+    last_store: Option<Instant>,
+}
+
+impl OverloadCounter {
+    /// Read the counter stored in flash, starting from zero if there is none
+    pub fn load() -> Self {
+        let mut flash = Flash::new();
+        let mut bytes = [0u8; 8];
+        let count = match flash.read(OVERLOAD_FLASH_ADDR, &mut bytes) {
+            Ok(())
+                if u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+                    == OVERLOAD_MAGIC =>
+            {
+                u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]])
+            }
+            Ok(()) => 0,
+            Err(_) => {
+                error!("Failed to read the overload counter from flash");
+                0
+            }
+        };
+
+        Self {
+            count,
+            stored: count,
+            last_store: None,
+        }
+    }
+
+    /// Number of overload events
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// Count an overload event
+    pub fn record(&mut self) {
+        self.count = self.count.saturating_add(1);
+    }
+
+    /// Whether there are events that haven't been written to flash
+    pub fn unsaved(&self) -> bool {
+        self.count != self.stored
+    }
+
+    /// Whether there are unsaved events and the last write is old enough to write again
+    pub fn persist_due(&self) -> bool {
+        self.unsaved()
+            && self
+                .last_store
+                .is_none_or(|last| last.elapsed() >= OVERLOAD_PERSIST_INTERVAL)
+    }
+
+    /// Write the count to flash
+    pub fn store(&mut self) -> Result<(), Error> {
+        let mut flash = Flash::new();
+        let mut bytes = [0u8; 8];
+        bytes[0..4].copy_from_slice(&OVERLOAD_MAGIC.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.count.to_le_bytes());
+
+        // Don't retry on every loop if the flash is failing
+        self.last_store = Some(Instant::now());
+        flash.write(OVERLOAD_FLASH_ADDR, &bytes).map_err(|_| {
+            error!("Failed to write the overload counter to flash");
+            Error::Flash
+        })?;
+        self.stored = self.count;
+        Ok(())
+    }
+}
+
+/// Cause of the last reset, grouped from the SoC reset reasons
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Format)]
+pub enum ResetReason {
+    /// The reset reason couldn't be read
+    Unknown = 0,
+    /// Power cycle
+    PowerOn = 1,
+    /// Software reset, e.g. after a `Reboot` command or a panic
+    Software = 2,
+    /// A watchdog expired
+    Watchdog = 3,
+    /// The supply voltage dropped too low
+    Brownout = 4,
+    /// Wake up from deep sleep
+    DeepSleep = 5,
+    /// Any other reset (e.g. JTAG, USB or eFuse)
+    Other = 6,
+}
+
+#[cfg(target_arch = "riscv32")]
+impl From<Option<SocResetReason>> for ResetReason {
+    fn from(reason: Option<SocResetReason>) -> Self {
+        match reason {
+            None => ResetReason::Unknown,
+            Some(SocResetReason::ChipPowerOn) => ResetReason::PowerOn,
+            Some(SocResetReason::CoreSw | SocResetReason::Cpu0Sw) => ResetReason::Software,
+            Some(
+                SocResetReason::CoreMwdt0
+                | SocResetReason::CoreMwdt1
+                | SocResetReason::CoreRtcWdt
+                | SocResetReason::Cpu0Mwdt0
+                | SocResetReason::Cpu0Mwdt1
+                | SocResetReason::Cpu0RtcWdt
+                | SocResetReason::SysRtcWdt
+                | SocResetReason::SysSuperWdt,
+            ) => ResetReason::Watchdog,
+            Some(SocResetReason::SysBrownOut) => ResetReason::Brownout,
+            Some(SocResetReason::CoreDeepSleep) => ResetReason::DeepSleep,
+            Some(_) => ResetReason::Other,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drift_time_constant_does_not_depend_on_the_sample_rate() {
+        let drift_after_a_minute = |sample_rate_hz: u32| {
+            let mut drift = DriftEstimator::new();
+            drift.add_sample(0.0, sample_rate_hz as f32);
+            for _ in 0..60 * sample_rate_hz {
+                drift.add_sample(0.5, sample_rate_hz as f32);
+            }
+            drift.estimate().unwrap()
+        };
+
+        let hx711 = drift_after_a_minute(80);
+        let nau7802 = drift_after_a_minute(320);
+        assert!((hx711 - 0.316).abs() < 0.01, "drift: {hx711}");
+        assert!(
+            (hx711 - nau7802).abs() < 0.01,
+            "drift: {hx711} vs {nau7802}"
+        );
+    }
+
+    fn buffer(samples: core::ops::Range<i32>) -> RawSampleBuffer {
+        let mut buffer = RawSampleBuffer::new();
+        samples.for_each(|raw| buffer.push(raw));
+        buffer
+    }
+
+    #[test]
+    fn raw_buffer_keeps_the_latest_readings() {
+        let buffer = buffer(0..150);
+
+        assert_eq!(buffer.len(), RAW_SAMPLE_BUFFER_SIZE);
+        assert_eq!(buffer.get(0), Some(50));
+        assert_eq!(buffer.get(RAW_SAMPLE_BUFFER_SIZE - 1), Some(149));
+        assert_eq!(buffer.get(RAW_SAMPLE_BUFFER_SIZE), None);
+    }
+
+    #[test]
+    fn raw_dump_sends_a_chunk_at_a_time() {
+        let mut dump = RawDump::new(buffer(0..25));
+        let mut sent = Vec::new();
+
+        dump.send_chunk(|raw| {
+            sent.push(raw);
+            true
+        });
+        assert_eq!(sent, (0..10).collect::<Vec<_>>());
+
+        dump.send_chunk(|raw| {
+            sent.push(raw);
+            true
+        });
+        dump.send_chunk(|raw| {
+            sent.push(raw);
+            true
+        });
+        assert_eq!(sent, (0..25).collect::<Vec<_>>());
+        assert!(dump.is_done());
+    }
+
+    #[test]
+    fn raw_dump_resumes_after_a_full_channel() {
+        let mut dump = RawDump::new(buffer(0..25));
+        let mut sent = Vec::new();
+
+        dump.send_chunk(|raw| {
+            if raw == 4 {
+                return false;
+            }
+            sent.push(raw);
+            true
+        });
+        assert_eq!(sent, [0, 1, 2, 3]);
+        assert!(!dump.is_done());
+
+        dump.send_chunk(|raw| {
+            sent.push(raw);
+            true
+        });
+        assert_eq!(sent, (0..14).collect::<Vec<_>>());
+    }
+}
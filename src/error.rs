@@ -0,0 +1,80 @@
+/// Errors
+///
+/// Error type shared by the fallible operations of the crate.
+use core::fmt;
+
+use defmt::Format;
+
+/// Crate error
+#[derive(Debug, Clone, Copy, PartialEq, Format)]
+pub enum Error {
+    /// The load cell has no reading ready
+    SensorNotReady,
+    /// The load cell reading is at the ADC limits
+    Overload,
+    /// An operation didn't complete in time
+    Timeout,
+    /// Calibration values are invalid (e.g. zero or non-finite scale, or points too close)
+    CalibrationInvalid,
+    /// Readings were not stable enough to be captured
+    Unstable,
+    /// Calibration reads a known load as negative
+    WrongPolarity,
+    /// Communication with the load cell ADC failed
+    Bus,
+    /// Flash storage error
+    Flash,
+    /// Build or runtime configuration is invalid
+    Config,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::SensorNotReady => write!(f, "Load cell not ready"),
+            Error::Overload => write!(f, "Load cell overload"),
+            Error::Timeout => write!(f, "Operation timed out"),
+            Error::CalibrationInvalid => write!(f, "Invalid calibration value"),
+            Error::Unstable => write!(f, "Readings not stable"),
+            Error::WrongPolarity => write!(f, "Calibration reads loads as negative"),
+            Error::Bus => write!(f, "Load cell bus error"),
+            Error::Flash => write!(f, "Flash storage error"),
+            Error::Config => write!(f, "Invalid configuration"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors_display_a_message() {
+        assert_eq!(Error::Timeout.to_string(), "Operation timed out");
+        assert_eq!(
+            Error::WrongPolarity.to_string(),
+            "Calibration reads loads as negative"
+        );
+    }
+
+    #[test]
+    fn errors_have_distinct_messages() {
+        let errors = [
+            Error::SensorNotReady,
+            Error::Overload,
+            Error::Timeout,
+            Error::CalibrationInvalid,
+            Error::Unstable,
+            Error::WrongPolarity,
+            Error::Bus,
+            Error::Flash,
+            Error::Config,
+        ];
+
+        for (i, a) in errors.iter().enumerate() {
+            for b in &errors[i + 1..] {
+                assert_ne!(a.to_string(), b.to_string());
+            }
+        }
+    }
+}
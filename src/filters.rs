@@ -0,0 +1,935 @@
+/// Measurement filters
+///
+/// Filters applied to the calibrated weight before it is sent to the client.
+use defmt::{debug, Format};
+
+use crate::{
+    load_cell::MAX_SAMPLE_RATE_HZ,
+    logging::{self, LogLevel},
+};
+
+/// Slew-rate limiter that clamps physically impossible jumps between consecutive samples
+///
+/// A single corrupted HX711 read can show up as a jump of hundreds of kg. Samples differing
+/// from the previous output by more than `max_step` kg are clamped to `previous ± max_step`.
+/// Note that a too tight limit will also clip legitimate explosive pulls.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SlewRateLimiter {
+    /// Previous output value in kg
+    previous: Option<f32>,
+}
+
+impl SlewRateLimiter {
+    /// Create a new limiter with no history
+    pub const fn new() -> Self {
+        Self { previous: None }
+    }
+
+    /// Forget the previous sample, used when a new measurement starts
+    pub fn reset(&mut self) {
+        self.previous = None;
+    }
+
+    /// Limit the change from the previous sample to `max_step` kg
+    pub fn apply(&mut self, weight: f32, max_step: f32) -> f32 {
+        let limited = match self.previous {
+            Some(previous) if (weight - previous).abs() > max_step => {
+                let limited = previous + max_step.copysign(weight - previous);
+                if logging::enabled(LogLevel::Debug) {
+                    debug!("Glitch sample clamped: {}kg -> {}kg", weight, limited);
+                }
+                limited
+            }
+            _ => weight,
+        };
+        self.previous = Some(limited);
+        limited
+    }
+}
+
+/// Filter applied to the calibrated weight stream
+#[derive(Copy, Debug, Clone, PartialEq, Format)]
+pub enum FilterMode {
+    /// No filtering
+    None,
+    /// 2nd order Butterworth low-pass, see [`BUTTERWORTH_20HZ_80HZ`]
+    Butterworth,
+    /// Median of the last readings, see [`MedianFilter`]
+    Median,
+    /// Exponential moving average with the given smoothing factor, see [`EmaFilter`]
+    Ema(f32),
+}
+
+/// Wire value of [`FilterMode::Ema`], followed by its smoothing factor
+pub const FILTER_MODE_EMA: u8 = 3;
+
+impl FilterMode {
+    /// Parse the mode from its wire value
+    ///
+    /// The EMA is not parsed here as it needs a smoothing factor, see [`Self::ema`].
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(FilterMode::None),
+            1 => Some(FilterMode::Butterworth),
+            2 => Some(FilterMode::Median),
+            _ => None,
+        }
+    }
+
+    /// Create an EMA mode, if the smoothing factor `alpha` is within (0, 1]
+    pub fn ema(alpha: f32) -> Option<Self> {
+        (alpha > 0.0 && alpha <= 1.0).then_some(FilterMode::Ema(alpha))
+    }
+
+    /// Get the wire value of the mode
+    pub fn to_u8(self) -> u8 {
+        match self {
+            FilterMode::None => 0,
+            FilterMode::Butterworth => 1,
+            FilterMode::Median => 2,
+            FilterMode::Ema(_) => FILTER_MODE_EMA,
+        }
+    }
+
+    /// Get the smoothing factor of the EMA as a u16 fixed point fraction of 65535, zero for
+    /// the other modes
+    pub fn alpha_u16(self) -> u16 {
+        match self {
+            FilterMode::Ema(alpha) => (alpha * u16::MAX as f32 + 0.5) as u16,
+            _ => 0,
+        }
+    }
+}
+
+/// Exponential moving average: `ema = alpha * input + (1 - alpha) * ema`
+///
+/// A single multiply-add per reading, with less lag than the median for real-time force
+/// bars. A smaller `alpha` smooths more: the time constant is ~`1 / alpha` readings, e.g.
+/// `alpha = 0.2` settles in ~60ms at 80Hz. An `alpha` of one disables the smoothing.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct EmaFilter {
+    /// Current average, `None` until the first reading after a reset
+    average: Option<f32>,
+}
+
+impl EmaFilter {
+    /// Create a filter without readings
+    pub const fn new() -> Self {
+        Self { average: None }
+    }
+
+    /// Discard the average
+    pub fn reset(&mut self) {
+        self.average = None;
+    }
+
+    /// Filter a reading with the smoothing factor `alpha`
+    ///
+    /// The first reading after a reset seeds the average, so the output does not ramp up
+    /// from zero.
+    pub fn apply(&mut self, input: f32, alpha: f32) -> f32 {
+        let average = match self.average {
+            Some(average) => alpha * input + (1.0 - alpha) * average,
+            None => input,
+        };
+        self.average = Some(average);
+        average
+    }
+}
+
+/// Number of readings the median filter takes the median of
+pub const MEDIAN_WINDOW: usize = 5;
+
+/// Median of the last [`MEDIAN_WINDOW`] readings
+///
+/// Rejects isolated spikes (e.g. noise picked up by long load cell cables) instead of smearing
+/// them over the following readings like the low-pass does: up to two outliers in the window
+/// don't move the output. Steps are delayed by half the window, ~25ms at 80Hz.
+#[derive(Debug, Clone, Copy)]
+pub struct MedianFilter {
+    /// Last readings, in insertion order until the window is full
+    window: [f32; MEDIAN_WINDOW],
+    /// Index where the next reading will be written
+    head: usize,
+    /// Number of readings in the window
+    len: usize,
+}
+
+impl Default for MedianFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MedianFilter {
+    /// Create a filter without readings
+    pub const fn new() -> Self {
+        Self {
+            window: [0.0; MEDIAN_WINDOW],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    /// Discard the readings
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Filter a reading
+    ///
+    /// Until the window is full, the median of the readings since the reset is returned.
+    pub fn apply(&mut self, input: f32) -> f32 {
+        self.window[self.head] = input;
+        self.head = (self.head + 1) % MEDIAN_WINDOW;
+        self.len = (self.len + 1).min(MEDIAN_WINDOW);
+
+        let mut sorted = self.window;
+        let sorted = &mut sorted[..self.len];
+        sorted.sort_unstable_by(f32::total_cmp);
+        sorted[self.len / 2]
+    }
+}
+
+/// Maximum difference in kg between a filter input and its output to consider it settled
+///
+/// While the force changes quickly the filtered value lags behind it; once the output is
+/// within this tolerance of the input, the transient has elapsed.
+pub const FILTER_SETTLED_TOLERANCE_KG: f32 = 0.1;
+
+/// Biquad coefficients, normalized so that `a0 = 1`
+#[derive(Debug, Clone, Copy)]
+pub struct BiquadCoefficients {
+    pub b0: f32,
+    pub b1: f32,
+    pub b2: f32,
+    pub a1: f32,
+    pub a2: f32,
+}
+
+/// 2nd order Butterworth low-pass with a 20Hz cutoff at 80Hz sampling
+///
+/// For other rates, with `K = tan(π·fc/fs)` and `Q = 1/√2`:
+/// - `norm = 1 / (1 + K/Q + K²)`
+/// - `b0 = K²·norm`, `b1 = 2·b0`, `b2 = b0`
+/// - `a1 = 2·(K² - 1)·norm`, `a2 = (1 - K/Q + K²)·norm`
+pub const BUTTERWORTH_20HZ_80HZ: BiquadCoefficients = BiquadCoefficients {
+    b0: 0.292_893_2,
+    b1: 0.585_786_4,
+    b2: 0.292_893_2,
+    a1: 0.0,
+    a2: 0.171_572_9,
+};
+
+/// 2nd order Butterworth low-pass with a 20Hz cutoff at 320Hz sampling, see
+/// [`BUTTERWORTH_20HZ_80HZ`]
+pub const BUTTERWORTH_20HZ_320HZ: BiquadCoefficients = BiquadCoefficients {
+    b0: 0.029_954_58,
+    b1: 0.059_909_16,
+    b2: 0.029_954_58,
+    a1: -1.454_243_6,
+    a2: 0.574_061_9,
+};
+
+/// Biquad IIR filter (transposed direct form II)
+#[derive(Debug, Clone, Copy)]
+pub struct Biquad {
+    /// Filter coefficients
+    coefficients: BiquadCoefficients,
+    /// Filter state
+    state: [f32; 2],
+    /// Whether the state has been initialized with a sample
+    primed: bool,
+}
+
+impl Biquad {
+    /// Create a new filter with the given coefficients
+    pub const fn new(coefficients: BiquadCoefficients) -> Self {
+        Self {
+            coefficients,
+            state: [0.0; 2],
+            primed: false,
+        }
+    }
+
+    /// Clear the filter state
+    pub fn reset(&mut self) {
+        self.state = [0.0; 2];
+        self.primed = false;
+    }
+
+    /// Filter a sample
+    ///
+    /// The first sample after a reset initializes the state to its steady state, so the
+    /// output does not ramp up from zero.
+    pub fn apply(&mut self, input: f32) -> f32 {
+        let c = self.coefficients;
+        if !self.primed {
+            // Steady state for a constant input (unity DC gain)
+            self.state = [input * (1.0 - c.b0), input * (c.b2 - c.a2)];
+            self.primed = true;
+        }
+
+        let output = c.b0 * input + self.state[0];
+        self.state[0] = c.b1 * input - c.a1 * output + self.state[1];
+        self.state[1] = c.b2 * input - c.a2 * output;
+        output
+    }
+}
+
+/// Conditioning of the calibrated readings of a load cell driver
+///
+/// Replaces non-finite readings by zero, clamps them to the plausible range and applies the
+/// configured filter, in that order, so neither the filter state nor the client ever see a NaN.
+#[derive(Debug, Clone, Copy)]
+pub struct ReadingConditioner {
+    /// Filter applied to calibrated readings
+    filter_mode: FilterMode,
+    /// Low-pass filter state
+    low_pass: Biquad,
+    /// Median filter state
+    median: MedianFilter,
+    /// Exponential moving average state
+    ema: EmaFilter,
+    /// Whether the last filtered reading had settled
+    filter_settled: bool,
+    /// Change in kg between consecutive readings that snaps the filter to the new reading
+    step_reset_threshold: f32,
+    /// Previous reading fed to the filter
+    previous_unfiltered: Option<f32>,
+    /// Plausible range of calibrated readings in kg (min, max)
+    plausible_range: (f32, f32),
+    /// Unclamped value of the last reading, if it was out of the plausible range
+    out_of_range: Option<f32>,
+    /// Whether the last calibrated reading was not finite
+    non_finite: bool,
+}
+
+impl ReadingConditioner {
+    /// Create a conditioner with no filter and the full range, using `low_pass` coefficients
+    /// matching the sample rate of the driver for the Butterworth filter
+    pub const fn new(low_pass: BiquadCoefficients) -> Self {
+        Self {
+            filter_mode: FilterMode::None,
+            low_pass: Biquad::new(low_pass),
+            median: MedianFilter::new(),
+            ema: EmaFilter::new(),
+            filter_settled: true,
+            step_reset_threshold: f32::INFINITY,
+            previous_unfiltered: None,
+            plausible_range: (f32::NEG_INFINITY, f32::INFINITY),
+            out_of_range: None,
+            non_finite: false,
+        }
+    }
+
+    /// Set the filter, changing the mode resets the filter state
+    pub fn set_filter_mode(&mut self, filter_mode: FilterMode) {
+        if self.filter_mode != filter_mode {
+            self.filter_mode = filter_mode;
+            self.reset();
+        }
+    }
+
+    /// Get the current filter mode
+    pub fn filter_mode(&self) -> FilterMode {
+        self.filter_mode
+    }
+
+    /// Set the change in kg between consecutive readings that snaps the filter to the new
+    /// reading, infinity disables it
+    pub fn set_step_reset_threshold(&mut self, threshold: f32) {
+        self.step_reset_threshold = threshold;
+    }
+
+    /// Whether the last filtered reading had settled, see [`FILTER_SETTLED_TOLERANCE_KG`]
+    pub fn filter_settled(&self) -> bool {
+        self.filter_settled
+    }
+
+    /// Set the plausible range of calibrated readings, in kg
+    pub fn set_plausible_range(&mut self, min: f32, max: f32) {
+        self.plausible_range = (min, max);
+    }
+
+    /// Unclamped value of the last reading, if it was out of the plausible range
+    pub fn out_of_range(&self) -> Option<f32> {
+        self.out_of_range
+    }
+
+    /// Whether the last reading was not finite and was replaced by zero
+    pub fn non_finite(&self) -> bool {
+        self.non_finite
+    }
+
+    /// Clear the state of the filters
+    pub fn reset(&mut self) {
+        self.low_pass.reset();
+        self.median.reset();
+        self.ema.reset();
+    }
+
+    /// Replace a non-finite reading by zero, see [`Self::non_finite`]
+    pub fn sanitize(&mut self, weight: f32) -> f32 {
+        self.non_finite = !weight.is_finite();
+        if self.non_finite {
+            0.0
+        } else {
+            weight
+        }
+    }
+
+    /// Sanitize, clamp and filter a calibrated reading
+    pub fn condition(&mut self, weight: f32) -> f32 {
+        let weight = self.sanitize(weight);
+
+        let (min, max) = self.plausible_range;
+        let clamped = weight.clamp(min, max);
+        self.out_of_range = (clamped != weight).then_some(weight);
+
+        self.filter(clamped)
+    }
+
+    /// Apply the configured filter
+    fn filter(&mut self, weight: f32) -> f32 {
+        if let Some(previous) = self.previous_unfiltered {
+            if (weight - previous).abs() > self.step_reset_threshold {
+                self.reset();
+            }
+        }
+        self.previous_unfiltered = Some(weight);
+
+        let filtered = match self.filter_mode {
+            FilterMode::None => weight,
+            FilterMode::Butterworth => self.low_pass.apply(weight),
+            FilterMode::Median => self.median.apply(weight),
+            FilterMode::Ema(alpha) => self.ema.apply(weight, alpha),
+        };
+        self.filter_settled = (filtered - weight).abs() <= FILTER_SETTLED_TOLERANCE_KG;
+        filtered
+    }
+}
+
+/// Report-on-change configuration
+///
+/// Dropping samples is unsuitable when every sample is needed, e.g. to compute RFD.
+#[derive(Debug, Clone, Copy, PartialEq, Format)]
+pub struct ReportOnChange {
+    /// Minimum change in kg since the last reported sample to report a new one
+    pub delta: f32,
+    /// Maximum time in milliseconds between reported samples, keeping the stream alive
+    pub heartbeat_ms: u16,
+}
+
+/// Decides which samples are reported when report-on-change is enabled
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ChangeDetector {
+    /// Last reported sample (weight in kg, timestamp in microseconds)
+    last_reported: Option<(f32, u32)>,
+}
+
+impl ChangeDetector {
+    /// Create a new detector with no history
+    pub const fn new() -> Self {
+        Self {
+            last_reported: None,
+        }
+    }
+
+    /// Forget the last reported sample, used when a new measurement starts
+    pub fn reset(&mut self) {
+        self.last_reported = None;
+    }
+
+    /// Check whether a sample should be reported, recording it if so
+    pub fn should_report(&mut self, weight: f32, timestamp: u32, config: &ReportOnChange) -> bool {
+        let report = match self.last_reported {
+            Some((last_weight, last_timestamp)) => {
+                (weight - last_weight).abs() > config.delta
+                    || timestamp.wrapping_sub(last_timestamp) / 1000 >= config.heartbeat_ms as u32
+            }
+            None => true,
+        };
+
+        if report {
+            self.last_reported = Some((weight, timestamp));
+        }
+        report
+    }
+}
+
+/// Maximum number of samples in a coalesced data point
+pub const MAX_COALESCED_SAMPLES: usize = 3;
+
+/// Groups the samples produced within a time window into a single data point
+///
+/// Samples are sent as a `CoalescedWeights` data point once the window since the first sample
+/// has elapsed, or earlier if [`MAX_COALESCED_SAMPLES`] are pending. This delays samples by up
+/// to the window.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SampleCoalescer {
+    /// Timestamp in microseconds of the first pending sample
+    first_timestamp: u64,
+    /// Pending weights
+    weights: [f32; MAX_COALESCED_SAMPLES],
+    /// Number of pending weights
+    len: usize,
+}
+
+impl SampleCoalescer {
+    /// Create a coalescer with no pending samples
+    pub const fn new() -> Self {
+        Self {
+            first_timestamp: 0,
+            weights: [0.0; MAX_COALESCED_SAMPLES],
+            len: 0,
+        }
+    }
+
+    /// Drop the pending samples, used when a new measurement starts
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Add a sample, returning the coalesced samples once the window is complete
+    ///
+    /// Returns the first timestamp and the weights of the completed window.
+    pub fn add(
+        &mut self,
+        weight: f32,
+        timestamp: u64,
+        window_ms: u16,
+    ) -> Option<(u64, [f32; MAX_COALESCED_SAMPLES], u8)> {
+        let window_us = window_ms as u64 * 1000;
+        let expired = self.len > 0 && timestamp.saturating_sub(self.first_timestamp) >= window_us;
+        let completed = if expired { self.flush() } else { None };
+
+        if self.len == 0 {
+            self.first_timestamp = timestamp;
+        }
+        self.weights[self.len] = weight;
+        self.len += 1;
+
+        match completed {
+            Some(completed) => Some(completed),
+            None if self.len == MAX_COALESCED_SAMPLES => self.flush(),
+            None => None,
+        }
+    }
+
+    /// Take the pending samples, if any
+    pub fn flush(&mut self) -> Option<(u64, [f32; MAX_COALESCED_SAMPLES], u8)> {
+        if self.len == 0 {
+            return None;
+        }
+        let pending = (self.first_timestamp, self.weights, self.len as u8);
+        self.reset();
+        Some(pending)
+    }
+}
+
+/// Force above the baseline in kg that marks the onset of a pull
+pub const ONSET_THRESHOLD_KG: f32 = 2.0;
+/// Time before the onset over which the baseline is averaged, in microseconds
+const BASELINE_WINDOW_US: u32 = 200_000;
+/// Number of samples kept to average the baseline, enough for the window at the highest sample
+/// rate
+const BASELINE_HISTORY_SAMPLES: usize =
+    MAX_SAMPLE_RATE_HZ * BASELINE_WINDOW_US as usize / 1_000_000 + 1;
+
+/// Subtracts the force resting on the load cell before each pull
+///
+/// While idle, the baseline is the mean force over the last 200ms. When the force rises
+/// [`ONSET_THRESHOLD_KG`] above it, the baseline is frozen and subtracted until the force drops
+/// back below half the threshold, so every pull starts at zero even with a forearm resting on
+/// the device.
+///
+/// It is applied on top of the tare: the tare removes the static load at the time it was
+/// taken, while the baseline follows whatever rests on the device before each pull. As it
+/// also cancels slow drift and any load held steadily, it is meant for RFD and pull
+/// measurements, not to weigh.
+#[derive(Debug, Clone, Copy)]
+pub struct BaselineSubtractor {
+    /// Recent idle samples (weight in kg, timestamp in microseconds)
+    history: [(f32, u32); BASELINE_HISTORY_SAMPLES],
+    /// Index of the next sample in the history
+    next: usize,
+    /// Number of samples in the history
+    len: usize,
+    /// Baseline frozen at the onset of the current pull
+    frozen: Option<f32>,
+}
+
+impl Default for BaselineSubtractor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BaselineSubtractor {
+    /// Create a subtractor with no history
+    pub const fn new() -> Self {
+        Self {
+            history: [(0.0, 0); BASELINE_HISTORY_SAMPLES],
+            next: 0,
+            len: 0,
+            frozen: None,
+        }
+    }
+
+    /// Forget the baseline, used when a new measurement starts
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Subtract the baseline from a sample
+    pub fn apply(&mut self, weight: f32, timestamp: u32) -> f32 {
+        if let Some(baseline) = self.frozen {
+            if weight < baseline + ONSET_THRESHOLD_KG / 2.0 {
+                // The pull has been released, track the baseline again from scratch
+                self.reset();
+            }
+            return weight - baseline;
+        }
+
+        let baseline = self.baseline(timestamp).unwrap_or(weight);
+        if weight > baseline + ONSET_THRESHOLD_KG {
+            if logging::enabled(LogLevel::Debug) {
+                debug!("Pull onset, baseline: {}kg", baseline);
+            }
+            self.frozen = Some(baseline);
+        } else {
+            self.history[self.next] = (weight, timestamp);
+            self.next = (self.next + 1) % BASELINE_HISTORY_SAMPLES;
+            self.len = (self.len + 1).min(BASELINE_HISTORY_SAMPLES);
+        }
+        weight - baseline
+    }
+
+    /// Mean of the idle samples within the baseline window before `timestamp`
+    fn baseline(&self, timestamp: u32) -> Option<f32> {
+        let (sum, count) = self.history[..self.len]
+            .iter()
+            .filter(|(_, t)| timestamp.wrapping_sub(*t) <= BASELINE_WINDOW_US)
+            .fold((0.0, 0u32), |(sum, count), (weight, _)| {
+                (sum + weight, count + 1)
+            });
+        (count > 0).then(|| sum / count as f32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Butterworth low-pass coefficients, as documented on [`BUTTERWORTH_20HZ_80HZ`]
+    fn butterworth(cutoff_hz: f32, sample_rate_hz: f32) -> BiquadCoefficients {
+        let k = (core::f32::consts::PI * cutoff_hz / sample_rate_hz).tan();
+        let q = core::f32::consts::FRAC_1_SQRT_2;
+        let norm = 1.0 / (1.0 + k / q + k * k);
+        let b0 = k * k * norm;
+        BiquadCoefficients {
+            b0,
+            b1: 2.0 * b0,
+            b2: b0,
+            a1: 2.0 * (k * k - 1.0) * norm,
+            a2: (1.0 - k / q + k * k) * norm,
+        }
+    }
+
+    fn assert_coefficients_eq(actual: BiquadCoefficients, expected: BiquadCoefficients) {
+        let actual = [actual.b0, actual.b1, actual.b2, actual.a1, actual.a2];
+        let expected = [
+            expected.b0,
+            expected.b1,
+            expected.b2,
+            expected.a1,
+            expected.a2,
+        ];
+        for (actual, expected) in actual.iter().zip(expected) {
+            assert!((actual - expected).abs() < 1e-5, "{actual} != {expected}");
+        }
+    }
+
+    #[test]
+    fn butterworth_coefficients_match_their_rates() {
+        assert_coefficients_eq(BUTTERWORTH_20HZ_80HZ, butterworth(20.0, 80.0));
+        assert_coefficients_eq(BUTTERWORTH_20HZ_320HZ, butterworth(20.0, 320.0));
+    }
+
+    #[test]
+    fn biquad_starts_at_the_first_input() {
+        let mut biquad = Biquad::new(BUTTERWORTH_20HZ_80HZ);
+
+        for _ in 0..10 {
+            assert!((biquad.apply(25.0) - 25.0).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn butterworth_removes_noise_at_the_nyquist_rate() {
+        let mut biquad = Biquad::new(BUTTERWORTH_20HZ_80HZ);
+        let mut output = 0.0;
+        for i in 0..50 {
+            let noise = if i % 2 == 0 { 1.0 } else { -1.0 };
+            output = biquad.apply(10.0 + noise);
+        }
+
+        assert!((output - 10.0).abs() < 1e-3, "output: {output}");
+    }
+
+    #[test]
+    fn ema_is_seeded_by_the_first_reading() {
+        let mut ema = EmaFilter::new();
+
+        assert_eq!(ema.apply(40.0, 0.2), 40.0);
+        assert_eq!(ema.apply(40.0, 0.2), 40.0);
+
+        ema.reset();
+        assert_eq!(ema.apply(10.0, 0.2), 10.0);
+    }
+
+    #[test]
+    fn ema_moves_by_alpha_of_the_difference() {
+        let mut ema = EmaFilter::new();
+        ema.apply(0.0, 0.25);
+
+        assert_eq!(ema.apply(8.0, 0.25), 2.0);
+        assert_eq!(ema.apply(8.0, 0.25), 3.5);
+        // One disables the smoothing
+        assert_eq!(ema.apply(-1.0, 1.0), -1.0);
+    }
+
+    #[test]
+    fn median_rejects_isolated_spikes() {
+        let mut median = MedianFilter::new();
+        for _ in 0..MEDIAN_WINDOW {
+            median.apply(10.0);
+        }
+
+        for input in [80.0, 10.0, -50.0, 10.0, 10.0, 90.0, 95.0, 10.0] {
+            assert_eq!(median.apply(input), 10.0);
+        }
+    }
+
+    #[test]
+    fn median_follows_steps_after_half_the_window() {
+        let mut median = MedianFilter::new();
+        for _ in 0..MEDIAN_WINDOW {
+            median.apply(0.0);
+        }
+
+        let outputs: Vec<f32> = (0..MEDIAN_WINDOW).map(|_| median.apply(20.0)).collect();
+
+        assert_eq!(outputs, [0.0, 0.0, 20.0, 20.0, 20.0]);
+    }
+
+    #[test]
+    fn median_starts_at_the_first_reading() {
+        let mut median = MedianFilter::new();
+        median.apply(3.0);
+        median.reset();
+
+        assert_eq!(median.apply(7.0), 7.0);
+        assert_eq!(median.apply(9.0), 9.0);
+        assert_eq!(median.apply(8.0), 8.0);
+    }
+
+    #[test]
+    fn conditioner_applies_the_butterworth_filter() {
+        let mut conditioner = ReadingConditioner::new(BUTTERWORTH_20HZ_80HZ);
+        conditioner.set_filter_mode(FilterMode::from_u8(1).unwrap());
+        conditioner.condition(0.0);
+
+        let filtered = conditioner.condition(10.0);
+
+        assert!(filtered > 0.0 && filtered < 10.0, "filtered: {filtered}");
+        assert!(!conditioner.filter_settled());
+    }
+
+    #[test]
+    fn conditioner_clamps_to_the_plausible_range() {
+        let mut conditioner = ReadingConditioner::new(BUTTERWORTH_20HZ_80HZ);
+        conditioner.set_plausible_range(-10.0, 200.0);
+
+        assert_eq!(conditioner.condition(150.0), 150.0);
+        assert_eq!(conditioner.out_of_range(), None);
+
+        assert_eq!(conditioner.condition(850.0), 200.0);
+        assert_eq!(conditioner.out_of_range(), Some(850.0));
+        assert_eq!(conditioner.condition(-50.0), -10.0);
+        assert_eq!(conditioner.out_of_range(), Some(-50.0));
+
+        conditioner.condition(0.0);
+        assert_eq!(conditioner.out_of_range(), None);
+    }
+
+    #[test]
+    fn non_finite_readings_are_replaced_by_zero() {
+        let mut conditioner = ReadingConditioner::new(BUTTERWORTH_20HZ_80HZ);
+
+        for weight in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+            assert_eq!(conditioner.condition(weight), 0.0);
+            assert!(conditioner.non_finite());
+        }
+
+        assert_eq!(conditioner.condition(12.0), 12.0);
+        assert!(!conditioner.non_finite());
+    }
+
+    #[test]
+    fn non_finite_readings_dont_poison_the_filter() {
+        let mut conditioner = ReadingConditioner::new(BUTTERWORTH_20HZ_80HZ);
+        conditioner.set_filter_mode(FilterMode::Butterworth);
+        conditioner.condition(0.0);
+
+        conditioner.condition(f32::NAN);
+
+        for _ in 0..20 {
+            assert!(conditioner.condition(0.0).is_finite());
+        }
+    }
+
+    #[test]
+    fn large_steps_reset_the_filter() {
+        let mut conditioner = ReadingConditioner::new(BUTTERWORTH_20HZ_80HZ);
+        conditioner.set_filter_mode(FilterMode::Butterworth);
+        conditioner.set_step_reset_threshold(5.0);
+        for _ in 0..20 {
+            conditioner.condition(0.0);
+        }
+
+        // Below the threshold the filter smooths the step
+        let filtered = conditioner.condition(4.0);
+        assert!(filtered < 4.0, "filtered: {filtered}");
+
+        // Above it the filter restarts at the new reading
+        let filtered = conditioner.condition(40.0);
+        assert!((filtered - 40.0).abs() < 1e-4, "filtered: {filtered}");
+        assert!(conditioner.filter_settled());
+    }
+
+    #[test]
+    fn slew_rate_limiter_clamps_jumps() {
+        let mut limiter = SlewRateLimiter::new();
+        assert_eq!(limiter.apply(10.0, 5.0), 10.0);
+
+        assert_eq!(limiter.apply(300.0, 5.0), 15.0);
+        assert_eq!(limiter.apply(-300.0, 5.0), 10.0);
+        assert_eq!(limiter.apply(12.0, 5.0), 12.0);
+    }
+
+    #[test]
+    fn slew_rate_limiter_starts_over_after_a_reset() {
+        let mut limiter = SlewRateLimiter::new();
+        limiter.apply(0.0, 5.0);
+
+        limiter.reset();
+
+        assert_eq!(limiter.apply(100.0, 5.0), 100.0);
+    }
+
+    /// Sample period at the highest sample rate, in microseconds
+    const PERIOD_US: u32 = 1_000_000 / MAX_SAMPLE_RATE_HZ as u32;
+
+    #[test]
+    fn coalescer_sends_the_samples_of_each_window() {
+        let mut coalescer = SampleCoalescer::new();
+
+        assert_eq!(coalescer.add(1.0, 0, 25), None);
+        assert_eq!(coalescer.add(2.0, 12_500, 25), None);
+        assert_eq!(
+            coalescer.add(3.0, 25_000, 25),
+            Some((0, [1.0, 2.0, 0.0], 2))
+        );
+
+        assert_eq!(coalescer.flush(), Some((25_000, [3.0, 0.0, 0.0], 1)));
+        assert_eq!(coalescer.flush(), None);
+    }
+
+    #[test]
+    fn coalescer_sends_full_windows_early() {
+        let mut coalescer = SampleCoalescer::new();
+
+        assert_eq!(coalescer.add(1.0, 0, 1000), None);
+        assert_eq!(coalescer.add(2.0, 1000, 1000), None);
+        assert_eq!(
+            coalescer.add(3.0, 2000, 1000),
+            Some((0, [1.0, 2.0, 3.0], 3))
+        );
+        assert_eq!(coalescer.flush(), None);
+    }
+
+    #[test]
+    fn baseline_averages_the_whole_window_at_the_highest_sample_rate() {
+        let mut baseline = BaselineSubtractor::new();
+        for i in 0..64 {
+            let weight = if i < 32 { 0.0 } else { 1.0 };
+            baseline.apply(weight, i * PERIOD_US);
+        }
+
+        let weight = baseline.apply(1.0, BASELINE_WINDOW_US);
+        assert!((weight - 0.5).abs() < 1e-6, "weight: {weight}");
+    }
+
+    #[test]
+    fn baseline_window_spans_the_clock_wrap() {
+        let mut baseline = BaselineSubtractor::new();
+        let start = 0u32.wrapping_sub(BASELINE_WINDOW_US / 2);
+        for i in 0..BASELINE_WINDOW_US / PERIOD_US {
+            baseline.apply(4.0, start.wrapping_add(i * PERIOD_US));
+        }
+
+        let weight = baseline.apply(5.0, start.wrapping_add(BASELINE_WINDOW_US));
+        assert!((weight - 1.0).abs() < 1e-6, "weight: {weight}");
+    }
+
+    /// Baseline subtractor with `weight` resting on the load cell for the whole window
+    fn resting(weight: f32) -> BaselineSubtractor {
+        let mut baseline = BaselineSubtractor::new();
+        for i in 0..=BASELINE_WINDOW_US / PERIOD_US {
+            assert_eq!(baseline.apply(weight, i * PERIOD_US), 0.0);
+        }
+        baseline
+    }
+
+    #[test]
+    fn baseline_is_frozen_during_a_pull() {
+        let mut baseline = resting(5.0);
+        let t = BASELINE_WINDOW_US + PERIOD_US;
+
+        assert_eq!(baseline.apply(30.0, t), 25.0);
+        // Well past the window, the resting force is still subtracted
+        assert_eq!(baseline.apply(20.0, t + 10 * BASELINE_WINDOW_US), 15.0);
+    }
+
+    #[test]
+    fn baseline_is_tracked_again_after_the_release() {
+        let mut baseline = resting(5.0);
+        let t = BASELINE_WINDOW_US + PERIOD_US;
+        baseline.apply(30.0, t);
+
+        assert_eq!(baseline.apply(5.5, t + PERIOD_US), 0.5);
+        assert_eq!(baseline.apply(5.5, t + 2 * PERIOD_US), 0.0);
+    }
+
+    #[test]
+    fn baseline_follows_slow_changes() {
+        let mut baseline = resting(5.0);
+        let t = BASELINE_WINDOW_US + PERIOD_US;
+
+        // Creeping up too slowly for an onset, e.g. drift
+        let mut weight = 5.0;
+        for i in 0..200 {
+            weight += 0.01;
+            assert!(baseline.apply(weight, t + i * PERIOD_US) < ONSET_THRESHOLD_KG);
+        }
+
+        let t = t + 200 * PERIOD_US;
+        for i in 0..=BASELINE_WINDOW_US / PERIOD_US {
+            baseline.apply(weight, t + i * PERIOD_US);
+        }
+        let weight = baseline.apply(weight, t + BASELINE_WINDOW_US + PERIOD_US);
+        assert!(weight.abs() < 1e-4, "weight: {weight}");
+    }
+}
@@ -0,0 +1,75 @@
+/// Flash
+///
+/// Storage of the NVS partition, where the calibration, the name, the overload counter, the
+/// device ID and, in a sector of its own, the last tare live. On the chip this is the SPI flash
+/// through `esp-storage`. Host tests get a fake in RAM instead, one per test thread, so tests
+/// storing values don't see each other's writes.
+#[cfg(target_arch = "riscv32")]
+pub use esp_storage::FlashStorage as Flash;
+
+#[cfg(not(target_arch = "riscv32"))]
+pub use self::ram::Flash;
+
+#[cfg(not(target_arch = "riscv32"))]
+mod ram {
+    use core::{cell::RefCell, ops::Range};
+
+    use embedded_storage::{ReadStorage, Storage};
+
+    use crate::hx711::NVS_ADDR;
+
+    /// Size in bytes of the NVS partition
+    const NVS_SIZE: usize = 0x6000;
+
+    std::thread_local! {
+        /// Contents of the NVS partition, erased until written
+        static NVS: RefCell<[u8; NVS_SIZE]> = const { RefCell::new([0xFF; NVS_SIZE]) };
+    }
+
+    /// Flash emulated in RAM, covering the NVS partition
+    #[derive(Default)]
+    pub struct Flash;
+
+    /// Access outside the NVS partition
+    #[derive(Debug)]
+    pub struct OutOfBounds;
+
+    impl Flash {
+        /// Access the flash
+        pub fn new() -> Self {
+            Self
+        }
+
+        /// Range of the NVS partition covered by an access
+        fn range(offset: u32, len: usize) -> Result<Range<usize>, OutOfBounds> {
+            let start = offset.checked_sub(NVS_ADDR).ok_or(OutOfBounds)? as usize;
+            let end = start + len;
+            if end > NVS_SIZE {
+                return Err(OutOfBounds);
+            }
+            Ok(start..end)
+        }
+    }
+
+    impl ReadStorage for Flash {
+        type Error = OutOfBounds;
+
+        fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+            let range = Self::range(offset, bytes.len())?;
+            NVS.with(|nvs| bytes.copy_from_slice(&nvs.borrow()[range]));
+            Ok(())
+        }
+
+        fn capacity(&self) -> usize {
+            NVS_ADDR as usize + NVS_SIZE
+        }
+    }
+
+    impl Storage for Flash {
+        fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+            let range = Self::range(offset, bytes.len())?;
+            NVS.with(|nvs| nvs.borrow_mut()[range].copy_from_slice(bytes));
+            Ok(())
+        }
+    }
+}
@@ -6,56 +6,112 @@
 /// Based on [loadcell] crate.
 ///
 /// [loadcell]: https://crates.io/crates/loadcell
-use core::fmt;
-
-use defmt::{debug, error, info, Format};
+#[cfg(target_arch = "riscv32")]
+use defmt::{debug, warn};
+use defmt::{error, info, Format};
+#[cfg(target_arch = "riscv32")]
+use embassy_futures::yield_now;
+use embassy_time::Duration;
+#[cfg(all(feature = "demo", target_arch = "riscv32"))]
+use embassy_time::Timer;
+#[cfg(target_arch = "riscv32")]
+use embassy_time::{with_deadline, with_timeout, Instant};
+#[cfg(all(not(feature = "demo"), target_arch = "riscv32"))]
 use embedded_hal::delay::DelayNs;
 use embedded_storage::{ReadStorage, Storage};
+#[cfg(target_arch = "riscv32")]
 use esp_hal::{
     delay::Delay,
-    gpio::{Input, Output},
+    gpio::{DriveMode, DriveStrength, Input, Output, OutputConfig, Pull},
+};
+
+#[cfg(all(feature = "demo", target_arch = "riscv32"))]
+use crate::demo::{ForceProfile, DEMO_SAMPLE_PERIOD};
+#[cfg(target_arch = "riscv32")]
+use crate::{
+    diagnostics::NOMINAL_SAMPLE_RATE_HZ,
+    filters::{FilterMode, ReadingConditioner, BUTTERWORTH_20HZ_80HZ},
+    load_cell::{min_averaging_window, LoadCell},
 };
-use esp_storage::FlashStorage;
+use crate::{error::Error, flash::Flash};
 
 /// The absolute minimum readings. A smaller value should be clamped.
 const HX711_MINIMUM: i32 = -(2i32.saturating_pow(24 - 1));
 /// The absolute maximum readings. A greater value should be clamped.
 const HX711_MAXIMUM: i32 = 2i32.saturating_pow(24 - 1) - 1;
 /// The default delay time in microseconds for the HX711.
+#[cfg(all(not(feature = "demo"), target_arch = "riscv32"))]
 const HX711_DELAY_TIME_US: u32 = 1;
 /// The number of bits in the HX711 reading
 const HX711_DATA_BITS: usize = 24;
 /// The sign bit position in the HX711 reading
 const HX711_SIGN_BIT: u32 = 0x800000;
 
+/// The number of readings taken by the startup self test
+pub const SELF_TEST_SAMPLES: usize = 8;
+/// The time to wait for a reading during the startup self test, in milliseconds.
+/// At 10Hz (the slowest HX711 rate) a reading takes 100ms.
+pub const SELF_TEST_TIMEOUT_MS: u64 = 500;
+/// The time to wait for a reading before giving up, e.g. when the load cell gets disconnected.
+/// Five times the time a reading takes at 10Hz.
+pub const READ_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// The number of readings taken by the boot zero offset check
+pub const ZERO_CHECK_SAMPLES: usize = 8;
+/// Offset from the stored zero reference, in kg, above which the boot check warns.
+///
+/// Well above the noise of a few averaged readings, but small enough to catch a load cell or
+/// mount that shifted between sessions.
+pub const ZERO_CHECK_TOLERANCE_KG: f32 = 0.5;
+
 /// The default address of the NVS flash storage.
-const NVS_ADDR: u32 = 0x9000;
+pub const NVS_ADDR: u32 = 0x9000;
 /// The default number of samples for taring
-const DEFAULT_TARING_SAMPLES: usize = 16;
+pub const DEFAULT_TARING_SAMPLES: usize = 16;
+/// The number of samples for a quick tare
+pub const QUICK_TARING_SAMPLES: usize = 4;
+/// The number of conversions discarded after taring, so the first measurement is settled
+pub const POST_TARE_DISCARD_SAMPLES: usize = 2;
 /// The default number of samples for calibration
-const DEFAULT_CALIBRATION_SAMPLES: usize = 100;
+pub const DEFAULT_CALIBRATION_SAMPLES: usize = 100;
+/// Standard deviation of the readings, in kg, above which a stable capture fails.
+///
+/// A still load shows a few grams of noise, someone still hanging the reference mass or
+/// touching the device shows much more.
+pub const CAPTURE_STABILITY_TOLERANCE_KG: f32 = 0.05;
+/// Marker stored before the calibration values in flash.
+///
+/// Flash without it holds the legacy `offset`/`factor` layout.
+const CALIBRATION_MAGIC: u32 = 0x3243_414C; // "LAC2"
+/// Size in bytes of the calibration stored in flash
+const CALIBRATION_FLASH_SIZE: usize = 12;
+/// Size in bytes of a flash sector, the unit erased by a write
+const FLASH_SECTOR_SIZE: u32 = 0x1000;
+/// Address of the last tare in flash
+///
+/// In the sector after the calibration, so the erase done by each tare write can't lose the
+/// calibration on a power loss.
+const TARE_FLASH_ADDR: u32 = NVS_ADDR + FLASH_SECTOR_SIZE;
+/// Marker stored before the tare in flash
+const TARE_MAGIC: u32 = 0x3152_4154; // "TAR1"
+/// Size in bytes of the tare stored in flash: magic, zero reference and calibration scale
+const TARE_FLASH_SIZE: usize = 12;
 /// The default calibration values.
 const DEFAULT_CALIBRATION: Calibration = Calibration {
-    offset: 0.0,
-    factor: 0.066,
+    zero_raw: 0,
+    scale: 0.000066,
 };
 
-/// Custom error type for HX711 operations
-#[derive(Debug)]
-pub enum Hx711Error {
-    /// Flash storage error
-    FlashError,
-    /// Invalid calibration value
-    InvalidCalibration,
-}
-
-impl fmt::Display for Hx711Error {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Hx711Error::FlashError => write!(f, "Flash storage error"),
-            Hx711Error::InvalidCalibration => write!(f, "Invalid calibration value"),
-        }
-    }
+/// Load cell faults detected by the startup self test
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Format)]
+pub enum LoadCellFault {
+    /// The HX711 never signals a reading is ready (not powered or not wired)
+    NotResponding = 1,
+    /// Readings are at the ADC limits (usually a floating input, no load cell)
+    Saturated = 2,
+    /// Readings don't change at all, a connected bridge always shows some noise
+    Stuck = 3,
 }
 
 /// The HX711 has different amplifier gain settings.
@@ -73,12 +129,14 @@ pub enum GainMode {
 }
 
 /// Calibration values
-#[derive(Debug, Clone, Copy)]
+///
+/// A raw reading converts to kg as `(raw - zero_raw) * scale`.
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Calibration {
-    /// Calibration offset
-    offset: f32,
-    /// Calibration factor
-    factor: f32,
+    /// Raw reading with no load applied
+    zero_raw: i32,
+    /// Scale, in kg per raw count
+    scale: f32,
 }
 
 impl Format for Calibration {
@@ -86,170 +144,576 @@ impl Format for Calibration {
         defmt::write!(
             fmt,
             "{{
-                    - Offset: {}
-                    - Factor: {}
+                    - Zero: {}
+                    - Scale: {}
                 }}",
-            self.offset,
-            self.factor
+            self.zero_raw,
+            self.scale
         );
     }
 }
 
 impl Calibration {
-    /// Check if the calibration values are valid
-    pub fn is_valid(&self) -> bool {
-        !self.offset.is_nan() && !self.factor.is_nan() && self.factor != 0.0
+    /// Create calibration values from a zero reference and a scale in kg per raw count
+    pub const fn new(zero_raw: i32, scale: f32) -> Self {
+        Self { zero_raw, scale }
     }
-}
 
-/// HX711 24-bit ADC driver
-pub struct Hx711<'d> {
-    /// Data pin
-    data: Input<'d>,
-    /// Clock pin
-    clock: Output<'d>,
-    /// Delay instance
-    delay: Delay,
-    /// Gain mode
-    gain_mode: GainMode,
-    /// Tare value
-    tare_value: i32,
-    /// Calibration
-    calibration: Calibration,
-}
+    /// Raw reading with no load applied
+    pub fn zero_raw(&self) -> i32 {
+        self.zero_raw
+    }
 
-impl<'d> Hx711<'d> {
-    /// Create a new HX711 driver.
-    pub fn new(data: Input<'d>, mut clock: Output<'d>, delay: Delay) -> Self {
-        info!("HX711 initialized");
-        clock.set_low();
-        Self {
-            data,
-            clock,
-            delay,
-            gain_mode: GainMode::A64,
-            tare_value: 0,
-            calibration: Self::get_calibration().unwrap_or(DEFAULT_CALIBRATION),
-        }
+    /// Scale, in kg per raw count
+    pub fn scale(&self) -> f32 {
+        self.scale
     }
 
     /// Read calibration values from flash
-    fn read_from_flash() -> Result<Calibration, Hx711Error> {
-        let mut flash = FlashStorage::new();
-        let mut bytes = [0u8; 8];
+    ///
+    /// Calibrations stored with the legacy `offset`/`factor` layout, where
+    /// `grams = raw * factor - offset`, are converted.
+    pub fn read_from_flash() -> Result<Calibration, Error> {
+        let mut flash = Flash::new();
+        let mut bytes = [0u8; CALIBRATION_FLASH_SIZE];
 
         flash.read(NVS_ADDR, &mut bytes).map_err(|_| {
             error!("Failed to read calibration from flash");
-            Hx711Error::FlashError
+            Error::Flash
         })?;
 
-        let offset = f32::from_le_bytes(bytes[0..4].try_into().unwrap());
-        let factor = f32::from_le_bytes(bytes[4..8].try_into().unwrap());
-        let calibration = Calibration { offset, factor };
+        let calibration =
+            if u32::from_le_bytes(bytes[0..4].try_into().unwrap()) == CALIBRATION_MAGIC {
+                Calibration {
+                    zero_raw: i32::from_le_bytes(bytes[4..8].try_into().unwrap()),
+                    scale: f32::from_le_bytes(bytes[8..12].try_into().unwrap()),
+                }
+            } else {
+                let offset = f32::from_le_bytes(bytes[0..4].try_into().unwrap());
+                let factor = f32::from_le_bytes(bytes[4..8].try_into().unwrap());
+                Calibration {
+                    zero_raw: (offset / factor) as i32,
+                    scale: factor / 1000.0,
+                }
+            };
 
         if !calibration.is_valid() {
             info!("Invalid calibration values read from flash");
-            return Err(Hx711Error::InvalidCalibration);
+            return Err(Error::CalibrationInvalid);
         }
 
         Ok(calibration)
     }
 
     /// Write calibration values to flash
-    fn write_to_flash(calibration: Calibration) -> Result<(), Hx711Error> {
-        if !calibration.is_valid() {
-            return Err(Hx711Error::InvalidCalibration);
+    pub fn write_to_flash(&self) -> Result<(), Error> {
+        if !self.is_valid() {
+            return Err(Error::CalibrationInvalid);
         }
 
-        let mut flash = FlashStorage::new();
-        let mut bytes = [0u8; 8];
+        let mut flash = Flash::new();
+        let mut bytes = [0u8; CALIBRATION_FLASH_SIZE];
 
-        bytes[0..4].copy_from_slice(&calibration.offset.to_le_bytes());
-        bytes[4..8].copy_from_slice(&calibration.factor.to_le_bytes());
+        bytes[0..4].copy_from_slice(&CALIBRATION_MAGIC.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.zero_raw.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.scale.to_le_bytes());
 
         flash.write(NVS_ADDR, &bytes).map_err(|_| {
             error!("Failed to write calibration to flash");
-            Hx711Error::FlashError
+            Error::Flash
         })?;
 
         Ok(())
     }
 
+    /// Store the zero reference in flash as the last tare, see [`Self::stored_tare`]
+    ///
+    /// Kept apart from the calibration, so a tare doesn't replace the calibrated zero. Flash
+    /// writes erase a whole sector and stall the CPU, so the write is skipped if the stored tare
+    /// is the same. Returns whether it was written.
+    pub fn write_tare_to_flash(&self) -> Result<bool, Error> {
+        if self.stored_tare() == Some(self.zero_raw) {
+            return Ok(false);
+        }
+
+        let mut flash = Flash::new();
+        let mut bytes = [0u8; TARE_FLASH_SIZE];
+
+        bytes[0..4].copy_from_slice(&TARE_MAGIC.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.zero_raw.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.scale.to_le_bytes());
+
+        flash.write(TARE_FLASH_ADDR, &bytes).map_err(|_| {
+            error!("Failed to write the tare to flash");
+            Error::Flash
+        })?;
+
+        Ok(true)
+    }
+
+    /// Read the zero reference of the last tare stored in flash
+    ///
+    /// The tare is stored with the scale it was taken with, and ignored once the load cell is
+    /// calibrated again.
+    pub fn stored_tare(&self) -> Option<i32> {
+        let mut flash = Flash::new();
+        let mut bytes = [0u8; TARE_FLASH_SIZE];
+        if flash.read(TARE_FLASH_ADDR, &mut bytes).is_err() {
+            error!("Failed to read the tare from flash");
+            return None;
+        }
+
+        let magic = u32::from_le_bytes(bytes[0..4].try_into().unwrap());
+        let scale = f32::from_le_bytes(bytes[8..12].try_into().unwrap());
+        if magic != TARE_MAGIC || scale.to_bits() != self.scale.to_bits() {
+            return None;
+        }
+        Some(i32::from_le_bytes(bytes[4..8].try_into().unwrap()))
+    }
+
+    /// Check if the calibration values are valid
+    pub fn is_valid(&self) -> bool {
+        self.scale.is_finite() && self.scale != 0.0
+    }
+
+    /// Converts a raw reading into kg.
+    pub fn to_kg(&self, raw: f32) -> f32 {
+        (raw - self.zero_raw as f32) * self.scale
+    }
+
+    /// Check that a raw reading taken under a known load reads as a positive weight
+    ///
+    /// A mis-signed scale reads every load as negative.
+    pub fn check_polarity(&self, loaded_raw: f32) -> Result<(), Error> {
+        if self.to_kg(loaded_raw) > 0.0 {
+            Ok(())
+        } else {
+            Err(Error::WrongPolarity)
+        }
+    }
+}
+
+/// Maximum deviation from a straight line, in percent of the heaviest mass, for a load cell to
+/// pass the linearity check
+pub const LINEARITY_TOLERANCE_PCT: f32 = 1.0;
+
+/// Maximum number of calibration points
+pub const MAX_CALIBRATION_POINTS: usize = 8;
+
+/// Least squares line through `(raw, weight)` points, as `(mean_raw, mean_weight, slope)`
+///
+/// Needs at least two points at different raw readings.
+fn least_squares(points: &[(f32, f32)]) -> Option<(f32, f32, f32)> {
+    if points.len() < 2 {
+        return None;
+    }
+
+    let n = points.len() as f32;
+    let mean_raw = points.iter().map(|(raw, _)| raw).sum::<f32>() / n;
+    let mean_weight = points.iter().map(|(_, weight)| weight).sum::<f32>() / n;
+    let (covariance, variance) = points.iter().fold((0.0, 0.0), |(cov, var), (raw, weight)| {
+        let dx = raw - mean_raw;
+        (cov + dx * (weight - mean_weight), var + dx * dx)
+    });
+    if variance < f32::EPSILON {
+        return None;
+    }
+
+    Some((mean_raw, mean_weight, covariance / variance))
+}
+
+/// Fit a calibration to `(raw, weight)` points, with weights in grams
+///
+/// Two or more points are fitted with least squares, so extra points spread over the range
+/// average out the errors of each one. A single point is taken relative to `zero_raw`, the
+/// current zero reference (i.e. the tare), the line going through both.
+///
+/// Fails if the heaviest weight is not positive, the points are too close together, or the
+/// heaviest point doesn't read as a positive weight.
+///
+/// The sign of the scale follows the direction of the points, so a load cell whose raw
+/// readings decrease under load is handled. The polarity check guards against the truncated
+/// zero reference landing past the loaded point, which flips the sign of loads when the points
+/// are less than a count apart.
+pub fn fit_calibration(points: &[(f32, f32)], zero_raw: i32) -> Result<Calibration, Error> {
+    let Some(&(loaded_raw, loaded_weight)) = points.iter().max_by(|(_, a), (_, b)| a.total_cmp(b))
+    else {
+        return Err(Error::CalibrationInvalid);
+    };
+    if loaded_weight <= 0.0 {
+        error!(
+            "Invalid calibration - no positive weight: {}",
+            loaded_weight
+        );
+        return Err(Error::CalibrationInvalid);
+    }
+
+    let calibration = if let [_] = points {
+        let span = loaded_raw - zero_raw as f32;
+        if span.abs() < f32::EPSILON {
+            error!("Invalid calibration - point too close to the zero reference");
+            return Err(Error::CalibrationInvalid);
+        }
+        Calibration::new(zero_raw, loaded_weight / 1000.0 / span)
+    } else {
+        let Some((mean_raw, mean_weight, slope)) = least_squares(points) else {
+            error!("Invalid calibration - points are too close together");
+            return Err(Error::CalibrationInvalid);
+        };
+        // Raw reading where the line crosses zero weight
+        let zero_raw = mean_raw - mean_weight / slope;
+        Calibration::new(zero_raw as i32, slope / 1000.0)
+    };
+
+    if !calibration.is_valid() {
+        error!("Invalid calibration values: {:?}", calibration);
+        return Err(Error::CalibrationInvalid);
+    }
+    if let Err(e) = calibration.check_polarity(loaded_raw) {
+        error!(
+            "Invalid calibration - {}kg reads as {}kg",
+            loaded_weight / 1000.0,
+            calibration.to_kg(loaded_raw)
+        );
+        return Err(e);
+    }
+
+    Ok(calibration)
+}
+
+/// Worst deviation of `(raw, weight)` points from their least squares line, in percent of the
+/// heaviest weight
+///
+/// Needs at least three points at different raw readings, as any two are on a line.
+pub fn linearity_error_pct(points: &[(f32, f32)]) -> Option<f32> {
+    if points.len() < 3 {
+        return None;
+    }
+
+    let (mean_raw, mean_weight, slope) = least_squares(points)?;
+    let full_scale = points
+        .iter()
+        .map(|(_, weight)| weight.abs())
+        .fold(0.0, f32::max);
+    if full_scale < f32::EPSILON {
+        return None;
+    }
+
+    let max_error = points
+        .iter()
+        .map(|(raw, weight)| (mean_weight + slope * (raw - mean_raw) - weight).abs())
+        .fold(0.0, f32::max);
+    Some(max_error / full_scale * 100.0)
+}
+
+/// Mean and variance of readings, updated incrementally
+///
+/// Welford's algorithm, which keeps its precision for large sample counts where a plain f32
+/// sum of 24-bit readings would round away the low bits.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RunningMean {
+    /// Number of readings added
+    count: u32,
+    /// Mean of the readings
+    mean: f32,
+    /// Sum of the squared deviations from the mean
+    sum_squares: f32,
+}
+
+impl RunningMean {
+    /// Create a mean without readings
+    pub const fn new() -> Self {
+        Self {
+            count: 0,
+            mean: 0.0,
+            sum_squares: 0.0,
+        }
+    }
+
+    /// Add a reading
+    pub fn add(&mut self, value: f32) {
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f32;
+        self.sum_squares += delta * (value - self.mean);
+    }
+
+    /// Number of readings added
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// Mean of the readings, zero without readings
+    pub fn mean(&self) -> f32 {
+        self.mean
+    }
+
+    /// Population variance of the readings, zero without readings
+    pub fn variance(&self) -> f32 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        self.sum_squares / self.count as f32
+    }
+}
+
+/// Zero reference of a tare, from readings added one at a time
+///
+/// Averages the first readings, then discards [`POST_TARE_DISCARD_SAMPLES`] more, so the first
+/// measurement after the tare is settled.
+#[derive(Debug, Clone, Copy)]
+pub struct TareCapture {
+    /// Number of readings averaged
+    samples: usize,
+    /// Mean of the readings averaged so far
+    mean: RunningMean,
+    /// Number of readings discarded so far
+    discarded: usize,
+}
+
+impl TareCapture {
+    /// Start a tare averaging `samples` readings
+    pub const fn new(samples: usize) -> Self {
+        Self {
+            samples,
+            mean: RunningMean::new(),
+            discarded: 0,
+        }
+    }
+
+    /// Add a reading, returning the zero reference once the discarded readings are in too
+    pub fn add(&mut self, raw: i32) -> Option<i32> {
+        if (self.mean.count() as usize) < self.samples {
+            self.mean.add(raw as f32);
+        } else {
+            self.discarded += 1;
+        }
+
+        let done = self.mean.count() as usize >= self.samples
+            && self.discarded >= POST_TARE_DISCARD_SAMPLES;
+        done.then(|| self.mean.mean() as i32)
+    }
+}
+
+/// Packs a sequence of data line bits (MSB first) into a raw reading.
+///
+/// Only the first [`HX711_DATA_BITS`] bits are used, so a recorded bit stream can be
+/// replayed without the hardware.
+pub fn raw_from_bits(bits: impl IntoIterator<Item = bool>) -> i32 {
+    let value = bits
+        .into_iter()
+        .take(HX711_DATA_BITS)
+        .fold(0u32, |acc, bit| (acc << 1) | bit as u32);
+    convert_raw(value)
+}
+
+/// Converts a 24-bit two's complement reading into a signed, clamped value.
+pub fn convert_raw(value: u32) -> i32 {
+    // Handle sign extension for 24-bit signed values
+    let extended_value = if value & HX711_SIGN_BIT != 0 {
+        value | 0xFF000000 // Negative value, extend the sign bit
+    } else {
+        value // Positive value, no change
+    };
+
+    // Clamp to valid range and return as signed 32-bit
+    (extended_value as i32).clamp(HX711_MINIMUM, HX711_MAXIMUM)
+}
+
+/// Read the calibration stored in flash, or the default HX711 calibration if none is stored
+pub fn stored_calibration() -> Result<Calibration, Error> {
+    match Calibration::read_from_flash() {
+        Ok(calibration) => {
+            info!("Read Calibration values: {:?}", calibration);
+            Ok(calibration)
+        }
+        Err(Error::CalibrationInvalid) => {
+            info!("Using default calibration values");
+            Ok(DEFAULT_CALIBRATION)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Electrical configuration of the clock pin
+///
+/// The default push-pull output at 20mA suits short wires. For long or capacitively loaded
+/// wires, raise the drive strength to 40mA to sharpen the edges first. Open drain only drives
+/// the line low: the internal pull-up is too weak for clean rising edges, so only use it with
+/// an external pull-up (e.g. 4.7kΩ to the HX711 supply), for instance with a 5V HX711.
+#[cfg(target_arch = "riscv32")]
+#[derive(Debug, Clone, Copy)]
+pub struct ClockPinConfig {
+    /// Output drive strength
+    pub drive_strength: DriveStrength,
+    /// Drive the line low only and let a pull-up drive it high
+    pub open_drain: bool,
+}
+
+#[cfg(target_arch = "riscv32")]
+impl ClockPinConfig {
+    /// Push-pull output with the default drive strength
+    pub const DEFAULT: Self = Self {
+        drive_strength: DriveStrength::_20mA,
+        open_drain: false,
+    };
+
+    /// Build the GPIO output configuration
+    fn output_config(&self) -> OutputConfig {
+        let (drive_mode, pull) = if self.open_drain {
+            (DriveMode::OpenDrain, Pull::Up)
+        } else {
+            (DriveMode::PushPull, Pull::None)
+        };
+        OutputConfig::default()
+            .with_drive_strength(self.drive_strength)
+            .with_drive_mode(drive_mode)
+            .with_pull(pull)
+    }
+}
+
+#[cfg(target_arch = "riscv32")]
+impl Default for ClockPinConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// HX711 24-bit ADC driver
+///
+/// # Ownership
+///
+/// The driver owns its pins, so a single instance can exist, and it is owned by the
+/// measurement task, never shared. Other tasks don't call into it: they request operations
+/// (taring, calibration, raw dumps...) through flags in `DeviceState`, which the measurement
+/// task serves between reads. This serializes every access to the ADC without a mutex, and a
+/// bit-banged read can't be interleaved with another operation.
+#[cfg(target_arch = "riscv32")]
+pub struct Hx711<'d> {
+    /// Data pin
+    #[cfg_attr(feature = "demo", allow(dead_code))]
+    data: Input<'d>,
+    /// Clock pin
+    clock: Output<'d>,
+    /// Delay instance
+    #[cfg_attr(feature = "demo", allow(dead_code))]
+    delay: Delay,
+    /// Gain mode
+    gain_mode: GainMode,
+    /// Calibration
+    calibration: Calibration,
+    /// Filtering and range checks of calibrated readings
+    conditioner: ReadingConditioner,
+    /// Number of samples averaged per calibrated reading
+    averaging_samples: usize,
+    /// Time window calibrated readings are averaged over instead of `averaging_samples`
+    averaging_window: Option<Duration>,
+    /// Whether the last calibrated reading included a raw reading at the ADC limits
+    saturated: bool,
+    /// Force profile played back instead of reading the HX711
+    #[cfg(feature = "demo")]
+    demo: ForceProfile,
+}
+
+#[cfg(target_arch = "riscv32")]
+impl<'d> Hx711<'d> {
+    /// Create a new HX711 driver.
+    ///
+    /// The clock pin is reconfigured with `clock_config`, see [`ClockPinConfig`].
+    pub fn new(
+        data: Input<'d>,
+        mut clock: Output<'d>,
+        delay: Delay,
+        clock_config: ClockPinConfig,
+    ) -> Self {
+        info!("HX711 initialized");
+        clock.apply_config(&clock_config.output_config());
+        clock.set_low();
+        let calibration = stored_calibration().unwrap_or(DEFAULT_CALIBRATION);
+        Self {
+            data,
+            clock,
+            delay,
+            gain_mode: GainMode::A64,
+            calibration,
+            conditioner: ReadingConditioner::new(BUTTERWORTH_20HZ_80HZ),
+            averaging_samples: 1,
+            averaging_window: None,
+            saturated: false,
+            #[cfg(feature = "demo")]
+            demo: ForceProfile::new(calibration.zero_raw),
+        }
+    }
+
     /// Update the calibration values in memory and flash.
-    pub fn update_calibration(&mut self, offset: f32, factor: f32) -> Result<(), Hx711Error> {
-        let calibration = Calibration { offset, factor };
+    pub fn update_calibration(&mut self, zero_raw: i32, scale: f32) -> Result<(), Error> {
+        let calibration = Calibration { zero_raw, scale };
 
         if !calibration.is_valid() {
             error!(
-                "Invalid calibration values: offset={}, factor={}",
-                offset, factor
+                "Invalid calibration values: zero_raw={}, scale={}",
+                zero_raw, scale
             );
-            return Err(Hx711Error::InvalidCalibration);
+            return Err(Error::CalibrationInvalid);
         }
 
         debug!(
-            "Updating calibration: offset: {}, factor: {}",
-            offset, factor
+            "Updating calibration: zero_raw: {}, scale: {}",
+            zero_raw, scale
         );
-        Self::write_to_flash(calibration)?;
+        calibration.write_to_flash()?;
 
         self.calibration = calibration;
         Ok(())
     }
 
-    pub fn get_calibration() -> Result<Calibration, Hx711Error> {
-        // Get the calibration values from the NVS flash storage.
-        match Self::read_from_flash() {
-            Ok(calibration) => {
-                info!("Read Calibration values: {:?}", calibration);
-                Ok(calibration)
-            }
-            Err(Hx711Error::InvalidCalibration) => {
-                info!("Using default calibration values");
-                Ok(DEFAULT_CALIBRATION)
-            }
-            Err(e) => Err(e),
-        }
-    }
-
     /// Get the current calibration values.
     pub fn current_calibration(&self) -> Calibration {
         self.calibration
     }
 
     /// Set the default calibration values.
-    pub fn default_calibration(&mut self) -> Result<(), Hx711Error> {
+    pub fn default_calibration(&mut self) -> Result<(), Error> {
         debug!("Restoring default calibration");
-        Self::write_to_flash(DEFAULT_CALIBRATION)?;
+        DEFAULT_CALIBRATION.write_to_flash()?;
         self.calibration = DEFAULT_CALIBRATION;
         Ok(())
     }
 
     /// Reads a single bit from the data pin.
+    ///
+    /// Only the clock high pulse is guarded by a critical section: if PD_SCK stays high
+    /// for more than 60µs the HX711 enters power down mode, so it must not be preempted.
+    /// The low phase can be stretched freely, which lets interrupts run between bits.
     #[inline]
+    #[cfg(not(feature = "demo"))]
     fn read_data_bit(&mut self) -> bool {
-        self.clock.set_high();
-        self.delay.delay_us(HX711_DELAY_TIME_US);
+        let bit = critical_section::with(|_| {
+            self.clock.set_high();
+            self.delay.delay_us(HX711_DELAY_TIME_US);
 
-        let bit = self.data.is_high();
+            let bit = self.data.is_high();
 
-        self.clock.set_low();
+            self.clock.set_low();
+            bit
+        });
         self.delay.delay_us(HX711_DELAY_TIME_US);
 
         bit
     }
 
     /// Toggles the clock pin to prepare for the next gain mode.
+    #[cfg(not(feature = "demo"))]
     fn send_gain_pulses(&mut self) {
-        critical_section::with(|_| {
-            let pulses = self.gain_mode as u8;
-            for _ in 0..pulses {
+        let pulses = self.gain_mode as u8;
+        for _ in 0..pulses {
+            critical_section::with(|_| {
                 self.clock.set_high();
                 self.delay.delay_us(HX711_DELAY_TIME_US);
                 self.clock.set_low();
-                self.delay.delay_us(HX711_DELAY_TIME_US);
-            }
-        });
+            });
+            self.delay.delay_us(HX711_DELAY_TIME_US);
+        }
     }
 
     /// Sets the gain mode for the next reading.
@@ -262,136 +726,737 @@ impl<'d> Hx711<'d> {
         self.gain_mode
     }
 
-    /// Reads 24 bits from the HX711 within a critical section.
+    /// Reads 24 bits from the HX711.
+    ///
+    /// Interrupts are only masked while each clock pulse is high (see [`Self::read_data_bit`]),
+    /// instead of for the whole ~50µs transfer, so BLE and timer interrupts are not delayed.
+    #[cfg(not(feature = "demo"))]
     fn read_raw(&mut self) -> i32 {
-        let value = critical_section::with(|_| {
-            let mut result: u32 = 0;
-            for _ in 0..HX711_DATA_BITS {
-                result = (result << 1) | (self.read_data_bit() as u32);
-            }
-            result
-        });
+        let mut bits = [false; HX711_DATA_BITS];
+        for bit in bits.iter_mut() {
+            *bit = self.read_data_bit();
+        }
 
         self.send_gain_pulses();
 
-        // Handle sign extension for 24-bit signed values
-        let extended_value = if value & HX711_SIGN_BIT != 0 {
-            value | 0xFF000000 // Negative value, extend the sign bit
-        } else {
-            value // Positive value, no change
-        };
+        raw_from_bits(bits)
+    }
 
-        // Clamp to valid range and return as signed 32-bit
-        (extended_value as i32).clamp(HX711_MINIMUM, HX711_MAXIMUM)
+    /// Plays back the next sample of the demo force profile instead of reading the HX711.
+    #[cfg(feature = "demo")]
+    fn read_raw(&mut self) -> i32 {
+        self.demo.next_raw(self.calibration.scale)
+    }
+
+    /// Sets the filter applied to calibrated readings.
+    ///
+    /// Changing the mode resets the filter state.
+    pub fn set_filter_mode(&mut self, filter_mode: FilterMode) {
+        self.conditioner.set_filter_mode(filter_mode);
+    }
+
+    /// Sets the change in kg between consecutive readings above which the filter state snaps to
+    /// the new reading instead of slewing towards it.
+    ///
+    /// Keeps sharp onsets (e.g. the start of a pull) in the filtered curve, while smaller
+    /// changes and noise are still smoothed. Set it well above the noise, infinity disables it.
+    pub fn set_step_reset_threshold(&mut self, threshold: f32) {
+        self.conditioner.set_step_reset_threshold(threshold);
+    }
+
+    /// Gets the current filter mode.
+    pub fn filter_mode(&self) -> FilterMode {
+        self.conditioner.filter_mode()
+    }
+
+    /// Whether the last filtered reading had settled, see [`ReadingConditioner::filter_settled`].
+    ///
+    /// Always true when no filter is applied.
+    pub fn filter_settled(&self) -> bool {
+        self.conditioner.filter_settled()
+    }
+
+    /// Sets the plausible range of calibrated readings, in kg.
+    ///
+    /// Readings outside of it are clamped, see [`Self::out_of_range`]. The full range is used
+    /// by default.
+    pub fn set_plausible_range(&mut self, min: f32, max: f32) {
+        self.conditioner.set_plausible_range(min, max);
+    }
+
+    /// Unclamped value of the last calibrated reading, if it was out of the plausible range.
+    ///
+    /// Usually a sign of a wiring or calibration mistake (e.g. flipped sign or bad scale).
+    pub fn out_of_range(&self) -> Option<f32> {
+        self.conditioner.out_of_range()
+    }
+
+    /// Whether the last calibrated reading was not finite (NaN or infinite) and was replaced
+    /// by zero.
+    ///
+    /// Only happens with a broken calibration, e.g. a non-finite scale.
+    pub fn non_finite(&self) -> bool {
+        self.conditioner.non_finite()
+    }
+
+    /// Checks whether the last calibrated reading hit the ADC limits.
+    ///
+    /// The clamped reading only says the load is beyond what the HX711 can measure (e.g. an
+    /// overloaded load cell), so the weight is not valid.
+    pub fn saturated(&self) -> bool {
+        self.saturated
+    }
+
+    /// Sets the number of samples averaged per calibrated reading, at least one.
+    ///
+    /// Averaging divides the effective sample rate, e.g. 4 samples at 80Hz give 20 readings
+    /// per second.
+    pub fn set_averaging_samples(&mut self, samples: usize) {
+        self.averaging_samples = samples.max(1);
+    }
+
+    /// Gets the number of samples averaged per calibrated reading.
+    pub fn averaging_samples(&self) -> usize {
+        self.averaging_samples
+    }
+
+    /// Sets the time window calibrated readings are averaged over, instead of
+    /// [`Self::averaging_samples`] samples. `None` averages by number of samples again.
+    ///
+    /// Windows shorter than two sample periods are lengthened, so each reading averages at
+    /// least one sample.
+    pub fn set_averaging_window(&mut self, window: Option<Duration>) {
+        self.averaging_window =
+            window.map(|window| window.max(min_averaging_window(NOMINAL_SAMPLE_RATE_HZ)));
     }
 
     /// Waits until the data is ready to be read.
+    #[cfg(not(feature = "demo"))]
     async fn wait_for_ready(&mut self) {
         self.data.wait_for_low().await;
     }
 
+    /// Waits for the next demo sample, paced at the HX711 sample rate.
+    #[cfg(feature = "demo")]
+    async fn wait_for_ready(&mut self) {
+        Timer::after(DEMO_SAMPLE_PERIOD).await;
+    }
+
+    /// Reads a raw value, failing with [`Error::Timeout`] if none is ready within `timeout`.
+    ///
+    /// The HX711 signals a reading is ready by pulling the data line low, which never happens
+    /// when it is not powered or not wired.
+    pub async fn read_with_timeout(&mut self, timeout: Duration) -> Result<i32, Error> {
+        self.read_with_deadline(Instant::now() + timeout).await
+    }
+
+    /// Reads a raw value, failing with [`Error::Timeout`] if none is ready by `deadline`.
+    async fn read_with_deadline(&mut self, deadline: Instant) -> Result<i32, Error> {
+        with_deadline(deadline, self.wait_for_ready())
+            .await
+            .map_err(|_| Error::Timeout)?;
+        let raw = self.read_raw();
+        self.saturated |= raw == HX711_MINIMUM || raw == HX711_MAXIMUM;
+        Ok(raw)
+    }
+
     /// Takes multiple samples and returns the average
-    async fn take_samples(&mut self, num_samples: usize) -> f32 {
-        let mut total: f32 = 0.0;
+    ///
+    /// Uses an incremental mean, which keeps its precision for large sample counts.
+    /// Yields between samples so BLE and timers are serviced during long averages.
+    async fn take_samples(&mut self, num_samples: usize) -> Result<f32, Error> {
+        let mut mean = RunningMean::new();
 
         for _ in 0..num_samples {
-            self.wait_for_ready().await;
-            total += self.read_raw() as f32;
+            let raw = self.read_with_timeout(READ_TIMEOUT).await?;
+            mean.add(raw as f32);
+            yield_now().await;
+        }
+
+        Ok(mean.mean())
+    }
+
+    /// Takes as many samples as arrive within `window` and returns the average
+    ///
+    /// Fails with [`Error::Timeout`] if no sample arrives within the window.
+    async fn take_samples_windowed(&mut self, window: Duration) -> Result<f32, Error> {
+        let deadline = Instant::now() + window;
+        let mut mean = RunningMean::new();
+
+        loop {
+            match self.read_with_deadline(deadline).await {
+                Ok(raw) => mean.add(raw as f32),
+                Err(Error::Timeout) => break,
+                Err(e) => return Err(e),
+            }
+            yield_now().await;
+        }
+
+        if mean.count() == 0 {
+            return Err(Error::Timeout);
+        }
+
+        Ok(mean.mean())
+    }
+
+    /// Discards readings for `duration` to let the HX711 settle after power-up.
+    ///
+    /// The datasheet gives an output settling time of 50ms at 80Hz (400ms at 10Hz), but the
+    /// readings keep drifting while the load cell and the ADC warm up, so a few hundred
+    /// milliseconds are recommended.
+    pub async fn warmup(&mut self, duration: Duration) {
+        debug!("Warming up the load cell for {}ms", duration.as_millis());
+        let deadline = Instant::now() + duration;
+        while with_deadline(deadline, self.wait_for_ready()).await.is_ok() {
+            let _ = self.read_raw();
+            yield_now().await;
+        }
+        self.conditioner.reset();
+    }
+
+    /// Checks that the HX711 responds and a load cell seems to be connected.
+    ///
+    /// The heuristic takes a few readings and fails if the HX711 doesn't signal data ready
+    /// in time, if any reading is at the ADC limits, or if all of them are identical.
+    pub async fn self_test(&mut self) -> Result<(), LoadCellFault> {
+        let mut min = i32::MAX;
+        let mut max = i32::MIN;
+
+        for _ in 0..SELF_TEST_SAMPLES {
+            let timeout = Duration::from_millis(SELF_TEST_TIMEOUT_MS);
+            if with_timeout(timeout, self.wait_for_ready()).await.is_err() {
+                return Err(LoadCellFault::NotResponding);
+            }
+
+            let raw = self.read_raw();
+            if raw == HX711_MINIMUM || raw == HX711_MAXIMUM {
+                return Err(LoadCellFault::Saturated);
+            }
+            min = min.min(raw);
+            max = max.max(raw);
         }
 
-        total / num_samples as f32
+        if min == max {
+            return Err(LoadCellFault::Stuck);
+        }
+
+        Ok(())
+    }
+
+    /// Compares the unloaded reading to the stored zero reference, meant to run at boot.
+    ///
+    /// Warns if they differ by more than [`ZERO_CHECK_TOLERANCE_KG`], which means the load cell
+    /// or its mount shifted. With `auto_zero`, the zero reference is then updated in memory
+    /// only, like a tare. Returns the offset in kg.
+    pub async fn check_zero_offset(&mut self, auto_zero: bool) -> Result<f32, Error> {
+        if !self.calibration.is_valid() {
+            return Ok(0.0);
+        }
+
+        let average = self.take_samples(ZERO_CHECK_SAMPLES).await?;
+        let offset = self.calibration.to_kg(average);
+        if offset.abs() > ZERO_CHECK_TOLERANCE_KG {
+            warn!("Zero offset of {}kg from the stored calibration", offset);
+            if auto_zero {
+                self.calibration.zero_raw = average as i32;
+                self.conditioner.reset();
+                info!("Zero reference set to: {}", self.calibration.zero_raw);
+            }
+        }
+
+        Ok(offset)
     }
 
     /// Tares the sensor by measuring the average of several readings.
-    pub async fn tare(&mut self) {
-        debug!("Taring the scale");
+    ///
+    /// The average overrides the zero reference of the calibration in memory only. If the
+    /// readings time out, the zero reference is kept.
+    pub async fn tare(&mut self) -> Result<(), Error> {
+        self.tare_with_samples(DEFAULT_TARING_SAMPLES).await
+    }
+
+    /// Tares the sensor with only a few readings.
+    ///
+    /// Takes ~50ms at 80Hz instead of ~200ms, but averaging fewer readings leaves about twice
+    /// the noise in the zero reference. Meant for re-zeroing between sets.
+    pub async fn quick_tare(&mut self) -> Result<(), Error> {
+        self.tare_with_samples(QUICK_TARING_SAMPLES).await
+    }
+
+    /// Tares the sensor on a raw reading already taken.
+    ///
+    /// Doesn't read the HX711, so it can zero a running measurement without stalling it. The
+    /// zero reference is as noisy as a single reading (or an averaged one, with averaging).
+    pub fn soft_tare(&mut self, raw: i32) {
         if !self.calibration.is_valid() {
             info!("Invalid calibration values, skipping tare");
             return;
         }
 
-        let average = self.take_samples(DEFAULT_TARING_SAMPLES).await;
-        self.tare_value = average as i32;
-        debug!("Tare value set to: {}", self.tare_value);
+        self.calibration.zero_raw = raw;
+        self.conditioner.reset();
+        debug!("Zero reference set to: {}", self.calibration.zero_raw);
+    }
+
+    /// Tares the sensor with the average of `num_samples` readings.
+    async fn tare_with_samples(&mut self, num_samples: usize) -> Result<(), Error> {
+        debug!("Taring the scale");
+        if !self.calibration.is_valid() {
+            info!("Invalid calibration values, skipping tare");
+            return Ok(());
+        }
+
+        let mut capture = TareCapture::new(num_samples);
+        let zero_raw = loop {
+            let raw = self.read_with_timeout(READ_TIMEOUT).await?;
+            if let Some(zero_raw) = capture.add(raw) {
+                break zero_raw;
+            }
+            yield_now().await;
+        };
+        self.calibration.zero_raw = zero_raw;
+        self.conditioner.reset();
+        debug!("Zero reference set to: {}", self.calibration.zero_raw);
+        Ok(())
     }
 
     /// Reads a raw value without calibration
-    pub async fn read_raw_value(&mut self) -> i32 {
-        self.wait_for_ready().await;
-        self.read_raw()
+    ///
+    /// Returns the ADC counts as read, bypassing the tare, the calibration, the averaging and
+    /// the filter. Meant for calibration tooling, e.g. to establish the zero reference or to
+    /// diagnose tare issues.
+    pub async fn read_raw_value(&mut self) -> Result<i32, Error> {
+        self.read_with_timeout(READ_TIMEOUT).await
     }
 
-    /// Reads a tared raw value (raw value minus tare value)
-    pub async fn read_tared(&mut self) -> i32 {
-        self.wait_for_ready().await;
-        self.read_raw() - self.tare_value
+    /// Reads a tared raw value (raw value minus the zero reference)
+    pub async fn read_tared(&mut self) -> Result<i32, Error> {
+        Ok(self.read_raw_value().await? - self.calibration.zero_raw)
     }
 
     /// Reads a calibrated value, in kg.
-    pub async fn read_calibrated(&mut self) -> f32 {
-        let raw_tared = self.read_tared().await;
-        let calibrated_value = raw_tared as f32 * self.calibration.factor - self.calibration.offset;
-        // Convert to kg
-        calibrated_value / 1000.0
+    pub async fn read_calibrated(&mut self) -> Result<f32, Error> {
+        let raw = self.read_raw_value().await?;
+        Ok(self
+            .conditioner
+            .condition(self.calibration.to_kg(raw as f32)))
     }
 
-    /// Perform two-point calibration with a known target weight
+    /// Reads a calibrated value, in kg, together with the raw reading it was computed from.
+    ///
+    /// The raw reading is averaged over the averaging window if one is set, see
+    /// [`Self::set_averaging_window`], or else over [`Self::averaging_samples`] samples.
+    pub async fn read_calibrated_with_raw(&mut self) -> Result<(f32, i32), Error> {
+        self.saturated = false;
+        let raw = if let Some(window) = self.averaging_window {
+            self.take_samples_windowed(window).await? as i32
+        } else if self.averaging_samples > 1 {
+            self.take_samples(self.averaging_samples).await? as i32
+        } else {
+            self.read_raw_value().await?
+        };
+        let weight = self
+            .conditioner
+            .condition(self.calibration.to_kg(raw as f32));
+        Ok((weight, raw))
+    }
+
+    /// Collect a calibration point with a known target weight
     ///
     /// This method collects raw values for calibration by taking multiple samples
     /// and averaging them for stability.
     ///
     /// Returns the average raw value for the calibration point.
-    pub async fn perform_calibration(&mut self, _target_weight: f32) -> f32 {
-        // Reset calibration to raw values first
-        let _ = self.update_calibration(0.0, 1.0);
-
+    pub async fn perform_calibration(&mut self, _target_weight: f32) -> Result<f32, Error> {
         // Take multiple readings and average them for stability
-        let average_value = self.take_samples(DEFAULT_CALIBRATION_SAMPLES).await;
+        let average_value = self.take_samples(DEFAULT_CALIBRATION_SAMPLES).await?;
         debug!("Calibration point collected: {}", average_value);
 
-        average_value
+        Ok(average_value)
     }
 
-    /// Apply two-point calibration using the collected calibration points
+    /// Collect a calibration point, failing if the readings are not stable
     ///
-    /// This method calculates and applies calibration parameters based on
-    /// two previously measured calibration points and a target weight.
+    /// Takes as many samples as [`Self::perform_calibration`] and returns their average raw
+    /// value, [`Error::Timeout`] if the readings stop, or [`Error::Unstable`] if their standard
+    /// deviation is above
+    /// [`CAPTURE_STABILITY_TOLERANCE_KG`]. The deviation is converted to kg with the current
+    /// calibration, which is close enough as long as it is roughly right.
+    pub async fn capture_stable(&mut self) -> Result<f32, Error> {
+        let mut mean = RunningMean::new();
+        for _ in 0..DEFAULT_CALIBRATION_SAMPLES {
+            let raw = self.read_with_timeout(READ_TIMEOUT).await?;
+            mean.add(raw as f32);
+            yield_now().await;
+        }
+
+        // Compare variances, there is no square root in core
+        let scale = self.calibration.scale;
+        let variance_kg = mean.variance() * scale * scale;
+        if variance_kg > CAPTURE_STABILITY_TOLERANCE_KG * CAPTURE_STABILITY_TOLERANCE_KG {
+            warn!("Unstable capture: {}kg² variance", variance_kg);
+            return Err(Error::Unstable);
+        }
+
+        debug!("Stable calibration point collected: {}", mean.mean());
+        Ok(mean.mean())
+    }
+
+    /// Apply a calibration fitted to `(raw, weight)` points, with weights in grams
     ///
-    /// Returns true if calibration was successfully applied, false otherwise.
-    pub fn apply_two_point_calibration(
-        &mut self,
-        calibration_points: [f32; 2],
-        target_weight: f32,
-    ) -> bool {
-        debug!("Calibration points: {:?}", calibration_points);
-
-        let (point1, point2) = (calibration_points[0], calibration_points[1]);
-
-        // Check for invalid calibration points
-        if (point2 - point1).abs() < f32::EPSILON {
-            error!("Invalid calibration - points are too close together");
-            return false;
+    /// See [`fit_calibration`]. A single point is taken relative to the current zero
+    /// reference. Fails if the points don't make a valid calibration or it can't be stored.
+    pub fn apply_calibration_points(&mut self, points: &[(f32, f32)]) -> Result<(), Error> {
+        debug!("Calibration points: {:?}", points);
+
+        let calibration = fit_calibration(points, self.calibration.zero_raw)?;
+        self.update_calibration(calibration.zero_raw, calibration.scale)?;
+        debug!("Calibration successfully applied");
+        Ok(())
+    }
+}
+
+#[cfg(target_arch = "riscv32")]
+impl LoadCell for Hx711<'_> {
+    fn stored_calibration() -> Result<Calibration, Error> {
+        stored_calibration()
+    }
+
+    fn current_calibration(&self) -> Calibration {
+        Hx711::current_calibration(self)
+    }
+
+    fn update_calibration(&mut self, zero_raw: i32, scale: f32) -> Result<(), Error> {
+        Hx711::update_calibration(self, zero_raw, scale)
+    }
+
+    fn default_calibration(&mut self) -> Result<(), Error> {
+        Hx711::default_calibration(self)
+    }
+
+    fn apply_calibration_points(&mut self, points: &[(f32, f32)]) -> Result<(), Error> {
+        Hx711::apply_calibration_points(self, points)
+    }
+
+    fn set_filter_mode(&mut self, filter_mode: FilterMode) {
+        Hx711::set_filter_mode(self, filter_mode)
+    }
+
+    fn set_step_reset_threshold(&mut self, threshold: f32) {
+        Hx711::set_step_reset_threshold(self, threshold)
+    }
+
+    fn filter_settled(&self) -> bool {
+        Hx711::filter_settled(self)
+    }
+
+    fn sample_rate_hz(&self) -> f32 {
+        NOMINAL_SAMPLE_RATE_HZ
+    }
+
+    fn set_averaging_samples(&mut self, samples: usize) {
+        Hx711::set_averaging_samples(self, samples)
+    }
+
+    fn set_averaging_window(&mut self, window: Option<Duration>) {
+        Hx711::set_averaging_window(self, window)
+    }
+
+    fn set_plausible_range(&mut self, min: f32, max: f32) {
+        Hx711::set_plausible_range(self, min, max)
+    }
+
+    fn out_of_range(&self) -> Option<f32> {
+        Hx711::out_of_range(self)
+    }
+
+    fn non_finite(&self) -> bool {
+        Hx711::non_finite(self)
+    }
+
+    fn saturated(&self) -> bool {
+        Hx711::saturated(self)
+    }
+
+    async fn warmup(&mut self, duration: Duration) {
+        Hx711::warmup(self, duration).await
+    }
+
+    async fn self_test(&mut self) -> Result<(), LoadCellFault> {
+        Hx711::self_test(self).await
+    }
+
+    async fn check_zero_offset(&mut self, auto_zero: bool) -> Result<f32, Error> {
+        Hx711::check_zero_offset(self, auto_zero).await
+    }
+
+    async fn tare(&mut self) -> Result<(), Error> {
+        Hx711::tare(self).await
+    }
+
+    async fn quick_tare(&mut self) -> Result<(), Error> {
+        Hx711::quick_tare(self).await
+    }
+
+    fn soft_tare(&mut self, raw: i32) {
+        Hx711::soft_tare(self, raw)
+    }
+
+    async fn read_calibrated_with_raw(&mut self) -> Result<(f32, i32), Error> {
+        Hx711::read_calibrated_with_raw(self).await
+    }
+
+    async fn perform_calibration(&mut self, target_weight: f32) -> Result<f32, Error> {
+        Hx711::perform_calibration(self, target_weight).await
+    }
+
+    async fn capture_stable(&mut self) -> Result<f32, Error> {
+        Hx711::capture_stable(self).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Bits of `value`, MSB first, as clocked out of the HX711
+    fn bits(value: u32, count: usize) -> impl Iterator<Item = bool> {
+        (0..count).rev().map(move |bit| value >> bit & 1 != 0)
+    }
+
+    #[test]
+    fn raw_value_is_sign_extended() {
+        assert_eq!(convert_raw(0x000001), 1);
+        assert_eq!(convert_raw(0x7FFFFF), HX711_MAXIMUM);
+        assert_eq!(convert_raw(0xFFFFFF), -1);
+        assert_eq!(convert_raw(0x800000), HX711_MINIMUM);
+    }
+
+    #[test]
+    fn raw_value_is_packed_msb_first() {
+        assert_eq!(raw_from_bits(bits(0x123456, 24)), 0x123456);
+        assert_eq!(raw_from_bits(bits(0xFFFFFE, 24)), -2);
+    }
+
+    #[test]
+    fn bits_past_the_reading_are_ignored() {
+        // Gain pulses clock out extra bits after the reading
+        let bits = bits(0x000010, 24).chain([true, true, true]);
+
+        assert_eq!(raw_from_bits(bits), 0x10);
+    }
+
+    #[test]
+    fn running_mean_keeps_the_precision_of_large_readings() {
+        let mut mean = RunningMean::new();
+        for i in 0..100_000 {
+            mean.add(8_388_000.0 + (i % 2) as f32);
         }
 
-        if target_weight <= 0.0 {
-            error!("Invalid target weight: {}", target_weight);
-            return false;
+        assert_eq!(mean.count(), 100_000);
+        assert!((mean.mean() - 8_388_000.5).abs() < 1.0, "{}", mean.mean());
+    }
+
+    #[test]
+    fn running_mean_tracks_the_variance() {
+        let mut mean = RunningMean::new();
+        assert_eq!(mean.variance(), 0.0);
+
+        for value in [-1.0, 1.0, -1.0, 1.0] {
+            mean.add(value + 1000.0);
         }
 
-        // Calculate calibration parameters (convert weight to raw value range)
-        let scale_factor = target_weight / (point2 - point1);
-        let offset = scale_factor * point1;
+        assert_eq!(mean.mean(), 1000.0);
+        assert_eq!(mean.variance(), 1.0);
+    }
 
-        // Apply the calibration
-        match self.update_calibration(offset, scale_factor) {
-            Ok(_) => {
-                debug!("Calibration successfully applied");
-                true
-            }
-            Err(e) => {
-                error!("Failed to apply calibration: {:?}", defmt::Debug2Format(&e));
-                false
-            }
+    #[test]
+    fn tare_discards_the_readings_after_the_average() {
+        let mut capture = TareCapture::new(4);
+        for raw in [100, 102, 98, 100] {
+            assert_eq!(capture.add(raw), None);
         }
+
+        // Still settling, not part of the average
+        for _ in 1..POST_TARE_DISCARD_SAMPLES {
+            assert_eq!(capture.add(5000), None);
+        }
+        assert_eq!(capture.add(5000), Some(100));
+    }
+
+    #[test]
+    fn polarity_needs_loads_to_read_positive() {
+        let calibration = Calibration::new(1000, 0.001);
+
+        assert!(calibration.check_polarity(2000.0).is_ok());
+        assert!(matches!(
+            calibration.check_polarity(500.0),
+            Err(Error::WrongPolarity)
+        ));
+    }
+
+    #[test]
+    fn single_point_is_fitted_from_the_zero_reference() {
+        let calibration = fit_calibration(&[(21000.0, 20000.0)], 1000).unwrap();
+
+        assert_eq!(calibration, Calibration::new(1000, 0.001));
+    }
+
+    #[test]
+    fn points_are_fitted_with_least_squares() {
+        let points = [(1000.0, 0.0), (11000.0, 10000.0), (21000.0, 20000.0)];
+
+        // The zero reference is only used for a single point
+        let calibration = fit_calibration(&points, 5000).unwrap();
+
+        assert_eq!(calibration, Calibration::new(1000, 0.001));
+    }
+
+    #[test]
+    fn readings_decreasing_under_load_are_fitted() {
+        let calibration = fit_calibration(&[(1000.0, 0.0), (-9000.0, 10000.0)], 0).unwrap();
+
+        assert_eq!(calibration, Calibration::new(1000, -0.001));
+        assert_eq!(calibration.to_kg(-9000.0), 10.0);
+    }
+
+    #[test]
+    fn degenerate_points_are_not_fitted() {
+        let invalid = |points: &[(f32, f32)], zero_raw| {
+            matches!(
+                fit_calibration(points, zero_raw),
+                Err(Error::CalibrationInvalid)
+            )
+        };
+
+        assert!(invalid(&[], 0));
+        assert!(invalid(&[(1000.0, 0.0)], 0));
+        assert!(invalid(&[(1000.0, 500.0)], 1000));
+        assert!(invalid(&[(500.0, 0.0), (500.0, 1000.0)], 0));
+    }
+
+    #[test]
+    fn truncated_zero_past_the_load_is_rejected() {
+        // The zero crossing at -1000.8 truncates to -1000, past the loaded point
+        let points = [(-1000.8, 0.0), (-1000.6, 1000.0)];
+
+        assert!(matches!(
+            fit_calibration(&points, 0),
+            Err(Error::WrongPolarity)
+        ));
+    }
+
+    #[test]
+    fn linearity_error_is_relative_to_the_heaviest_weight() {
+        let line = [(0.0, 0.0), (1000.0, 10_000.0), (2000.0, 20_000.0)];
+        assert_eq!(linearity_error_pct(&line), Some(0.0));
+
+        // The middle point sits 300g high, which the fit splits as 100g low at the ends
+        let bent = [(0.0, 0.0), (1000.0, 10_300.0), (2000.0, 20_000.0)];
+        let error = linearity_error_pct(&bent).unwrap();
+        assert!((error - 1.0).abs() < 0.01, "{}", error);
+    }
+
+    #[test]
+    fn linearity_needs_three_distinct_points() {
+        assert_eq!(linearity_error_pct(&[(0.0, 0.0), (1000.0, 10_000.0)]), None);
+        assert_eq!(
+            linearity_error_pct(&[(500.0, 0.0), (500.0, 100.0), (500.0, 200.0)]),
+            None
+        );
+        assert_eq!(
+            linearity_error_pct(&[(0.0, 0.0), (1000.0, 0.0), (2000.0, 0.0)]),
+            None
+        );
+    }
+
+    #[test]
+    fn calibration_converts_from_the_zero_reference() {
+        let calibration = Calibration::new(1000, 0.5);
+
+        assert_eq!(calibration.to_kg(1000.0), 0.0);
+        assert_eq!(calibration.to_kg(1010.0), 5.0);
+        assert_eq!(calibration.to_kg(990.0), -5.0);
+    }
+
+    #[test]
+    fn calibration_roundtrips_through_flash() {
+        let calibration = Calibration::new(-4321, 0.000066);
+
+        calibration.write_to_flash().unwrap();
+
+        assert_eq!(Calibration::read_from_flash().unwrap(), calibration);
+    }
+
+    #[test]
+    fn legacy_calibration_is_converted() {
+        // grams = raw * factor - offset, zero at raw 1000 and 2g per count
+        let mut bytes = [0u8; 8];
+        bytes[0..4].copy_from_slice(&2000.0f32.to_le_bytes());
+        bytes[4..8].copy_from_slice(&2.0f32.to_le_bytes());
+        Flash::new().write(NVS_ADDR, &bytes).unwrap();
+
+        assert_eq!(
+            Calibration::read_from_flash().unwrap(),
+            Calibration::new(1000, 0.002)
+        );
+    }
+
+    #[test]
+    fn blank_flash_has_no_calibration() {
+        assert!(matches!(
+            Calibration::read_from_flash(),
+            Err(Error::CalibrationInvalid)
+        ));
+    }
+
+    #[test]
+    fn invalid_calibration_is_not_stored() {
+        assert!(Calibration::new(0, 0.0).write_to_flash().is_err());
+        assert!(Calibration::new(0, f32::NAN).write_to_flash().is_err());
+    }
+
+    #[test]
+    fn tare_is_restored_for_the_same_calibration() {
+        let calibration = Calibration::new(100, 0.00005);
+        assert_eq!(calibration.stored_tare(), None);
+
+        Calibration::new(2500, 0.00005)
+            .write_tare_to_flash()
+            .unwrap();
+
+        assert_eq!(calibration.stored_tare(), Some(2500));
+        assert_eq!(Calibration::new(100, 0.00006).stored_tare(), None);
+    }
+
+    #[test]
+    fn unchanged_tare_is_not_written_again() {
+        let calibration = Calibration::new(2500, 0.00005);
+
+        assert!(calibration.write_tare_to_flash().unwrap());
+        assert!(!calibration.write_tare_to_flash().unwrap());
+        assert!(Calibration::new(2501, 0.00005)
+            .write_tare_to_flash()
+            .unwrap());
+    }
+
+    #[test]
+    fn tare_is_stored_in_its_own_sector() {
+        assert_ne!(
+            TARE_FLASH_ADDR / FLASH_SECTOR_SIZE,
+            NVS_ADDR / FLASH_SECTOR_SIZE
+        );
+    }
+
+    #[test]
+    fn tare_doesnt_replace_the_calibration() {
+        let calibration = Calibration::new(100, 0.00005);
+        calibration.write_to_flash().unwrap();
+
+        Calibration::new(2500, 0.00005)
+            .write_tare_to_flash()
+            .unwrap();
+
+        assert_eq!(Calibration::read_from_flash().unwrap(), calibration);
     }
 }
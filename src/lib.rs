@@ -0,0 +1,51 @@
+//! Crimpdeq firmware library
+//!
+//! Everything but the hardware bring-up in `main.rs`, so the protocol and the signal processing
+//! can be tested on the host. Modules that drive the ESP32-C3 peripherals are only built for the
+//! chip, run `cargo test-host` to test the rest.
+#![cfg_attr(target_arch = "riscv32", no_std)]
+
+pub mod battery;
+#[cfg(target_arch = "riscv32")]
+pub mod ble;
+#[cfg(feature = "demo")]
+pub mod demo;
+pub mod device_id;
+pub mod device_name;
+pub mod diagnostics;
+pub mod error;
+pub mod filters;
+pub mod flash;
+pub mod hx711;
+pub mod load_cell;
+pub mod logging;
+pub mod metrics;
+#[cfg(all(feature = "nau7802", target_arch = "riscv32"))]
+pub mod nau7802;
+#[cfg(target_arch = "riscv32")]
+pub mod power;
+pub mod progressor;
+pub mod provisioning;
+#[cfg(all(feature = "uart-stream", target_arch = "riscv32"))]
+pub mod uart;
+#[cfg(target_arch = "riscv32")]
+pub mod watchdog;
+
+/// Discards the defmt logs of host tests, the firmware logs over RTT
+#[cfg(not(target_arch = "riscv32"))]
+#[defmt::global_logger]
+struct HostLogger;
+
+#[cfg(not(target_arch = "riscv32"))]
+unsafe impl defmt::Logger for HostLogger {
+    fn acquire() {}
+
+    unsafe fn flush() {}
+
+    unsafe fn release() {}
+
+    unsafe fn write(_bytes: &[u8]) {}
+}
+
+#[cfg(not(target_arch = "riscv32"))]
+defmt::timestamp!("{=u64:us}", embassy_time::Instant::now().as_micros());
@@ -0,0 +1,127 @@
+/// Load cell
+///
+/// Interface of the load cell ADC driver, so the measurement task and the protocol don't depend
+/// on the chip. The `Hx711` and the `Nau7802` implement it, the `nau7802` feature selects which
+/// one is the `BoardLoadCell`.
+use embassy_time::Duration;
+
+// Host tests have no board, the calibration is read as stored for an HX711
+#[cfg(not(target_arch = "riscv32"))]
+pub use crate::hx711::stored_calibration;
+#[cfg(all(not(feature = "nau7802"), target_arch = "riscv32"))]
+use crate::hx711::Hx711;
+#[cfg(all(feature = "nau7802", target_arch = "riscv32"))]
+use crate::nau7802::Nau7802;
+use crate::{
+    error::Error,
+    filters::FilterMode,
+    hx711::{Calibration, LoadCellFault},
+};
+
+/// Load cell driver fitted to the board, the NAU7802 with the `nau7802` feature
+///
+/// Embassy tasks can't be generic, so the driver is picked here rather than with a type
+/// parameter.
+#[cfg(all(not(feature = "nau7802"), target_arch = "riscv32"))]
+pub type BoardLoadCell = Hx711<'static>;
+/// Load cell driver fitted to the board
+#[cfg(all(feature = "nau7802", target_arch = "riscv32"))]
+pub type BoardLoadCell = Nau7802<'static>;
+
+/// Read the calibration stored for the board's load cell, see [`LoadCell::stored_calibration`]
+#[cfg(target_arch = "riscv32")]
+pub fn stored_calibration() -> Result<Calibration, Error> {
+    BoardLoadCell::stored_calibration()
+}
+
+/// Highest nominal sample rate of the supported ADCs in Hz, the NAU7802 at 320SPS
+///
+/// Buffers holding a time window of samples are sized for it.
+pub const MAX_SAMPLE_RATE_HZ: usize = 320;
+
+/// Shortest time window readings are averaged over at `sample_rate_hz`
+///
+/// Two sample periods, so at least one sample always arrives within the window.
+pub fn min_averaging_window(sample_rate_hz: f32) -> Duration {
+    Duration::from_micros((2_000_000.0 / sample_rate_hz) as u64)
+}
+
+/// A load cell ADC with calibration, taring and filtering
+#[allow(async_fn_in_trait)]
+pub trait LoadCell {
+    /// Read the calibration stored in flash, or the default one if none is stored
+    fn stored_calibration() -> Result<Calibration, Error>
+    where
+        Self: Sized;
+
+    /// Get the calibration in use
+    fn current_calibration(&self) -> Calibration;
+
+    /// Update the calibration in memory and flash
+    fn update_calibration(&mut self, zero_raw: i32, scale: f32) -> Result<(), Error>;
+
+    /// Restore the default calibration, in memory and flash
+    fn default_calibration(&mut self) -> Result<(), Error>;
+
+    /// Fit a calibration to `(raw, grams)` points and store it, see `fit_calibration`
+    fn apply_calibration_points(&mut self, points: &[(f32, f32)]) -> Result<(), Error>;
+
+    /// Set the filter applied to calibrated readings
+    fn set_filter_mode(&mut self, filter_mode: FilterMode);
+
+    /// Set the change in kg between readings that snaps the filter to the new reading
+    fn set_step_reset_threshold(&mut self, threshold: f32);
+
+    /// Check whether the filter output has settled
+    fn filter_settled(&self) -> bool;
+
+    /// Nominal sample rate in Hz, before averaging
+    fn sample_rate_hz(&self) -> f32;
+
+    /// Set the number of samples averaged per calibrated reading, at least one
+    fn set_averaging_samples(&mut self, samples: usize);
+
+    /// Average calibrated readings over a time window instead of a number of samples, `None`
+    /// averages by number. Windows are at least [`min_averaging_window`]
+    fn set_averaging_window(&mut self, window: Option<Duration>);
+
+    /// Set the range of plausible calibrated readings in kg, readings outside are clamped
+    fn set_plausible_range(&mut self, min: f32, max: f32);
+
+    /// Get the unclamped value of the last reading, if it was out of the plausible range
+    fn out_of_range(&self) -> Option<f32>;
+
+    /// Check whether the last calibrated reading was not finite
+    fn non_finite(&self) -> bool;
+
+    /// Check whether the last calibrated reading hit the ADC limits, the weight is not valid
+    fn saturated(&self) -> bool;
+
+    /// Discard readings for `duration` after power-up
+    async fn warmup(&mut self, duration: Duration);
+
+    /// Check the load cell responds with plausible readings
+    async fn self_test(&mut self) -> Result<(), LoadCellFault>;
+
+    /// Compare the unloaded reading to the stored zero reference, re-zeroing if `auto_zero`.
+    /// Returns the offset in kg
+    async fn check_zero_offset(&mut self, auto_zero: bool) -> Result<f32, Error>;
+
+    /// Tare with a full set of samples
+    async fn tare(&mut self) -> Result<(), Error>;
+
+    /// Tare with a few samples, to start a measurement quickly
+    async fn quick_tare(&mut self) -> Result<(), Error>;
+
+    /// Tare on a raw reading already taken, without sampling, to zero mid-measurement
+    fn soft_tare(&mut self, raw: i32);
+
+    /// Read a calibrated weight in kg along with its raw reading
+    async fn read_calibrated_with_raw(&mut self) -> Result<(f32, i32), Error>;
+
+    /// Average readings for a calibration point, returns the raw value
+    async fn perform_calibration(&mut self, target_weight: f32) -> Result<f32, Error>;
+
+    /// Average readings once they are stable, returns the raw value
+    async fn capture_stable(&mut self) -> Result<f32, Error>;
+}
@@ -0,0 +1,51 @@
+/// Runtime log level
+///
+/// defmt filters logs at build time through `DEFMT_LOG`. This module adds a runtime gate on
+/// top of it, so the verbosity of a field device can be lowered or raised back (up to the
+/// build time level) without reflashing.
+///
+/// Only the high rate logs of the measurement and notification paths are gated, see
+/// [`enabled`].
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use defmt::Format;
+
+/// Log levels, from least to most verbose
+#[derive(Copy, Debug, Clone, PartialEq, PartialOrd, Format)]
+#[repr(u8)]
+pub enum LogLevel {
+    Off = 0,
+    Error = 1,
+    Warn = 2,
+    Info = 3,
+    Debug = 4,
+    Trace = 5,
+}
+
+impl LogLevel {
+    /// Parse the level from its wire value
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(LogLevel::Off),
+            1 => Some(LogLevel::Error),
+            2 => Some(LogLevel::Warn),
+            3 => Some(LogLevel::Info),
+            4 => Some(LogLevel::Debug),
+            5 => Some(LogLevel::Trace),
+            _ => None,
+        }
+    }
+}
+
+/// Current runtime log level, everything allowed by `DEFMT_LOG` is logged by default
+static LOG_LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Trace as u8);
+
+/// Set the runtime log level
+pub fn set_log_level(level: LogLevel) {
+    LOG_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Check whether logs of the given level are enabled at runtime
+pub fn enabled(level: LogLevel) -> bool {
+    level as u8 <= LOG_LEVEL.load(Ordering::Relaxed)
+}
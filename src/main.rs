@@ -1,47 +1,127 @@
 #![no_std]
 #![no_main]
 
-use core::cell::RefCell;
+use core::cell::{Cell, RefCell};
 
 use bt_hci::controller::ExternalController;
+#[cfg(not(feature = "nau7802"))]
+use crimpdeq::hx711::{ClockPinConfig, Hx711};
+#[cfg(feature = "nau7802")]
+use crimpdeq::nau7802::{Nau7802, Nau7802Config};
+#[cfg(feature = "uart-stream")]
+use crimpdeq::uart;
+use crimpdeq::{
+    battery::{Battery, LowBatteryDetector, BATTERY_CHECK_INTERVAL_S},
+    ble::{
+        advertise,
+        request_connection_params,
+        Server,
+        CONNECTIONS_MAX,
+        L2CAP_CHANNELS_MAX,
+        L2CAP_MTU,
+    },
+    device_id,
+    device_name::DeviceName,
+    diagnostics::{
+        DriftEstimator,
+        OverloadCounter,
+        RateMonitor,
+        RawDump,
+        RawSampleBuffer,
+        ResetReason,
+    },
+    error::Error,
+    filters::{BaselineSubtractor, ChangeDetector, SampleCoalescer, SlewRateLimiter},
+    hx711::{LoadCellFault, MAX_CALIBRATION_POINTS, ZERO_CHECK_TOLERANCE_KG},
+    load_cell::{BoardLoadCell, LoadCell},
+    logging::{self, LogLevel},
+    metrics::{
+        EnvelopeAccumulator,
+        ImpulseAccumulator,
+        PeakRfd,
+        RateEstimator,
+        RepCounter,
+        SessionChecksum,
+    },
+    power::Power,
+    progressor::{
+        self,
+        CalibrationPoint,
+        ControlOpCode,
+        DataPoint,
+        DataPointChannel,
+        DeviceState,
+        GuidedCalibration,
+        GuidedCalibrationStep,
+        MeasurementTaskStatus,
+        ResponseCode,
+    },
+    provisioning::{self, ConfigExport, CONFIG_CHUNK_SIZE},
+    watchdog::{self, BleOperation},
+};
 use critical_section::Mutex;
 use defmt::{debug, error, info, warn};
 use defmt_rtt as _;
 use embassy_executor::Spawner;
-use embassy_futures::{join::join, select::select};
-use embassy_sync::channel::Channel;
-use embassy_time::{Duration, Timer};
+use embassy_futures::{
+    join::join,
+    select::{select, Either},
+};
+use embassy_sync::{
+    blocking_mutex::raw::CriticalSectionRawMutex,
+    channel::Channel,
+    signal::Signal,
+};
+use embassy_time::{Duration, Instant, Timer};
 use esp_alloc as _;
+#[cfg(feature = "nau7802")]
+use esp_hal::time::Rate;
 use esp_hal::{
     clock::CpuClock,
-    delay::Delay,
-    gpio::{Input, InputConfig, Level, Output, OutputConfig, Pull},
+    gpio::{Input, InputConfig, Pull},
     rng::Rng,
-    time,
+    system::{reset_reason, software_reset},
     timer::{systimer::SystemTimer, timg::TimerGroup},
+    tsens::{self, TemperatureSensor},
     Config,
 };
+#[cfg(not(feature = "nau7802"))]
+use esp_hal::{
+    delay::Delay,
+    gpio::{Level, Output, OutputConfig},
+};
 use esp_println as _;
 use esp_wifi::{ble::controller::BleConnector, init, EspWifiController};
 use panic_rtt_target as _;
 use trouble_host::prelude::*;
 
-use crate::{
-    ble::{advertise, Server, CONNECTIONS_MAX, L2CAP_CHANNELS_MAX, L2CAP_MTU},
-    hx711::Hx711,
-    progressor::{
-        ControlOpCode,
-        DataPoint,
-        DataPointChannel,
-        DeviceState,
-        MeasurementTaskStatus,
-        ResponseCode,
-    },
-};
-
-pub mod ble;
-pub mod hx711;
-pub mod progressor;
+/// Delay before a requested reboot or shutdown, in milliseconds
+const REBOOT_DELAY_MS: u64 = 100;
+/// Time the load cell readings are discarded for after power-up, in milliseconds
+///
+/// Covers the HX711 settling time and the initial drift, see `Hx711::warmup`.
+const LOAD_CELL_WARMUP_MS: u64 = 500;
+/// Re-zero at boot when the unloaded reading is off the stored zero reference
+///
+/// Off by default, as it also hides a load applied while booting.
+const BOOT_AUTO_ZERO: bool = false;
+/// Delay before advertising again after a BLE error, in milliseconds
+const BLE_RETRY_DELAY_MS: u64 = 1000;
+/// Interval between heap usage logs, in seconds
+const HEAP_MONITOR_INTERVAL_S: u64 = 30;
+/// Free heap in bytes below which the heap usage is logged as a warning
+const HEAP_LOW_WATERMARK_BYTES: usize = 8 * 1024;
+/// Notifications bit of a Client Characteristic Configuration Descriptor
+const CCCD_NOTIFY: u8 = 0x01;
+/// Electrical configuration of the load cell clock pin, adjust for long wires
+#[cfg(not(feature = "nau7802"))]
+const LOAD_CELL_CLOCK_CONFIG: ClockPinConfig = ClockPinConfig::DEFAULT;
+/// Configuration of the NAU7802, adjust the sample rate, gain and excitation to the load cell
+#[cfg(feature = "nau7802")]
+const LOAD_CELL_NAU7802_CONFIG: Nau7802Config = Nau7802Config::DEFAULT;
+/// I2C clock of the NAU7802, in kHz
+#[cfg(feature = "nau7802")]
+const NAU7802_I2C_FREQUENCY_KHZ: u32 = 400;
 
 // Helper macro for static allocation
 macro_rules! mk_static {
@@ -54,12 +134,16 @@ macro_rules! mk_static {
 }
 
 /// Static tracking the state of the device
-static DEVICE_STATE: Mutex<RefCell<DeviceState>> = Mutex::new(RefCell::new(DeviceState {
-    measurement_status: MeasurementTaskStatus::Disabled,
-    tared: false,
-    start_time: 0,
-    calibration_points: [None, None],
-}));
+static DEVICE_STATE: Mutex<RefCell<DeviceState>> = Mutex::new(RefCell::new(DeviceState::new()));
+
+/// Signaled once the load cell has warmed up, advertising waits for it
+static LOAD_CELL_READY: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// Signaled before powering off, the BLE loop disconnects and stops advertising
+static BLE_SHUTDOWN: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// Latest weight measurement, served to clients reading the data point characteristic
+static LATEST_MEASUREMENT: Mutex<Cell<Option<DataPoint>>> = Mutex::new(Cell::new(None));
 
 #[esp_hal_embassy::main]
 async fn main(spawner: Spawner) -> ! {
@@ -67,10 +151,18 @@ async fn main(spawner: Spawner) -> ! {
     let config = Config::default().with_cpu_clock(CpuClock::max());
     let peripherals = esp_hal::init(config);
 
+    // Cache the reset reason for diagnostics
+    let reason = ResetReason::from(reset_reason());
+    info!("Reset reason: {:?}", reason);
+    critical_section::with(|cs| DEVICE_STATE.borrow_ref_mut(cs).reset_reason = reason);
+
     // Allocate 72KB of heap memory
     esp_alloc::heap_allocator!(size: 72 * 1024);
 
-    debug!("{}", Hx711::get_calibration().unwrap());
+    match BoardLoadCell::stored_calibration() {
+        Ok(calibration) => debug!("{}", calibration),
+        Err(e) => error!("Error reading calibration: {}", e),
+    }
 
     // Initialize BLE controller
     let timg0 = TimerGroup::new(peripherals.TIMG0);
@@ -84,13 +176,43 @@ async fn main(spawner: Spawner) -> ! {
         .unwrap()
     );
 
-    // Initialize load cell pins
-    let clock_pin = Output::new(peripherals.GPIO5, Level::Low, OutputConfig::default());
-    let data_pin = Input::new(
-        peripherals.GPIO4,
-        InputConfig::default().with_pull(Pull::None),
-    );
-    let delay = Delay::new();
+    // Initialize the load cell
+    #[cfg(not(feature = "nau7802"))]
+    let load_cell = {
+        let clock_pin = Output::new(peripherals.GPIO5, Level::Low, OutputConfig::default());
+        let data_pin = Input::new(
+            peripherals.GPIO4,
+            InputConfig::default().with_pull(Pull::None),
+        );
+        let delay = Delay::new();
+        Hx711::new(data_pin, clock_pin, delay, LOAD_CELL_CLOCK_CONFIG)
+    };
+    // NAU7802 on I2C0 (SDA on GPIO5, SCL on GPIO6), DRDY on GPIO4
+    #[cfg(feature = "nau7802")]
+    let load_cell = {
+        let config = esp_hal::i2c::master::Config::default()
+            .with_frequency(Rate::from_khz(NAU7802_I2C_FREQUENCY_KHZ));
+        let i2c = esp_hal::i2c::master::I2c::new(peripherals.I2C0, config)
+            .unwrap()
+            .with_sda(peripherals.GPIO5)
+            .with_scl(peripherals.GPIO6)
+            .into_async();
+        let drdy = Input::new(
+            peripherals.GPIO4,
+            InputConfig::default().with_pull(Pull::None),
+        );
+        Nau7802::new(i2c, drdy, LOAD_CELL_NAU7802_CONFIG)
+    };
+
+    // Initialize the battery voltage monitor
+    let battery = Battery::new(peripherals.ADC1, peripherals.GPIO3);
+
+    // Initialize deep sleep, woken up by a button on GPIO1
+    let power = Power::new(peripherals.LPWR, peripherals.GPIO1);
+
+    // Initialize the chip temperature sensor
+    let temperature_sensor =
+        TemperatureSensor::new(peripherals.TSENS, tsens::Config::default()).unwrap();
 
     // Initialize embassy
     let systimer = SystemTimer::new(peripherals.SYSTIMER);
@@ -100,10 +222,16 @@ async fn main(spawner: Spawner) -> ! {
     let bluetooth = peripherals.BT;
     let connector = BleConnector::new(esp_wifi_ctrl, bluetooth);
     let controller: ExternalController<_, 20> = ExternalController::new(connector);
-    // Use the last 6 bytes of the DEVIC_NAME for the address
-    let device_name = env!("DEVICE_NAME");
+    // Use the last 6 bytes of the DEVIC_NAME for the address, which stays the same when the
+    // device is renamed
+    let build_name = env!("DEVICE_NAME");
     let mut buff: [u8; 6] = [0u8; 6];
-    buff.copy_from_slice(&device_name.as_bytes()[device_name.len() - 6..]);
+    buff.copy_from_slice(&build_name.as_bytes()[build_name.len() - 6..]);
+    let device_name = match DeviceName::load() {
+        Some(name) => mk_static!(DeviceName, name).as_str(),
+        None => build_name,
+    };
+    info!("Device name: {}", device_name);
     buff[5] |= 0xC0;
     let address: Address = Address::random(buff);
     let mut resources: HostResources<
@@ -131,27 +259,78 @@ async fn main(spawner: Spawner) -> ! {
 
     // Spawn tasks
     spawner
-        .spawn(measurement_task(channel, clock_pin, data_pin, delay))
+        .spawn(measurement_task(
+            channel,
+            load_cell,
+            temperature_sensor,
+            battery,
+            power,
+        ))
         .unwrap();
+    spawner.spawn(heap_monitor_task()).unwrap();
+
+    // Reset the device if the measurement task or the executor hang
+    let timg1 = TimerGroup::new(peripherals.TIMG1);
+    spawner.spawn(watchdog::watchdog_task(timg1.wdt)).unwrap();
+
+    // Mirror measurements to the serial port
+    #[cfg(feature = "uart-stream")]
+    {
+        let config = esp_hal::uart::Config::default().with_baudrate(uart::UART_BAUDRATE);
+        let tx = esp_hal::uart::UartTx::new(peripherals.UART0, config)
+            .unwrap()
+            .with_tx(peripherals.GPIO21)
+            .into_async();
+        spawner.spawn(uart::uart_task(tx)).unwrap();
+    }
 
     let _ = join(ble_task(runner), async {
+        // Don't show up as ready until the first measurement can get clean data
+        LOAD_CELL_READY.wait().await;
         loop {
-            match advertise(device_name, &mut peripheral, &server).await {
+            let result = match select(
+                advertise(device_name, &mut peripheral, &server),
+                BLE_SHUTDOWN.wait(),
+            )
+            .await
+            {
+                Either::First(result) => result,
+                // Dropping the advertiser stops advertising
+                Either::Second(()) => break,
+            };
+            match result {
                 Ok(conn) => {
                     // run until any task ends (usually because the connection has been closed),
                     // then return to advertising state.
-                    select(
-                        gatt_events_task(&server, &conn, channel),
-                        data_processing_task(&server, &conn, channel),
+                    let shutdown = select(
+                        select(
+                            gatt_events_task(&server, &conn, channel),
+                            join(
+                                request_connection_params(&stack, &conn),
+                                data_processing_task(&server, &conn, channel),
+                            ),
+                        ),
+                        BLE_SHUTDOWN.wait(),
                     )
                     .await;
+                    if let Either::Second(()) = shutdown {
+                        conn.raw().disconnect();
+                        break;
+                    }
+
+                    // Start the next connection clean, dropping data meant for this client
+                    critical_section::with(|cs| DEVICE_STATE.borrow_ref_mut(cs).reset_session());
+                    channel.clear();
                 }
                 Err(e) => {
-                    let e = defmt::Debug2Format(&e);
-                    panic!("BLE error: {:?}", e);
+                    // Errors (e.g. running out of memory in the host) only drop the current
+                    // attempt, the device keeps measuring and advertises again
+                    error!("BLE error: {:?}", defmt::Debug2Format(&e));
+                    Timer::after(Duration::from_millis(BLE_RETRY_DELAY_MS)).await;
                 }
             }
         }
+        info!("BLE stopped");
     })
     .await;
 
@@ -164,89 +343,594 @@ async fn main(spawner: Spawner) -> ! {
 async fn ble_task<C: Controller, P: PacketPool>(mut runner: Runner<'_, C, P>) {
     loop {
         if let Err(e) = runner.run().await {
-            let e = defmt::Debug2Format(&e);
-            panic!("BLE error: {:?}", e);
+            error!("BLE error: {:?}", defmt::Debug2Format(&e));
+            Timer::after(Duration::from_millis(BLE_RETRY_DELAY_MS)).await;
+        }
+    }
+}
+
+/// Periodically log the heap usage, warning when it runs low
+///
+/// Failed allocations abort, so this only gives early notice as the heap gets tighter.
+#[embassy_executor::task]
+async fn heap_monitor_task() {
+    loop {
+        let (used, free) = (esp_alloc::HEAP.used(), esp_alloc::HEAP.free());
+        if free < HEAP_LOW_WATERMARK_BYTES {
+            warn!("Heap running low: {} bytes used, {} bytes free", used, free);
+        } else if logging::enabled(LogLevel::Debug) {
+            debug!("Heap: {} bytes used, {} bytes free", used, free);
         }
+        Timer::after(Duration::from_secs(HEAP_MONITOR_INTERVAL_S)).await;
     }
 }
 
+/// Owns the load cell and serves every operation on it
+///
+/// The load cell is only accessed from this task. Commands that need the ADC set a request in
+/// `DeviceState`, which is handled here between reads, so operations never run concurrently.
 #[embassy_executor::task]
 async fn measurement_task(
     channel: &'static DataPointChannel,
-    clock_pin: Output<'static>,
-    data_pin: Input<'static>,
-    delay: Delay,
+    mut load_cell: BoardLoadCell,
+    temperature_sensor: TemperatureSensor<'static>,
+    mut battery: Battery,
+    power: Power,
 ) {
-    let mut load_cell = Hx711::new(data_pin, clock_pin, delay);
+    load_cell
+        .warmup(Duration::from_millis(LOAD_CELL_WARMUP_MS))
+        .await;
+    // Zero on the last tare, so the first measurement doesn't need a new one
+    let stored_tare = load_cell.current_calibration().stored_tare();
+    if let Some(zero_raw) = stored_tare {
+        info!("Restoring the last tare: {}", zero_raw);
+        load_cell.soft_tare(zero_raw);
+    }
+    match load_cell.self_test().await {
+        Ok(()) => match load_cell.check_zero_offset(BOOT_AUTO_ZERO).await {
+            Ok(offset) => {
+                // The stored tare may be stale, it only counts if the unloaded reading matches it
+                if stored_tare.is_some() && offset.abs() <= ZERO_CHECK_TOLERANCE_KG {
+                    critical_section::with(|cs| DEVICE_STATE.borrow_ref_mut(cs).tared = true);
+                }
+            }
+            Err(e) => error!("Error checking the zero offset: {}", e),
+        },
+        Err(fault) => {
+            error!("Load cell self test failed: {:?}", fault);
+            critical_section::with(|cs| {
+                DEVICE_STATE.borrow_ref_mut(cs).load_cell_fault = Some(fault)
+            });
+        }
+    }
+    LOAD_CELL_READY.signal(());
+    let mut overloads = OverloadCounter::load();
+    critical_section::with(|cs| DEVICE_STATE.borrow_ref_mut(cs).overload_count = overloads.count());
+    let mut impulse = ImpulseAccumulator::new();
+    let mut envelope = EnvelopeAccumulator::new();
+    let mut slew_limiter = SlewRateLimiter::new();
+    let mut rep_counter = RepCounter::new();
+    let mut change_detector = ChangeDetector::new();
+    let mut coalescer = SampleCoalescer::new();
+    let mut rate_estimator = RateEstimator::new();
+    let mut baseline = BaselineSubtractor::new();
+    let mut peak_rfd = PeakRfd::new();
+    let mut was_out_of_range = false;
+    let mut was_non_finite = false;
+    let mut was_saturated = false;
+    let mut was_not_responding = false;
+    let mut checksum = SessionChecksum::new();
+    // Whether a measurement is running (or paused), to report its checksum when it stops
+    let mut measuring = false;
+    // Number of samples measured and timestamp of the last one, reported when it stops
+    let mut session_samples: u32 = 0;
+    let mut last_timestamp: u64 = 0;
+    let mut raw_samples = RawSampleBuffer::new();
+    let mut raw_dump: Option<RawDump> = None;
+    let mut config_export: Option<ConfigExport> = None;
+    let mut drift = DriftEstimator::new();
+    let mut rate_monitor = RateMonitor::new();
+    let mut last_heartbeat = Instant::now();
+    let mut last_battery_check = Instant::now();
+    let mut low_battery = LowBatteryDetector::new();
+    // Start time of the measurement the accumulators belong to
+    let mut session_start_time = None;
 
     loop {
+        watchdog::check_in();
+
         // Get current device state
-        let (status, start_time) = critical_section::with(|cs| {
-            let state = DEVICE_STATE.borrow_ref(cs);
-            (state.measurement_status, state.start_time)
-        });
+        let state = critical_section::with(|cs| *DEVICE_STATE.borrow_ref(cs));
+        let (status, start_time) = (state.measurement_status, state.start_time);
+        load_cell.set_filter_mode(state.filter_mode);
+        load_cell.set_step_reset_threshold(state.filter_step_reset);
+        load_cell.set_averaging_samples(state.averaging_samples as usize);
+        load_cell.set_averaging_window(
+            (state.averaging_window_ms > 0)
+                .then(|| Duration::from_millis(state.averaging_window_ms as u64)),
+        );
+        let (min, max) = state.plausible_range;
+        load_cell.set_plausible_range(min, max);
+
+        // Reset or power off between operations, so no tare, calibration or flash write is
+        // interrupted. The calibration is stored when applied, so only the overload counter
+        // may have to be flushed.
+        if state.shutting_down() {
+            if overloads.unsaved() {
+                let _ = overloads.store();
+            }
+            DataPoint::from(ResponseCode::ShuttingDown).send(channel);
+            // Give the BLE stack some time to acknowledge the command and notify the client
+            Timer::after(Duration::from_millis(REBOOT_DELAY_MS)).await;
+            if state.shutdown_requested {
+                info!("Shutting down");
+                BLE_SHUTDOWN.signal(());
+                // Let the client be disconnected before the radio powers off
+                Timer::after(Duration::from_millis(REBOOT_DELAY_MS)).await;
+                power.deep_sleep();
+            }
+            info!("Rebooting");
+            software_reset();
+        }
+
+        if state.temperature_requested {
+            critical_section::with(|cs| {
+                DEVICE_STATE.borrow_ref_mut(cs).temperature_requested = false
+            });
+            let temperature = temperature_sensor.get_temperature().to_celsius();
+            let response = ResponseCode::Temperature(temperature);
+            info!("Temperature: {:?}", response);
+            DataPoint::from(response).send(channel);
+        }
+
+        if state.battery_requested {
+            critical_section::with(|cs| DEVICE_STATE.borrow_ref_mut(cs).battery_requested = false);
+            let response = ResponseCode::SampleBatteryVoltage(battery.voltage_mv().await);
+            info!("SampleBattery: {:?}", response);
+            DataPoint::from(response).send(channel);
+        }
+
+        // Warn once and power off when the battery runs low, the shutdown happens on the next
+        // iteration so no further check is done. Only checked between sessions, so a bad
+        // reading never cuts a measurement short.
+        if status == MeasurementTaskStatus::Disabled
+            && !state.shutting_down()
+            && last_battery_check.elapsed().as_secs() >= BATTERY_CHECK_INTERVAL_S
+        {
+            last_battery_check = Instant::now();
+            let voltage_mv = battery.voltage_mv().await;
+            if low_battery.update(voltage_mv) {
+                warn!("Battery low ({}mV), shutting down", voltage_mv);
+                DataPoint::from(ResponseCode::LowPowerWarning).send(channel);
+                critical_section::with(|cs| {
+                    DEVICE_STATE.borrow_ref_mut(cs).shutdown_requested = true
+                });
+            }
+        }
+
+        // Show the client the device is alive while there is no data stream
+        if status == MeasurementTaskStatus::Enabled || state.heartbeat_interval_ms == 0 {
+            last_heartbeat = Instant::now();
+        } else if last_heartbeat.elapsed().as_millis() >= state.heartbeat_interval_ms as u64 {
+            last_heartbeat = Instant::now();
+            let uptime_ms = last_heartbeat.as_millis() as u32;
+            DataPoint::from(ResponseCode::Heartbeat(uptime_ms)).send(channel);
+        }
+
+        if state.peak_rfd_requested {
+            critical_section::with(|cs| DEVICE_STATE.borrow_ref_mut(cs).peak_rfd_requested = false);
+            let response = ResponseCode::PeakRfd(state.reported_weight(peak_rfd.peak()));
+            info!("Peak RFD: {:?}", response);
+            DataPoint::from(response).send(channel);
+        }
+
+        if state.drift_requested {
+            critical_section::with(|cs| DEVICE_STATE.borrow_ref_mut(cs).drift_requested = false);
+            let response = ResponseCode::DriftEstimate(drift.estimate().unwrap_or(f32::NAN));
+            info!("Drift estimate: {:?}", response);
+            DataPoint::from(response).send(channel);
+        }
+
+        if state.config_export_requested {
+            critical_section::with(|cs| {
+                DEVICE_STATE.borrow_ref_mut(cs).config_export_requested = false
+            });
+            match progressor::device_id() {
+                Ok(device_id) => {
+                    let blob = provisioning::export(
+                        load_cell.current_calibration(),
+                        device_id,
+                        DeviceName::load(),
+                    );
+                    config_export = Some(ConfigExport::new(blob));
+                }
+                Err(e) => error!("Error exporting configuration: {}", e),
+            }
+        }
+        // Stream the export without stalling measurements, chunks that don't fit in the channel
+        // are sent on the next iteration. It is dropped with the client, like the raw dump
+        if !state.notifications_enabled {
+            config_export = None;
+        }
+        if let Some(export) = config_export.as_mut() {
+            export.send_chunks(|offset, chunk| {
+                let mut data = [0u8; CONFIG_CHUNK_SIZE];
+                data[..chunk.len()].copy_from_slice(chunk);
+                let response = ResponseCode::ConfigChunk {
+                    offset,
+                    len: chunk.len() as u8,
+                    data,
+                };
+                DataPoint::from(response).send(channel)
+            });
+            if export.is_done() {
+                config_export = None;
+            }
+        }
+
+        if let Some(name) = state.pending_name {
+            let result = name.store();
+            if let Err(e) = result {
+                error!("Error storing the device name: {}", e);
+            }
+            DataPoint::from(ResponseCode::NameSet(result.is_ok())).send(channel);
+            critical_section::with(|cs| {
+                let mut state = DEVICE_STATE.borrow_ref_mut(cs);
+                state.pending_name = None;
+                // Reboot to advertise the new name and update the GAP device name
+                if result.is_ok() {
+                    state.stop_measurement();
+                    state.reboot_requested = true;
+                }
+            });
+        }
+
+        if let Some(id) = state.pending_device_id {
+            let result = device_id::store(id);
+            if let Err(e) = result {
+                error!("Error storing the Progressor ID: {}", e);
+            }
+            DataPoint::from(ResponseCode::ProgressorIdSet(result.is_ok())).send(channel);
+            critical_section::with(|cs| DEVICE_STATE.borrow_ref_mut(cs).pending_device_id = None);
+        }
+
+        if let Some(calibration) = state.imported_calibration {
+            critical_section::with(|cs| {
+                DEVICE_STATE.borrow_ref_mut(cs).imported_calibration = None
+            });
+            let result = load_cell.update_calibration(calibration.zero_raw(), calibration.scale());
+            if let Err(e) = result {
+                error!("Error applying imported configuration: {}", e);
+            }
+            DataPoint::from(ResponseCode::ConfigImported(result.is_ok())).send(channel);
+        }
+
+        // Stream a chunk of the raw buffer dump, if any, without stalling measurements. What
+        // doesn't fit in the channel is sent on the next iteration. The dump is dropped with the
+        // client, the session reset disables notifications.
+        if state.raw_dump_requested {
+            critical_section::with(|cs| DEVICE_STATE.borrow_ref_mut(cs).raw_dump_requested = false);
+            raw_dump = Some(RawDump::new(raw_samples));
+        }
+        if !state.notifications_enabled {
+            raw_dump = None;
+        }
+        if let Some(dump) = raw_dump.as_mut() {
+            dump.send_chunk(|raw| DataPoint::from(ResponseCode::RawValue(raw)).send(channel));
+            if dump.is_done() {
+                raw_dump = None;
+            }
+        }
 
         match status {
             MeasurementTaskStatus::Disabled => {
+                if measuring {
+                    measuring = false;
+                    if let Some(data_point) = flush_coalesced(&mut coalescer, &state, channel) {
+                        checksum.update(data_point.payload());
+                    }
+                    DataPoint::from(ResponseCode::PeakRfd(
+                        state.reported_weight(peak_rfd.peak()),
+                    ))
+                    .send(channel);
+                    let response = ResponseCode::SessionChecksum(checksum.value());
+                    info!("Measurement stopped: {:?}", response);
+                    DataPoint::from(response).send(channel);
+                    DataPoint::from(ResponseCode::MeasurementStopped {
+                        samples: session_samples,
+                        duration_ms: (last_timestamp / 1000) as u32,
+                    })
+                    .send(channel);
+                }
+
+                // Flash writes stall the CPU, so only persist between measurements
+                if overloads.persist_due() {
+                    let _ = overloads.store();
+                }
+
                 // Do nothing when disabled
                 Timer::after(Duration::from_millis(10)).await;
             }
-            MeasurementTaskStatus::Tare => {
+            MeasurementTaskStatus::Paused => {
+                // Keep the accumulated metrics, but don't integrate over the pause
+                impulse.pause();
+                rate_monitor.reset();
+                Timer::after(Duration::from_millis(10)).await;
+            }
+            MeasurementTaskStatus::Tare
+            | MeasurementTaskStatus::TareAndStart
+            | MeasurementTaskStatus::QuickTare => {
                 // Perform taring operation
-                load_cell.tare().await;
+                let result = if status == MeasurementTaskStatus::QuickTare {
+                    load_cell.quick_tare().await
+                } else {
+                    load_cell.tare().await
+                };
+                match result {
+                    // Only tares asked for are kept, not the auto-tare before a measurement,
+                    // as flash writes stall the CPU
+                    Ok(()) if status != MeasurementTaskStatus::TareAndStart => {
+                        if let Err(e) = load_cell.current_calibration().write_tare_to_flash() {
+                            error!("Error storing the tare: {}", e);
+                        }
+                    }
+                    Ok(()) => {}
+                    Err(e) => {
+                        error!("Error taring: {}", e);
+                        report_not_responding(channel);
+                    }
+                }
+                // The drift is relative to the zero reference
+                drift.reset();
 
                 critical_section::with(|cs| {
                     let mut state = DEVICE_STATE.borrow_ref_mut(cs);
-                    state.tared = true;
-                    state.measurement_status = MeasurementTaskStatus::Disabled;
+                    state.tared |= result.is_ok();
+                    // Only start if the measurement wasn't stopped while taring
+                    if result.is_ok()
+                        && state.measurement_status == MeasurementTaskStatus::TareAndStart
+                    {
+                        state.start_measurement();
+                        DataPoint::from(ResponseCode::MeasurementStarted(state.start_time as u32))
+                            .send(channel);
+                    } else {
+                        state.measurement_status = MeasurementTaskStatus::Disabled;
+                    }
                 });
             }
             MeasurementTaskStatus::Enabled => {
-                send_weight_measurement(&mut load_cell, start_time, channel).await;
+                measuring = true;
+
+                // A new start time means a new measurement was started
+                if session_start_time != Some(start_time) {
+                    session_start_time = Some(start_time);
+                    impulse.reset();
+                    envelope.reset();
+                    rate_monitor.reset();
+                    slew_limiter.reset();
+                    rep_counter.reset();
+                    change_detector.reset();
+                    coalescer.reset();
+                    rate_estimator.reset();
+                    baseline.reset();
+                    peak_rfd.reset();
+                    checksum.reset();
+                    session_samples = 0;
+                }
+
+                // Report once when readings stop, without stalling the rest of the device
+                let (weight, raw) = match load_cell.read_calibrated_with_raw().await {
+                    Ok(reading) => {
+                        was_not_responding = false;
+                        reading
+                    }
+                    Err(e) => {
+                        if !was_not_responding {
+                            error!("Error reading the load cell: {}", e);
+                            report_not_responding(channel);
+                        }
+                        was_not_responding = true;
+                        continue;
+                    }
+                };
+                // Report once when readings start saturating, and drop them as the weight is
+                // not valid
+                let saturated = load_cell.saturated();
+                if saturated && !was_saturated {
+                    warn!("Load cell ADC saturated");
+                    DataPoint::from(ResponseCode::LoadCellSaturated).send(channel);
+                    overloads.record();
+                    critical_section::with(|cs| {
+                        DEVICE_STATE.borrow_ref_mut(cs).overload_count = overloads.count()
+                    });
+                }
+                was_saturated = saturated;
+                if saturated {
+                    continue;
+                }
+
+                // Zero on this reading, it was taken against the previous zero reference
+                if state.soft_tare_requested {
+                    load_cell.soft_tare(raw);
+                    drift.reset();
+                    critical_section::with(|cs| {
+                        let mut state = DEVICE_STATE.borrow_ref_mut(cs);
+                        state.soft_tare_requested = false;
+                        state.tared = true;
+                    });
+                    continue;
+                }
+                raw_samples.push(raw);
+                let sample_rate_hz = state.measurement_rate_hz(load_cell.sample_rate_hz());
+                drift.add_sample(weight, sample_rate_hz);
+
+                // Report once when readings start being clamped
+                let out_of_range = load_cell.out_of_range();
+                if let (Some(unclamped), false) = (out_of_range, was_out_of_range) {
+                    warn!("Weight out of the plausible range: {}kg", unclamped);
+                    DataPoint::from(ResponseCode::WeightOutOfRange(unclamped)).send(channel);
+                    overloads.record();
+                    critical_section::with(|cs| {
+                        DEVICE_STATE.borrow_ref_mut(cs).overload_count = overloads.count()
+                    });
+                }
+                was_out_of_range = out_of_range.is_some();
+
+                // Report once when readings start being non-finite
+                let non_finite = load_cell.non_finite();
+                if non_finite && !was_non_finite {
+                    error!("Non-finite weight replaced by zero, check the calibration");
+                    DataPoint::from(ResponseCode::InvalidWeight).send(channel);
+                }
+                was_non_finite = non_finite;
+
+                let weight = slew_limiter.apply(weight, state.max_weight_step);
+                let settled = load_cell.filter_settled();
+                let elapsed_us = measurement_timestamp(start_time);
+                // The metrics only take differences between samples, which wrap cleanly
+                let timestamp = elapsed_us as u32;
+                let weight = if state.baseline_subtraction {
+                    baseline.apply(weight, timestamp)
+                } else {
+                    weight
+                };
+                session_samples = session_samples.saturating_add(1);
+                last_timestamp = elapsed_us;
+                let report = match state.report_on_change.as_ref() {
+                    Some(config) => change_detector.should_report(weight, timestamp, config),
+                    None => true,
+                };
+                peak_rfd.add_sample(weight, timestamp);
+                let rate = if state.rate_window_ms > 0 {
+                    rate_estimator.add_sample(weight, timestamp, state.rate_window_ms)
+                } else {
+                    0.0
+                };
+                if report {
+                    let sample = WeightSample {
+                        weight,
+                        raw,
+                        timestamp: elapsed_us,
+                        settled,
+                        rate,
+                    };
+                    if let Some(data_point) =
+                        send_weight_measurement(&sample, &state, &mut coalescer, channel)
+                    {
+                        checksum.update(data_point.payload());
+                    }
+                    #[cfg(feature = "uart-stream")]
+                    uart::mirror(elapsed_us, state.reported_weight(weight));
+                }
+
+                let impulse_interval = state
+                    .impulse_report_interval
+                    .unwrap_or((sample_rate_hz as u32).max(1));
+                if let Some(value) = impulse.add_sample(weight, timestamp, impulse_interval) {
+                    DataPoint::from(ResponseCode::Impulse(value)).send(channel);
+                }
+
+                if let Some(actual_hz) = rate_monitor.add_sample(timestamp, sample_rate_hz) {
+                    warn!("Measurement rate dropped to {}Hz", actual_hz);
+                    DataPoint::from(ResponseCode::RateWarning(actual_hz)).send(channel);
+                }
+
+                if let Some((mean, min, max)) = envelope.add_sample(weight, state.envelope_window) {
+                    let response = ResponseCode::WeightEnvelope {
+                        mean: state.reported_weight(mean),
+                        min: state.reported_weight(min),
+                        max: state.reported_weight(max),
+                    };
+                    DataPoint::from(response).send(channel);
+                }
+
+                if let Some(thresholds) = state.rep_thresholds.as_ref() {
+                    let force = state.pull_force(weight);
+                    if let Some(count) = rep_counter.add_sample(force, timestamp, thresholds) {
+                        DataPoint::from(ResponseCode::RepCount(count)).send(channel);
+                    }
+                }
+
+                if state.measure_duration_elapsed(elapsed_us) {
+                    info!("Measure duration reached, stopping measurement");
+                    critical_section::with(|cs| {
+                        let mut state = DEVICE_STATE.borrow_ref_mut(cs);
+                        // Don't stop a measurement restarted in the meantime
+                        if state.start_time == start_time {
+                            state.stop_measurement();
+                        }
+                    });
+                }
             }
             MeasurementTaskStatus::Calibration(weight) => {
                 // Use the load cell's own calibration method to collect a calibration point
-                let calibration_point = load_cell.perform_calibration(weight).await;
+                let raw = load_cell.perform_calibration(weight).await;
+                if let Err(e) = raw {
+                    error!("Error collecting calibration point: {}", e);
+                    report_not_responding(channel);
+                    DataPoint::from(ResponseCode::CalibrationResult(false)).send(channel);
+                }
 
-                critical_section::with(|cs| {
+                let calibration_points = critical_section::with(|cs| {
                     let mut state = DEVICE_STATE.borrow_ref_mut(cs);
-
-                    // Store calibration point (either first or second)
-                    if state.calibration_points[0].is_none() {
-                        state.calibration_points[0] = Some(calibration_point);
-                    } else {
-                        state.calibration_points[1] = Some(calibration_point);
-
-                        // Calculate and apply calibration if we have both points
-                        if let (Some(point1), Some(point2)) =
-                            (state.calibration_points[0], state.calibration_points[1])
-                        {
-                            if !load_cell.apply_two_point_calibration([point1, point2], weight) {
-                                error!(
-                                    "Failed to apply calibration points: {:?}",
-                                    state.calibration_points
-                                );
-                            }
-                        }
+                    let slot = state.calibration_points.iter_mut().find(|p| p.is_none());
+                    if let (Some(slot), Ok(raw)) = (slot, raw) {
+                        *slot = Some(CalibrationPoint { raw, weight });
                     }
 
                     // Disable measurement mode after capturing point
                     state.measurement_status = MeasurementTaskStatus::Disabled;
+                    state.calibration_points
+                });
+
+                // Fit the calibration once there is a loaded point, e.g. after the zero
+                let mut points = [(0.0, 0.0); MAX_CALIBRATION_POINTS];
+                let mut len = 0;
+                for point in calibration_points.iter().flatten() {
+                    points[len] = (point.raw, point.weight);
+                    len += 1;
+                }
+                if raw.is_ok() && points[..len].iter().any(|&(_, weight)| weight > 0.0) {
+                    let result = load_cell.apply_calibration_points(&points[..len]);
+                    if let Err(e) = result {
+                        error!(
+                            "Failed to apply calibration points {:?}: {}",
+                            calibration_points, e
+                        );
+                    }
+                    DataPoint::from(ResponseCode::CalibrationResult(result.is_ok())).send(channel);
+                }
+            }
+            MeasurementTaskStatus::LinearityPoint(weight) => {
+                // Collected like a calibration point, but kept apart from the calibration
+                let raw = load_cell.perform_calibration(weight).await;
+                if let Err(e) = raw {
+                    error!("Error collecting linearity point: {}", e);
+                    report_not_responding(channel);
+                }
+
+                critical_section::with(|cs| {
+                    let mut state = DEVICE_STATE.borrow_ref_mut(cs);
+                    let slot = state.linearity_points.iter_mut().find(|p| p.is_none());
+                    if let (Some(slot), Ok(raw)) = (slot, raw) {
+                        *slot = Some(CalibrationPoint { raw, weight });
+                    }
+                    state.measurement_status = MeasurementTaskStatus::Disabled;
                 });
             }
             MeasurementTaskStatus::DefaultCalibration => {
                 // Reset calibration to default values
                 if let Err(e) = load_cell.default_calibration() {
-                    error!(
-                        "Error applying default calibration: {:?}",
-                        defmt::Debug2Format(&e)
-                    );
+                    error!("Error applying default calibration: {}", e);
                 }
                 critical_section::with(|cs| {
                     let mut state = DEVICE_STATE.borrow_ref_mut(cs);
                     state.measurement_status = MeasurementTaskStatus::Disabled;
                 });
             }
+            MeasurementTaskStatus::GuidedCalibration(phase) => {
+                run_guided_calibration(phase, status, &mut load_cell, channel).await;
+            }
         }
 
         // Add a short delay to prevent tight loops
@@ -256,24 +940,202 @@ async fn measurement_task(
     }
 }
 
-/// Send a weight measurement data point with current timestamp
-async fn send_weight_measurement(
-    load_cell: &mut Hx711<'_>,
-    start_time: u32,
+/// Tell the client the load cell stopped responding
+fn report_not_responding(channel: &'static DataPointChannel) {
+    DataPoint::from(ResponseCode::LoadCellFault(LoadCellFault::NotResponding)).send(channel);
+}
+
+/// Run a step of a guided calibration, see `GuidedCalibration`
+///
+/// `status` is the status the step was started from: if it changed meanwhile (e.g. the
+/// calibration was cancelled), the outcome is discarded.
+async fn run_guided_calibration(
+    phase: GuidedCalibration,
+    status: MeasurementTaskStatus,
+    load_cell: &mut impl LoadCell,
     channel: &'static DataPointChannel,
 ) {
-    let weight = load_cell.read_calibrated().await;
-    let timestamp = (time::Instant::now().duration_since_epoch()).as_micros() as u32 - start_time;
+    let cancelled =
+        || critical_section::with(|cs| DEVICE_STATE.borrow_ref(cs).measurement_status != status);
 
-    debug!(
-        "Sending measurement: Weight: {}kg, Timestamp: {:?}",
-        weight,
-        timestamp as f32 / 1000000.0
-    );
+    let (next, step) = match phase {
+        GuidedCalibration::WaitingForZero | GuidedCalibration::WaitingForSpan { .. } => {
+            // Stream live readings, so the user can see when the load is stable
+            match load_cell.read_calibrated_with_raw().await {
+                Ok((weight, raw)) => {
+                    DataPoint::from(ResponseCode::WeightWithRaw { weight, raw }).send(channel);
+                }
+                Err(e) => error!("Error reading the load cell: {}", e),
+            }
+            return;
+        }
+        GuidedCalibration::CaptureZero => match load_cell.capture_stable().await {
+            Ok(zero_raw) => (
+                MeasurementTaskStatus::GuidedCalibration(GuidedCalibration::WaitingForSpan {
+                    zero_raw,
+                }),
+                GuidedCalibrationStep::ZeroCaptured,
+            ),
+            Err(Error::Unstable) => (
+                MeasurementTaskStatus::GuidedCalibration(GuidedCalibration::WaitingForZero),
+                GuidedCalibrationStep::Unstable,
+            ),
+            Err(e) => {
+                error!("Guided calibration failed: {}", e);
+                (
+                    MeasurementTaskStatus::Disabled,
+                    GuidedCalibrationStep::Failed,
+                )
+            }
+        },
+        GuidedCalibration::CaptureSpan { zero_raw, weight } => {
+            match load_cell.capture_stable().await {
+                // Don't store a calibration that was cancelled while capturing
+                Ok(_) if cancelled() => return,
+                Ok(span_raw) => {
+                    let result =
+                        load_cell.apply_calibration_points(&[(zero_raw, 0.0), (span_raw, weight)]);
+                    if let Err(e) = result {
+                        error!("Guided calibration failed: {}", e);
+                    }
+                    let step = if result.is_ok() {
+                        GuidedCalibrationStep::Completed
+                    } else {
+                        GuidedCalibrationStep::Failed
+                    };
+                    (MeasurementTaskStatus::Disabled, step)
+                }
+                Err(Error::Unstable) => (
+                    MeasurementTaskStatus::GuidedCalibration(GuidedCalibration::WaitingForSpan {
+                        zero_raw,
+                    }),
+                    GuidedCalibrationStep::Unstable,
+                ),
+                Err(e) => {
+                    error!("Guided calibration failed: {}", e);
+                    (
+                        MeasurementTaskStatus::Disabled,
+                        GuidedCalibrationStep::Failed,
+                    )
+                }
+            }
+        }
+    };
+
+    let applied = critical_section::with(|cs| {
+        let mut state = DEVICE_STATE.borrow_ref_mut(cs);
+        let applied = state.measurement_status == status;
+        if applied {
+            state.measurement_status = next;
+        }
+        applied
+    });
+    if applied {
+        info!("Guided calibration: {:?}", step);
+        DataPoint::from(ResponseCode::GuidedCalibration(step)).send(channel);
+    }
+}
+
+/// Get the current timestamp of a measurement, in microseconds since it was started
+fn measurement_timestamp(start_time: u64) -> u64 {
+    Instant::now().as_micros().saturating_sub(start_time)
+}
+
+/// Weight measurement with the values that can be reported along with it
+struct WeightSample {
+    /// Weight in kg
+    weight: f32,
+    /// Raw reading the weight was computed from
+    raw: i32,
+    /// Timestamp in microseconds since the measurement was started
+    timestamp: u64,
+    /// Whether the filter had settled
+    settled: bool,
+    /// Rate of change of the weight in kg/s
+    rate: f32,
+}
+
+/// Send a weight measurement data point
+///
+/// When raw, settled or rate reporting is enabled, the weight is sent together with its raw
+/// reading, filter settled flag or rate instead. Otherwise, with a coalesce window set, it is
+/// added to the coalescer and only sent when the window completes. Returns the data point
+/// sent, if any.
+fn send_weight_measurement(
+    sample: &WeightSample,
+    state: &DeviceState,
+    coalescer: &mut SampleCoalescer,
+    channel: &'static DataPointChannel,
+) -> Option<DataPoint> {
+    let WeightSample {
+        raw,
+        timestamp,
+        settled,
+        ..
+    } = *sample;
+    let weight = state.reported_weight(sample.weight);
 
-    let response = ResponseCode::WeightMeasurement(weight, timestamp);
+    if logging::enabled(LogLevel::Debug) {
+        debug!(
+            "Sending measurement: Weight: {}kg, Timestamp: {:?}",
+            weight,
+            timestamp as f32 / 1000000.0
+        );
+    }
+
+    let unit = state.timestamp_unit;
+    let response = if state.report_raw {
+        ResponseCode::WeightWithRaw { weight, raw }
+    } else if state.report_settled {
+        ResponseCode::WeightWithSettled {
+            weight,
+            timestamp: unit.encode(timestamp),
+            settled,
+        }
+    } else if state.rate_window_ms > 0 {
+        ResponseCode::ForceAndRate {
+            force: weight,
+            rate: state.reported_weight(sample.rate),
+        }
+    } else if state.coalesce_window_ms > 0 {
+        // Reads of the data point still get the latest measurement on its own
+        let latest = DataPoint::weight_measurement(weight, unit.encode(timestamp));
+        critical_section::with(|cs| LATEST_MEASUREMENT.borrow(cs).set(Some(latest)));
+        let (timestamp, weights, count) =
+            coalescer.add(weight, timestamp, state.coalesce_window_ms)?;
+        let data_point = DataPoint::from(ResponseCode::CoalescedWeights {
+            timestamp: unit.encode(timestamp),
+            count,
+            weights,
+        });
+        data_point.send(channel);
+        return Some(data_point);
+    } else {
+        ResponseCode::WeightMeasurement(weight, unit.encode(timestamp))
+    };
     let data_point = DataPoint::from(response);
+    critical_section::with(|cs| LATEST_MEASUREMENT.borrow(cs).set(Some(data_point)));
+    data_point.send(channel);
+
+    Some(data_point)
+}
+
+/// Send the weight measurements pending in the coalescer, used when a measurement stops
+///
+/// Returns the data point sent, if any.
+fn flush_coalesced(
+    coalescer: &mut SampleCoalescer,
+    state: &DeviceState,
+    channel: &'static DataPointChannel,
+) -> Option<DataPoint> {
+    let (timestamp, weights, count) = coalescer.flush()?;
+    let data_point = DataPoint::from(ResponseCode::CoalescedWeights {
+        timestamp: state.timestamp_unit.encode(timestamp),
+        count,
+        weights,
+    });
     data_point.send(channel);
+    Some(data_point)
 }
 
 /// Stream Events until the connection closes.
@@ -286,6 +1148,7 @@ async fn gatt_events_task<P: PacketPool>(
     channel: &'static DataPointChannel,
 ) -> Result<(), Error> {
     let control_point = server.progressor.control_point;
+    let data_point = server.progressor.data_point;
     loop {
         match conn.next().await {
             GattConnectionEvent::Disconnected { reason } => {
@@ -298,18 +1161,54 @@ async fn gatt_events_task<P: PacketPool>(
                     if let GattEvent::Write(write_event) = &event {
                         if write_event.handle() == control_point.handle {
                             let cmd_data = write_event.data();
-                            let op_code = ControlOpCode::from(cmd_data[0]);
-                            info!("Control Point Received: {:?}", op_code);
+                            if let Some(&op_code) = cmd_data.first() {
+                                let op_code = ControlOpCode::from(op_code);
+                                info!("Control Point Received: {:?}", op_code);
+
+                                critical_section::with(|cs| {
+                                    let mut device_state = DEVICE_STATE.borrow_ref_mut(cs);
+                                    op_code.process(cmd_data, channel, &mut device_state);
+                                });
+                            } else {
+                                warn!("Empty control point write ignored");
+                            }
+                        }
 
-                            critical_section::with(|cs| {
-                                let mut device_state = DEVICE_STATE.borrow_ref_mut(cs);
-                                op_code.process(cmd_data, channel, &mut device_state);
+                        // Track whether the client subscribed to the data point
+                        if Some(write_event.handle()) == data_point.cccd_handle {
+                            let enabled = write_event
+                                .data()
+                                .first()
+                                .is_some_and(|&cccd| cccd & CCCD_NOTIFY != 0);
+                            info!("Data point notifications enabled: {}", enabled);
+                            let fault = critical_section::with(|cs| {
+                                let mut state = DEVICE_STATE.borrow_ref_mut(cs);
+                                state.notifications_enabled = enabled;
+                                state.load_cell_fault
                             });
+                            // Report a load cell fault detected at startup to every client
+                            if let (true, Some(fault)) = (enabled, fault) {
+                                DataPoint::from(ResponseCode::LoadCellFault(fault)).send(channel);
+                            }
+                        }
+                    }
+
+                    // Serve the latest measurement to clients reading instead of subscribing
+                    if let GattEvent::Read(read_event) = &event {
+                        if read_event.handle() == data_point.handle {
+                            let latest =
+                                critical_section::with(|cs| LATEST_MEASUREMENT.borrow(cs).get());
+                            if let Some(latest) = latest {
+                                if server.set(&data_point, &latest).is_err() {
+                                    warn!("Error updating data point value");
+                                }
+                            }
                         }
                     }
 
                     // Ensure reply is sent
                     if let Ok(reply) = event.accept() {
+                        let _pending = watchdog::ble_operation(BleOperation::GattReply);
                         reply.send().await;
                     } else {
                         warn!("Error sending response");
@@ -323,10 +1222,6 @@ async fn gatt_events_task<P: PacketPool>(
     }
 
     info!("BLE task finished");
-    critical_section::with(|cs| {
-        let mut device_state = DEVICE_STATE.borrow_ref_mut(cs);
-        device_state.stop_measurement();
-    });
 
     Ok(())
 }
@@ -341,9 +1236,21 @@ async fn data_processing_task<P: PacketPool>(
 
     loop {
         let data_point = channel.receive().await;
-        debug!("Sending Data Point: {:?}", data_point);
+        let (mode, notifications_enabled) = critical_section::with(|cs| {
+            let state = DEVICE_STATE.borrow_ref(cs);
+            (state.protocol_mode, state.notifications_enabled)
+        });
+        // Drain the channel while the client isn't subscribed, so it gets fresh data once it
+        // subscribes again
+        if !notifications_enabled || !data_point.allowed_in(mode) {
+            continue;
+        }
+        if logging::enabled(LogLevel::Debug) {
+            debug!("Sending Data Point: {:?}", data_point);
+        }
 
         // Send notification with the data packet
+        let _pending = watchdog::ble_operation(BleOperation::Notify);
         if let Err(e) = data_point_handle.notify(conn, &data_point).await {
             info!("Error sending Data Point: {:?}", defmt::Debug2Format(&e));
             break;
@@ -0,0 +1,454 @@
+/// Measurement metrics
+///
+/// Metrics computed on-device from the stream of weight measurements.
+use crate::load_cell::MAX_SAMPLE_RATE_HZ;
+
+/// Number of microseconds in a second
+const MICROS_PER_SECOND: f32 = 1_000_000.0;
+
+/// Running impulse (∫F dt) accumulated with the trapezoidal rule
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ImpulseAccumulator {
+    /// Accumulated impulse in kg·s
+    impulse: f32,
+    /// Previous sample (weight in kg, timestamp in microseconds)
+    previous: Option<(f32, u32)>,
+    /// Number of samples since the last report
+    samples_since_report: u32,
+}
+
+impl ImpulseAccumulator {
+    /// Create an empty accumulator
+    pub const fn new() -> Self {
+        Self {
+            impulse: 0.0,
+            previous: None,
+            samples_since_report: 0,
+        }
+    }
+
+    /// Clear the accumulated impulse, used when a new measurement starts
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Keep the accumulated impulse but don't integrate across the gap until the next sample,
+    /// used when a measurement is paused
+    pub fn pause(&mut self) {
+        self.previous = None;
+    }
+
+    /// Get the accumulated impulse in kg·s
+    pub fn impulse(&self) -> f32 {
+        self.impulse
+    }
+
+    /// Add a sample to the accumulator
+    ///
+    /// Returns the accumulated impulse every `report_interval` samples, or never if
+    /// `report_interval` is zero.
+    pub fn add_sample(&mut self, weight: f32, timestamp: u32, report_interval: u32) -> Option<f32> {
+        if let Some((previous_weight, previous_timestamp)) = self.previous {
+            let dt = timestamp.wrapping_sub(previous_timestamp) as f32 / MICROS_PER_SECOND;
+            self.impulse += (weight + previous_weight) * 0.5 * dt;
+        }
+        self.previous = Some((weight, timestamp));
+
+        if report_interval == 0 {
+            return None;
+        }
+
+        self.samples_since_report += 1;
+        if self.samples_since_report >= report_interval {
+            self.samples_since_report = 0;
+            Some(self.impulse)
+        } else {
+            None
+        }
+    }
+}
+
+/// Mean, min and max of the weight over a window of samples
+#[derive(Debug, Default, Clone, Copy)]
+pub struct EnvelopeAccumulator {
+    /// Sum of the weights in the current window, in kg
+    sum: f32,
+    /// Minimum weight in the current window, in kg
+    min: f32,
+    /// Maximum weight in the current window, in kg
+    max: f32,
+    /// Number of samples in the current window
+    samples: u32,
+}
+
+impl EnvelopeAccumulator {
+    /// Create an empty accumulator
+    pub const fn new() -> Self {
+        Self {
+            sum: 0.0,
+            min: f32::INFINITY,
+            max: f32::NEG_INFINITY,
+            samples: 0,
+        }
+    }
+
+    /// Discard the current window, used when a new measurement starts
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Add a sample to the current window
+    ///
+    /// Returns the `(mean, min, max)` of the window every `window` samples, or never if
+    /// `window` is zero.
+    pub fn add_sample(&mut self, weight: f32, window: u32) -> Option<(f32, f32, f32)> {
+        if window == 0 {
+            return None;
+        }
+
+        self.sum += weight;
+        self.min = self.min.min(weight);
+        self.max = self.max.max(weight);
+        self.samples += 1;
+        if self.samples < window {
+            return None;
+        }
+
+        let envelope = (self.sum / self.samples as f32, self.min, self.max);
+        self.reset();
+        Some(envelope)
+    }
+}
+
+/// Maximum window in milliseconds over which the rate of force development is computed
+pub const MAX_RATE_WINDOW_MS: u16 = 250;
+
+/// Number of samples kept to compute the rate of force development, enough for the longest
+/// window at the highest sample rate
+const RATE_HISTORY_SAMPLES: usize = MAX_SAMPLE_RATE_HZ * MAX_RATE_WINDOW_MS as usize / 1000 + 1;
+
+/// Rate of change of the force, smoothed over a time window
+///
+/// The rate is the slope between the current sample and the oldest one within the window,
+/// which averages out the sample to sample noise that a plain difference would amplify.
+#[derive(Debug, Clone, Copy)]
+pub struct RateEstimator {
+    /// Recent samples (weight in kg, timestamp in microseconds), oldest first once full
+    history: [(f32, u32); RATE_HISTORY_SAMPLES],
+    /// Index of the next sample in the history
+    next: usize,
+    /// Number of samples in the history
+    len: usize,
+}
+
+impl Default for RateEstimator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RateEstimator {
+    /// Create an estimator with no history
+    pub const fn new() -> Self {
+        Self {
+            history: [(0.0, 0); RATE_HISTORY_SAMPLES],
+            next: 0,
+            len: 0,
+        }
+    }
+
+    /// Forget the history, used when a new measurement starts
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Add a sample, returning the rate in kg/s over the last `window_ms` milliseconds
+    ///
+    /// The rate is zero until a second sample is available.
+    pub fn add_sample(&mut self, weight: f32, timestamp: u32, window_ms: u16) -> f32 {
+        let window_us = window_ms.min(MAX_RATE_WINDOW_MS) as u32 * 1000;
+        self.history[self.next] = (weight, timestamp);
+        self.next = (self.next + 1) % RATE_HISTORY_SAMPLES;
+        self.len = (self.len + 1).min(RATE_HISTORY_SAMPLES);
+
+        // Walk back to the oldest sample within the window
+        let mut oldest = None;
+        for age in 1..self.len {
+            let index = (self.next + RATE_HISTORY_SAMPLES - 1 - age) % RATE_HISTORY_SAMPLES;
+            let (previous_weight, previous_timestamp) = self.history[index];
+            if timestamp.wrapping_sub(previous_timestamp) > window_us {
+                break;
+            }
+            oldest = Some((previous_weight, previous_timestamp));
+        }
+
+        match oldest {
+            Some((previous_weight, previous_timestamp)) if previous_timestamp != timestamp => {
+                let dt = timestamp.wrapping_sub(previous_timestamp) as f32 / MICROS_PER_SECOND;
+                (weight - previous_weight) / dt
+            }
+            _ => 0.0,
+        }
+    }
+}
+
+/// Window of the rate of force development, in milliseconds
+///
+/// Four samples at 80Hz, sixteen at 320Hz: short enough to catch the steepest part of an onset,
+/// long enough not to be dominated by the noise between two samples.
+pub const RFD_WINDOW_MS: u16 = 50;
+
+/// Peak rate of force development (RFD) of a measurement, in kg/s
+///
+/// The RFD is the rate of change of the force over [`RFD_WINDOW_MS`], see [`RateEstimator`].
+/// Only rising force counts, so the peak is zero until the force increases.
+#[derive(Debug, Clone, Copy)]
+pub struct PeakRfd {
+    /// Rate of change over the RFD window
+    rate: RateEstimator,
+    /// Highest RFD seen, in kg/s
+    peak: f32,
+}
+
+impl Default for PeakRfd {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PeakRfd {
+    /// Create a tracker with no samples
+    pub const fn new() -> Self {
+        Self {
+            rate: RateEstimator::new(),
+            peak: 0.0,
+        }
+    }
+
+    /// Forget the peak, used when a new measurement starts
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Add a sample (weight in kg, timestamp in microseconds)
+    pub fn add_sample(&mut self, weight: f32, timestamp: u32) {
+        let rfd = self.rate.add_sample(weight, timestamp, RFD_WINDOW_MS);
+        self.peak = self.peak.max(rfd);
+    }
+
+    /// Get the peak RFD in kg/s
+    pub fn peak(&self) -> f32 {
+        self.peak
+    }
+}
+
+/// Rep counter configuration
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RepThresholds {
+    /// Force in kg above which a rep starts
+    pub on: f32,
+    /// Force in kg below which a rep ends, lower than `on` to avoid counting jitter
+    pub off: f32,
+    /// Minimum duration of a rep in milliseconds, shorter ones are ignored
+    pub min_duration_ms: u16,
+}
+
+/// Counts reps as force crossings above a threshold lasting a minimum duration
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RepCounter {
+    /// Number of reps counted
+    count: u16,
+    /// Timestamp in microseconds when the current rep started
+    rep_start: Option<u32>,
+}
+
+impl RepCounter {
+    /// Create a counter with no reps
+    pub const fn new() -> Self {
+        Self {
+            count: 0,
+            rep_start: None,
+        }
+    }
+
+    /// Clear the count, used when a new measurement starts
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Add a sample to the counter
+    ///
+    /// Returns the new count when a rep has been completed.
+    pub fn add_sample(
+        &mut self,
+        weight: f32,
+        timestamp: u32,
+        thresholds: &RepThresholds,
+    ) -> Option<u16> {
+        match self.rep_start {
+            None if weight >= thresholds.on => {
+                self.rep_start = Some(timestamp);
+                None
+            }
+            Some(start) if weight < thresholds.off => {
+                self.rep_start = None;
+                let duration_ms = timestamp.wrapping_sub(start) / 1000;
+                if duration_ms >= thresholds.min_duration_ms as u32 {
+                    self.count = self.count.saturating_add(1);
+                    Some(self.count)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+/// CRC-32 of the data sent during a measurement
+///
+/// Uses the standard CRC-32 (IEEE 802.3): reflected polynomial `0xEDB88320`, initial value
+/// `0xFFFFFFFF` and final XOR `0xFFFFFFFF`, the same as zlib's `crc32`. It is computed over
+/// the payload bytes (without response code and length) of every weight data point sent,
+/// in order.
+#[derive(Debug, Clone, Copy)]
+pub struct SessionChecksum {
+    /// Running CRC register
+    crc: u32,
+}
+
+impl Default for SessionChecksum {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SessionChecksum {
+    /// Reflected CRC-32 polynomial
+    const POLYNOMIAL: u32 = 0xEDB8_8320;
+
+    /// Create a checksum with no data
+    pub const fn new() -> Self {
+        Self { crc: 0xFFFF_FFFF }
+    }
+
+    /// Clear the checksum, used when a new measurement starts
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Add data to the checksum
+    pub fn update(&mut self, data: &[u8]) {
+        for byte in data {
+            self.crc ^= *byte as u32;
+            for _ in 0..8 {
+                let mask = (self.crc & 1).wrapping_neg();
+                self.crc = (self.crc >> 1) ^ (Self::POLYNOMIAL & mask);
+            }
+        }
+    }
+
+    /// Get the checksum of the data added so far
+    pub fn value(&self) -> u32 {
+        !self.crc
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sample period at the highest sample rate, in microseconds
+    const PERIOD_US: u32 = 1_000_000 / MAX_SAMPLE_RATE_HZ as u32;
+
+    #[test]
+    fn rate_window_covers_the_highest_sample_rate() {
+        let mut estimator = RateEstimator::new();
+        let mut rate = 0.0;
+        // A 10kg step 200ms in, the rate 100ms later spans back to before the step
+        for i in 0..=96 {
+            let weight = if i < 64 { 0.0 } else { 10.0 };
+            rate = estimator.add_sample(weight, i * PERIOD_US, MAX_RATE_WINDOW_MS);
+        }
+
+        assert!((rate - 40.0).abs() < 1e-3, "rate: {rate}");
+    }
+
+    #[test]
+    fn rate_is_zero_until_a_second_sample() {
+        let mut estimator = RateEstimator::new();
+
+        assert_eq!(estimator.add_sample(10.0, 0, 100), 0.0);
+        assert_eq!(estimator.add_sample(12.0, 12_500, 100), 160.0);
+    }
+
+    #[test]
+    fn rate_spans_the_window_only() {
+        let mut estimator = RateEstimator::new();
+        // Flat, then rising at 20kg/s
+        for i in 0..40 {
+            let t = i * 12_500;
+            let weight = if i < 20 {
+                0.0
+            } else {
+                (t - 250_000) as f32 * 20e-6
+            };
+            estimator.add_sample(weight, t, 50);
+        }
+
+        let rate = estimator.add_sample(5.0, 500_000, 50);
+        assert!((rate - 20.0).abs() < 1e-3, "rate: {rate}");
+    }
+
+    #[test]
+    fn rate_window_is_clamped() {
+        let mut estimator = RateEstimator::new();
+        estimator.add_sample(0.0, 0, u16::MAX);
+        estimator.add_sample(5.0, 200_000, u16::MAX);
+
+        // The first sample is past the longest window
+        let rate = estimator.add_sample(10.0, 400_000, u16::MAX);
+        assert!((rate - 25.0).abs() < 1e-3, "rate: {rate}");
+    }
+
+    /// Timestamp `us` microseconds after the clock wraps, negative for before it
+    fn around_wrap(us: i32) -> u32 {
+        0u32.wrapping_add_signed(us)
+    }
+
+    #[test]
+    fn impulse_integrates_across_the_clock_wrap() {
+        let mut impulse = ImpulseAccumulator::new();
+
+        impulse.add_sample(10.0, around_wrap(-500_000), 0);
+        impulse.add_sample(10.0, around_wrap(500_000), 0);
+
+        assert!((impulse.impulse() - 10.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn rate_is_estimated_across_the_clock_wrap() {
+        let mut estimator = RateEstimator::new();
+
+        estimator.add_sample(0.0, around_wrap(-25_000), RFD_WINDOW_MS);
+        let rate = estimator.add_sample(5.0, around_wrap(25_000), RFD_WINDOW_MS);
+
+        assert!((rate - 100.0).abs() < 1e-3, "rate: {rate}");
+    }
+
+    #[test]
+    fn reps_are_timed_across_the_clock_wrap() {
+        let thresholds = RepThresholds {
+            on: 10.0,
+            off: 5.0,
+            min_duration_ms: 500,
+        };
+        let mut reps = RepCounter::new();
+
+        reps.add_sample(20.0, around_wrap(-300_000), &thresholds);
+        assert_eq!(
+            reps.add_sample(0.0, around_wrap(300_000), &thresholds),
+            Some(1)
+        );
+    }
+}
@@ -0,0 +1,626 @@
+/// NAU7802 driver
+///
+/// A driver for the NAU7802 24-bit ADC over I2C, an alternative to the HX711 sampling at up to
+/// 320Hz, which resolves the rate of force development much better. It is used instead of the
+/// HX711 with the `nau7802` feature, see `load_cell::BoardLoadCell`.
+///
+/// The NAU7802 signals each conversion on its DRDY pin (active high), so readings are awaited
+/// on the pin instead of polling the bus. The bridge excitation (AVDD) comes from the internal
+/// LDO, whose voltage must stay at least 0.3V below the supply. The calibration is stored in
+/// the same flash slot as the HX711 one, so recalibrate after switching chips.
+use defmt::{debug, error, info, warn, Format};
+use embassy_futures::yield_now;
+use embassy_time::{with_deadline, Duration, Instant, Timer};
+use esp_hal::{gpio::Input, i2c::master::I2c, Async};
+
+use crate::{
+    error::Error,
+    filters::{
+        BiquadCoefficients,
+        FilterMode,
+        ReadingConditioner,
+        BUTTERWORTH_20HZ_320HZ,
+        BUTTERWORTH_20HZ_80HZ,
+    },
+    hx711::{
+        convert_raw,
+        fit_calibration,
+        Calibration,
+        LoadCellFault,
+        RunningMean,
+        TareCapture,
+        CAPTURE_STABILITY_TOLERANCE_KG,
+        DEFAULT_CALIBRATION_SAMPLES,
+        DEFAULT_TARING_SAMPLES,
+        QUICK_TARING_SAMPLES,
+        READ_TIMEOUT,
+        SELF_TEST_SAMPLES,
+        SELF_TEST_TIMEOUT_MS,
+        ZERO_CHECK_SAMPLES,
+        ZERO_CHECK_TOLERANCE_KG,
+    },
+    load_cell::{min_averaging_window, LoadCell},
+};
+
+/// I2C address of the NAU7802
+const NAU7802_ADDRESS: u8 = 0x2A;
+
+/// Power-up control register
+const REG_PU_CTRL: u8 = 0x00;
+/// Control register 1: gain, LDO voltage and DRDY polarity
+const REG_CTRL1: u8 = 0x01;
+/// Control register 2: conversion rate and AFE calibration
+const REG_CTRL2: u8 = 0x02;
+/// First (most significant) byte of the conversion result, followed by the other two
+const REG_ADCO_B2: u8 = 0x12;
+/// ADC control register
+const REG_ADC: u8 = 0x15;
+/// PGA power control register
+const REG_PGA_PWR: u8 = 0x1C;
+
+/// `PU_CTRL`: register reset
+const PU_CTRL_RR: u8 = 1 << 0;
+/// `PU_CTRL`: power up the digital circuit
+const PU_CTRL_PUD: u8 = 1 << 1;
+/// `PU_CTRL`: power up the analog circuit
+const PU_CTRL_PUA: u8 = 1 << 2;
+/// `PU_CTRL`: power up ready
+const PU_CTRL_PUR: u8 = 1 << 3;
+/// `PU_CTRL`: start conversions
+const PU_CTRL_CS: u8 = 1 << 4;
+/// `PU_CTRL`: supply AVDD from the internal LDO
+const PU_CTRL_AVDDS: u8 = 1 << 7;
+/// `CTRL2`: start the AFE calibration, cleared once done
+const CTRL2_CALS: u8 = 1 << 2;
+/// `CTRL2`: the AFE calibration failed
+const CTRL2_CAL_ERR: u8 = 1 << 3;
+/// `ADC`: chopper clock disabled, as the datasheet recommends
+const ADC_CHOPPER_OFF: u8 = 0x30;
+/// `PGA_PWR`: enable the decoupling capacitor on the unused channel 2
+const PGA_PWR_CAP_EN: u8 = 1 << 7;
+
+/// The time to wait for the analog circuit to power up
+const POWER_UP_TIMEOUT: Duration = Duration::from_millis(10);
+/// The time to wait for the AFE calibration, which takes a few conversions at 10Hz
+const AFE_CALIBRATION_TIMEOUT: Duration = Duration::from_secs(1);
+/// Interval between polls of a register bit
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// The absolute minimum reading
+const NAU7802_MINIMUM: i32 = -(1 << 23);
+/// The absolute maximum reading
+const NAU7802_MAXIMUM: i32 = (1 << 23) - 1;
+
+/// The default calibration values, roughly right for a 150kg 2mV/V load cell at 128 gain and
+/// 3.0V excitation
+const DEFAULT_CALIBRATION: Calibration = Calibration::new(0, 0.0000385);
+
+/// Conversion rate
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Format)]
+pub enum SampleRate {
+    /// 10 samples per second
+    Sps10 = 0b000,
+    /// 20 samples per second
+    Sps20 = 0b001,
+    /// 40 samples per second
+    Sps40 = 0b010,
+    /// 80 samples per second
+    Sps80 = 0b011,
+    /// 320 samples per second
+    Sps320 = 0b111,
+}
+
+impl SampleRate {
+    /// Butterworth low-pass coefficients for this rate
+    ///
+    /// The cutoff is 20Hz at 80Hz and above, a quarter of the rate below.
+    fn low_pass(self) -> BiquadCoefficients {
+        match self {
+            SampleRate::Sps320 => BUTTERWORTH_20HZ_320HZ,
+            _ => BUTTERWORTH_20HZ_80HZ,
+        }
+    }
+}
+
+/// PGA gain
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Format)]
+pub enum Gain {
+    /// Gain of 1
+    X1 = 0,
+    /// Gain of 2
+    X2 = 1,
+    /// Gain of 4
+    X4 = 2,
+    /// Gain of 8
+    X8 = 3,
+    /// Gain of 16
+    X16 = 4,
+    /// Gain of 32
+    X32 = 5,
+    /// Gain of 64
+    X64 = 6,
+    /// Gain of 128
+    X128 = 7,
+}
+
+/// Voltage of the internal LDO supplying AVDD
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Format)]
+pub enum Ldo {
+    /// 2.4V
+    V2_4 = 0b111,
+    /// 2.7V
+    V2_7 = 0b110,
+    /// 3.0V
+    V3_0 = 0b101,
+    /// 3.3V
+    V3_3 = 0b100,
+    /// 3.6V
+    V3_6 = 0b011,
+    /// 3.9V
+    V3_9 = 0b010,
+    /// 4.2V
+    V4_2 = 0b001,
+    /// 4.5V
+    V4_5 = 0b000,
+}
+
+/// Configuration of the NAU7802
+#[derive(Clone, Copy, Debug, Format)]
+pub struct Nau7802Config {
+    /// Conversion rate
+    pub sample_rate: SampleRate,
+    /// PGA gain
+    pub gain: Gain,
+    /// Bridge excitation voltage
+    pub ldo: Ldo,
+}
+
+impl Nau7802Config {
+    /// 320Hz at 128 gain with 3.0V excitation
+    ///
+    /// The LDO needs some headroom below the supply, 3.0V is the highest setting that stays
+    /// regulated on the board's 3.3V rail.
+    pub const DEFAULT: Self = Self {
+        sample_rate: SampleRate::Sps320,
+        gain: Gain::X128,
+        ldo: Ldo::V3_0,
+    };
+}
+
+impl Default for Nau7802Config {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// NAU7802 driver
+///
+/// Like the `Hx711`, it is owned by the measurement task and never shared.
+pub struct Nau7802<'d> {
+    /// I2C bus
+    i2c: I2c<'d, Async>,
+    /// Data ready pin
+    drdy: Input<'d>,
+    /// Configuration applied on power-up
+    config: Nau7802Config,
+    /// Calibration
+    calibration: Calibration,
+    /// Filtering and range checks of calibrated readings
+    conditioner: ReadingConditioner,
+    /// Number of samples averaged per calibrated reading
+    averaging_samples: usize,
+    /// Time window calibrated readings are averaged over instead of `averaging_samples`
+    averaging_window: Option<Duration>,
+    /// Whether the last calibrated reading included a raw reading at the ADC limits
+    saturated: bool,
+}
+
+impl<'d> Nau7802<'d> {
+    /// Create a new NAU7802 driver
+    ///
+    /// The chip is powered up and configured by [`LoadCell::warmup`].
+    pub fn new(i2c: I2c<'d, Async>, drdy: Input<'d>, config: Nau7802Config) -> Self {
+        info!("NAU7802 initialized: {:?}", config);
+        Self {
+            i2c,
+            drdy,
+            config,
+            calibration: Self::stored_calibration().unwrap_or(DEFAULT_CALIBRATION),
+            conditioner: ReadingConditioner::new(config.sample_rate.low_pass()),
+            averaging_samples: 1,
+            averaging_window: None,
+            saturated: false,
+        }
+    }
+
+    /// Writes a register
+    async fn write_register(&mut self, register: u8, value: u8) -> Result<(), Error> {
+        self.i2c
+            .write_async(NAU7802_ADDRESS, &[register, value])
+            .await
+            .map_err(|e| {
+                error!("NAU7802 write error: {:?}", defmt::Debug2Format(&e));
+                Error::Bus
+            })
+    }
+
+    /// Reads consecutive registers starting at `register`
+    async fn read_registers(&mut self, register: u8, buffer: &mut [u8]) -> Result<(), Error> {
+        self.i2c
+            .write_read_async(NAU7802_ADDRESS, &[register], buffer)
+            .await
+            .map_err(|e| {
+                error!("NAU7802 read error: {:?}", defmt::Debug2Format(&e));
+                Error::Bus
+            })
+    }
+
+    /// Reads a register
+    async fn read_register(&mut self, register: u8) -> Result<u8, Error> {
+        let mut value = [0u8];
+        self.read_registers(register, &mut value).await?;
+        Ok(value[0])
+    }
+
+    /// Polls `register` until `done` holds for its value, failing after `timeout`
+    async fn poll_register(
+        &mut self,
+        register: u8,
+        timeout: Duration,
+        done: impl Fn(u8) -> bool,
+    ) -> Result<u8, Error> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let value = self.read_register(register).await?;
+            if done(value) {
+                return Ok(value);
+            }
+            if Instant::now() >= deadline {
+                return Err(Error::Timeout);
+            }
+            Timer::after(POLL_INTERVAL).await;
+        }
+    }
+
+    /// Resets, powers up and configures the chip, then calibrates the analog front end
+    async fn power_up(&mut self) -> Result<(), Error> {
+        self.write_register(REG_PU_CTRL, PU_CTRL_RR).await?;
+        Timer::after(POLL_INTERVAL).await;
+        self.write_register(REG_PU_CTRL, PU_CTRL_PUD | PU_CTRL_PUA)
+            .await?;
+        self.poll_register(REG_PU_CTRL, POWER_UP_TIMEOUT, |value| {
+            value & PU_CTRL_PUR != 0
+        })
+        .await?;
+
+        // DRDY active high, as expected by `wait_for_ready`
+        let config = self.config;
+        self.write_register(REG_CTRL1, (config.ldo as u8) << 3 | config.gain as u8)
+            .await?;
+        self.write_register(REG_CTRL2, (config.sample_rate as u8) << 4)
+            .await?;
+        self.write_register(REG_ADC, ADC_CHOPPER_OFF).await?;
+        let pga_pwr = self.read_register(REG_PGA_PWR).await?;
+        self.write_register(REG_PGA_PWR, pga_pwr | PGA_PWR_CAP_EN)
+            .await?;
+        self.write_register(
+            REG_PU_CTRL,
+            PU_CTRL_PUD | PU_CTRL_PUA | PU_CTRL_CS | PU_CTRL_AVDDS,
+        )
+        .await?;
+
+        // Calibrate the offset and gain of the analog front end with the new configuration
+        let ctrl2 = self.read_register(REG_CTRL2).await?;
+        self.write_register(REG_CTRL2, ctrl2 | CTRL2_CALS).await?;
+        let ctrl2 = self
+            .poll_register(REG_CTRL2, AFE_CALIBRATION_TIMEOUT, |value| {
+                value & CTRL2_CALS == 0
+            })
+            .await?;
+        if ctrl2 & CTRL2_CAL_ERR != 0 {
+            warn!("NAU7802 AFE calibration failed");
+        }
+
+        debug!("NAU7802 powered up");
+        Ok(())
+    }
+
+    /// Waits until a conversion is ready to be read
+    async fn wait_for_ready(&mut self) {
+        self.drdy.wait_for_high().await;
+    }
+
+    /// Reads the last conversion, which clears DRDY
+    async fn read_conversion(&mut self) -> Result<i32, Error> {
+        let mut bytes = [0u8; 3];
+        self.read_registers(REG_ADCO_B2, &mut bytes).await?;
+        Ok(convert_raw(u32::from_be_bytes([
+            0, bytes[0], bytes[1], bytes[2],
+        ])))
+    }
+
+    /// Reads a raw value, failing with [`Error::Timeout`] if none is ready within `timeout`
+    pub async fn read_with_timeout(&mut self, timeout: Duration) -> Result<i32, Error> {
+        self.read_with_deadline(Instant::now() + timeout).await
+    }
+
+    /// Reads a raw value, failing with [`Error::Timeout`] if none is ready by `deadline`
+    async fn read_with_deadline(&mut self, deadline: Instant) -> Result<i32, Error> {
+        with_deadline(deadline, self.wait_for_ready())
+            .await
+            .map_err(|_| Error::Timeout)?;
+        let raw = self.read_conversion().await?;
+        self.saturated |= raw == NAU7802_MINIMUM || raw == NAU7802_MAXIMUM;
+        Ok(raw)
+    }
+
+    /// Takes multiple samples and returns the average, see `Hx711::take_samples`
+    async fn take_samples(&mut self, num_samples: usize) -> Result<f32, Error> {
+        let mut mean = RunningMean::new();
+
+        for _ in 0..num_samples {
+            let raw = self.read_with_timeout(READ_TIMEOUT).await?;
+            mean.add(raw as f32);
+            yield_now().await;
+        }
+
+        Ok(mean.mean())
+    }
+
+    /// Takes as many samples as arrive within `window` and returns the average, see
+    /// `Hx711::take_samples_windowed`
+    async fn take_samples_windowed(&mut self, window: Duration) -> Result<f32, Error> {
+        let deadline = Instant::now() + window;
+        let mut mean = RunningMean::new();
+
+        loop {
+            match self.read_with_deadline(deadline).await {
+                Ok(raw) => mean.add(raw as f32),
+                Err(Error::Timeout) => break,
+                Err(e) => return Err(e),
+            }
+            yield_now().await;
+        }
+
+        if mean.count() == 0 {
+            return Err(Error::Timeout);
+        }
+
+        Ok(mean.mean())
+    }
+
+    /// Tares the sensor with the average of `num_samples` readings
+    async fn tare_with_samples(&mut self, num_samples: usize) -> Result<(), Error> {
+        debug!("Taring the scale");
+        if !self.calibration.is_valid() {
+            info!("Invalid calibration values, skipping tare");
+            return Ok(());
+        }
+
+        let mut capture = TareCapture::new(num_samples);
+        let zero_raw = loop {
+            let raw = self.read_with_timeout(READ_TIMEOUT).await?;
+            if let Some(zero_raw) = capture.add(raw) {
+                break zero_raw;
+            }
+            yield_now().await;
+        };
+        self.calibration = Calibration::new(zero_raw, self.calibration.scale());
+        self.conditioner.reset();
+        debug!("Zero reference set to: {}", self.calibration.zero_raw());
+        Ok(())
+    }
+}
+
+impl LoadCell for Nau7802<'_> {
+    fn stored_calibration() -> Result<Calibration, Error> {
+        match Calibration::read_from_flash() {
+            Ok(calibration) => Ok(calibration),
+            Err(Error::CalibrationInvalid) => {
+                info!("Using default calibration values");
+                Ok(DEFAULT_CALIBRATION)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn current_calibration(&self) -> Calibration {
+        self.calibration
+    }
+
+    fn update_calibration(&mut self, zero_raw: i32, scale: f32) -> Result<(), Error> {
+        let calibration = Calibration::new(zero_raw, scale);
+        if !calibration.is_valid() {
+            error!("Invalid calibration values: {:?}", calibration);
+            return Err(Error::CalibrationInvalid);
+        }
+
+        calibration.write_to_flash()?;
+        self.calibration = calibration;
+        Ok(())
+    }
+
+    fn default_calibration(&mut self) -> Result<(), Error> {
+        debug!("Restoring default calibration");
+        DEFAULT_CALIBRATION.write_to_flash()?;
+        self.calibration = DEFAULT_CALIBRATION;
+        Ok(())
+    }
+
+    fn apply_calibration_points(&mut self, points: &[(f32, f32)]) -> Result<(), Error> {
+        debug!("Calibration points: {:?}", points);
+        let calibration = fit_calibration(points, self.calibration.zero_raw())?;
+        self.update_calibration(calibration.zero_raw(), calibration.scale())
+    }
+
+    fn set_filter_mode(&mut self, filter_mode: FilterMode) {
+        self.conditioner.set_filter_mode(filter_mode);
+    }
+
+    fn set_step_reset_threshold(&mut self, threshold: f32) {
+        self.conditioner.set_step_reset_threshold(threshold);
+    }
+
+    fn filter_settled(&self) -> bool {
+        self.conditioner.filter_settled()
+    }
+
+    fn sample_rate_hz(&self) -> f32 {
+        match self.config.sample_rate {
+            SampleRate::Sps10 => 10.0,
+            SampleRate::Sps20 => 20.0,
+            SampleRate::Sps40 => 40.0,
+            SampleRate::Sps80 => 80.0,
+            SampleRate::Sps320 => 320.0,
+        }
+    }
+
+    fn set_averaging_samples(&mut self, samples: usize) {
+        self.averaging_samples = samples.max(1);
+    }
+
+    fn set_averaging_window(&mut self, window: Option<Duration>) {
+        let min_window = min_averaging_window(self.sample_rate_hz());
+        self.averaging_window = window.map(|window| window.max(min_window));
+    }
+
+    fn set_plausible_range(&mut self, min: f32, max: f32) {
+        self.conditioner.set_plausible_range(min, max);
+    }
+
+    fn out_of_range(&self) -> Option<f32> {
+        self.conditioner.out_of_range()
+    }
+
+    fn non_finite(&self) -> bool {
+        self.conditioner.non_finite()
+    }
+
+    fn saturated(&self) -> bool {
+        self.saturated
+    }
+
+    /// Powers the chip up, then discards readings for `duration` while it settles
+    ///
+    /// If the chip doesn't respond, the error is logged and the self test reports it.
+    async fn warmup(&mut self, duration: Duration) {
+        if let Err(e) = self.power_up().await {
+            error!("NAU7802 power up failed: {}", e);
+            return;
+        }
+
+        debug!("Warming up the load cell for {}ms", duration.as_millis());
+        let deadline = Instant::now() + duration;
+        while with_deadline(deadline, self.wait_for_ready()).await.is_ok() {
+            let _ = self.read_conversion().await;
+        }
+        self.conditioner.reset();
+    }
+
+    async fn self_test(&mut self) -> Result<(), LoadCellFault> {
+        let mut min = i32::MAX;
+        let mut max = i32::MIN;
+
+        for _ in 0..SELF_TEST_SAMPLES {
+            let timeout = Duration::from_millis(SELF_TEST_TIMEOUT_MS);
+            let raw = self
+                .read_with_timeout(timeout)
+                .await
+                .map_err(|_| LoadCellFault::NotResponding)?;
+            if raw == NAU7802_MINIMUM || raw == NAU7802_MAXIMUM {
+                return Err(LoadCellFault::Saturated);
+            }
+            min = min.min(raw);
+            max = max.max(raw);
+        }
+
+        if min == max {
+            return Err(LoadCellFault::Stuck);
+        }
+
+        Ok(())
+    }
+
+    async fn check_zero_offset(&mut self, auto_zero: bool) -> Result<f32, Error> {
+        if !self.calibration.is_valid() {
+            return Ok(0.0);
+        }
+
+        let average = self.take_samples(ZERO_CHECK_SAMPLES).await?;
+        let offset = self.calibration.to_kg(average);
+        if offset.abs() > ZERO_CHECK_TOLERANCE_KG {
+            warn!("Zero offset of {}kg from the stored calibration", offset);
+            if auto_zero {
+                self.calibration = Calibration::new(average as i32, self.calibration.scale());
+                self.conditioner.reset();
+                info!("Zero reference set to: {}", self.calibration.zero_raw());
+            }
+        }
+
+        Ok(offset)
+    }
+
+    async fn tare(&mut self) -> Result<(), Error> {
+        self.tare_with_samples(DEFAULT_TARING_SAMPLES).await
+    }
+
+    async fn quick_tare(&mut self) -> Result<(), Error> {
+        self.tare_with_samples(QUICK_TARING_SAMPLES).await
+    }
+
+    fn soft_tare(&mut self, raw: i32) {
+        if !self.calibration.is_valid() {
+            info!("Invalid calibration values, skipping tare");
+            return;
+        }
+
+        self.calibration = Calibration::new(raw, self.calibration.scale());
+        self.conditioner.reset();
+        debug!("Zero reference set to: {}", self.calibration.zero_raw());
+    }
+
+    async fn read_calibrated_with_raw(&mut self) -> Result<(f32, i32), Error> {
+        self.saturated = false;
+        let raw = if let Some(window) = self.averaging_window {
+            self.take_samples_windowed(window).await? as i32
+        } else if self.averaging_samples > 1 {
+            self.take_samples(self.averaging_samples).await? as i32
+        } else {
+            self.read_with_timeout(READ_TIMEOUT).await?
+        };
+        let weight = self
+            .conditioner
+            .condition(self.calibration.to_kg(raw as f32));
+        Ok((weight, raw))
+    }
+
+    async fn perform_calibration(&mut self, _target_weight: f32) -> Result<f32, Error> {
+        let average_value = self.take_samples(DEFAULT_CALIBRATION_SAMPLES).await?;
+        debug!("Calibration point collected: {}", average_value);
+        Ok(average_value)
+    }
+
+    /// Collect a calibration point, failing if the readings are not stable, see
+    /// `Hx711::capture_stable`
+    async fn capture_stable(&mut self) -> Result<f32, Error> {
+        let mut mean = RunningMean::new();
+        for _ in 0..DEFAULT_CALIBRATION_SAMPLES {
+            let raw = self.read_with_timeout(READ_TIMEOUT).await?;
+            mean.add(raw as f32);
+            yield_now().await;
+        }
+
+        let scale = self.calibration.scale();
+        let variance_kg = mean.variance() * scale * scale;
+        if variance_kg > CAPTURE_STABILITY_TOLERANCE_KG * CAPTURE_STABILITY_TOLERANCE_KG {
+            warn!("Unstable capture: {}kg² variance", variance_kg);
+            return Err(Error::Unstable);
+        }
+
+        debug!("Stable calibration point collected: {}", mean.mean());
+        Ok(mean.mean())
+    }
+}
@@ -0,0 +1,43 @@
+/// Power
+///
+/// `Shutdown` puts the ESP32-C3 into deep sleep, the lowest power mode short of removing the
+/// battery. Only the RTC domain stays powered, so the load cell, BLE and all RAM state are
+/// lost. The device wakes up when GPIO1 is pulled low, e.g. by a push button between GPIO1 and
+/// GND, and then boots as after a reset. GPIO1 is one of the RTC capable pins (GPIO0 to GPIO5)
+/// that can wake the ESP32-C3 from deep sleep, and not a strapping pin.
+use esp_hal::{
+    gpio::RtcPinWithResistors,
+    peripherals::{GPIO1, LPWR},
+    rtc_cntl::{
+        sleep::{RtcioWakeupSource, WakeupLevel},
+        Rtc,
+    },
+};
+
+/// Deep sleep control
+pub struct Power {
+    /// RTC controller entering deep sleep
+    rtc: Rtc<'static>,
+    /// Pin waking the device up
+    wake_pin: GPIO1<'static>,
+}
+
+impl Power {
+    /// Set up deep sleep, waking up on `wake_pin`
+    pub fn new(lpwr: LPWR<'static>, wake_pin: GPIO1<'static>) -> Self {
+        Self {
+            rtc: Rtc::new(lpwr),
+            wake_pin,
+        }
+    }
+
+    /// Enter deep sleep until the wake pin is pulled low
+    ///
+    /// Never returns, waking up resets the device.
+    pub fn deep_sleep(mut self) -> ! {
+        let wake_pins: &mut [(&mut dyn RtcPinWithResistors, WakeupLevel)] =
+            &mut [(&mut self.wake_pin, WakeupLevel::Low)];
+        let wakeup_source = RtcioWakeupSource::new(wake_pins);
+        self.rtc.sleep_deep(&[&wakeup_source])
+    }
+}
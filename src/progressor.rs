@@ -3,25 +3,156 @@
 /// See [Tindeq API documentation] for more information
 ///
 /// [Tindeq API documentation]: https://tindeq.com/progressor_api/
+///
+/// # Byte order
+///
+/// Like on the Progressor, command arguments (control point writes) are big endian, while
+/// response values (data point notifications) are little endian. Commands are decoded with
+/// `command_f32` and `command_u16`.
+#[cfg(target_arch = "riscv32")]
 use core::cell::UnsafeCell;
 
-use defmt::{debug, error, info, trace, Format};
+use defmt::{debug, error, info, trace, warn, Format};
 use embassy_sync::{blocking_mutex::raw::NoopRawMutex, channel::Channel};
-use esp_hal::time;
+use embassy_time::Instant;
+#[cfg(target_arch = "riscv32")]
 use trouble_host::types::gatt_traits::{AsGatt, FromGatt, FromGattError};
 
-use crate::hx711::Hx711;
+use crate::{
+    device_id::DEVICE_ID_SIZE,
+    device_name::DeviceName,
+    diagnostics::ResetReason,
+    error::Error,
+    filters::{FilterMode, ReportOnChange, FILTER_MODE_EMA, MAX_COALESCED_SAMPLES},
+    hx711::{
+        linearity_error_pct,
+        Calibration,
+        LoadCellFault,
+        LINEARITY_TOLERANCE_PCT,
+        MAX_CALIBRATION_POINTS,
+    },
+    load_cell,
+    logging::{self, LogLevel},
+    metrics::{RepThresholds, MAX_RATE_WINDOW_MS},
+    provisioning::{ConfigImport, CONFIG_CHUNK_SIZE},
+};
 
 /// Size of the channel used to send data points
 const DATA_POINT_COMMAND_CHANNEL_SIZE: usize = 80;
 /// Channel used to send data points
 pub type DataPointChannel = Channel<NoopRawMutex, DataPoint, DATA_POINT_COMMAND_CHANNEL_SIZE>;
 
+/// Maximum number of points collected for a linearity check
+pub const MAX_LINEARITY_POINTS: usize = 6;
+
 /// Maximum size of the data payload in bytes for any data point
-pub const MAX_PAYLOAD_SIZE: usize = 10;
+pub const MAX_PAYLOAD_SIZE: usize = 16;
+
+/// Capability bit: `WeightWithRaw` reporting is supported
+pub const CAPABILITY_RAW_REPORTING: u32 = 1 << 0;
+/// Capability bit: live impulse reports are supported
+pub const CAPABILITY_IMPULSE: u32 = 1 << 1;
+/// Capability bit: the glitch slew-rate limiter is supported
+pub const CAPABILITY_SLEW_RATE_LIMITER: u32 = 1 << 2;
+/// Capability bit: measurements can stop automatically after a set duration
+pub const CAPABILITY_AUTO_STOP: u32 = 1 << 3;
+/// Capability bit: the Butterworth low-pass filter is supported
+pub const CAPABILITY_LOW_PASS_FILTER: u32 = 1 << 4;
+/// Capability bit: measurements can be reported as a percentage of bodyweight
+pub const CAPABILITY_BODYWEIGHT_PERCENT: u32 = 1 << 5;
+/// Capability bit: reps can be counted on-device
+pub const CAPABILITY_REP_COUNTER: u32 = 1 << 6;
+/// Capability bit: measurements can be reported only on significant change
+pub const CAPABILITY_REPORT_ON_CHANGE: u32 = 1 << 7;
+/// Capability bit: min/max envelopes of the measurements can be reported
+pub const CAPABILITY_ENVELOPE: u32 = 1 << 8;
+/// Capability bit: push (compression) forces can be measured in signed mode
+pub const CAPABILITY_SIGNED_MODE: u32 = 1 << 9;
+/// Capability bit: coalescing weight measurements into `CoalescedWeights` is supported
+pub const CAPABILITY_COALESCING: u32 = 1 << 10;
+/// Capability bit: the guided zero-and-span calibration is supported
+pub const CAPABILITY_GUIDED_CALIBRATION: u32 = 1 << 11;
+/// Capability bit: the median filter is supported
+pub const CAPABILITY_MEDIAN_FILTER: u32 = 1 << 12;
+/// Capability bit: measurements can be reported in pounds
+pub const CAPABILITY_POUNDS: u32 = 1 << 13;
+/// Capability bit: the exponential moving average filter is supported
+pub const CAPABILITY_EMA_FILTER: u32 = 1 << 14;
+/// Capability bit: the recent raw readings can be dumped with `DumpRawBuffer`
+pub const CAPABILITY_RAW_DUMP: u32 = 1 << 15;
+/// Capability bit: extension responses can be turned off with `SetProtocolMode`
+pub const CAPABILITY_PROTOCOL_MODE: u32 = 1 << 16;
+/// Capability bit: the configuration can be exported and imported, see `provisioning`
+pub const CAPABILITY_CONFIG_TRANSFER: u32 = 1 << 17;
+/// Capability bit: the advertised name can be changed with `SetName`
+pub const CAPABILITY_SET_NAME: u32 = 1 << 18;
+/// Capability bit: heartbeats can be sent while not measuring
+pub const CAPABILITY_HEARTBEAT: u32 = 1 << 19;
+/// Capability bit: the force derivative can be reported in `ForceAndRate`
+pub const CAPABILITY_FORCE_AND_RATE: u32 = 1 << 20;
+/// Capability bit: the force resting on the device can be subtracted before each pull
+pub const CAPABILITY_BASELINE_SUBTRACTION: u32 = 1 << 21;
+/// Capability bit: a canned force profile is played back instead of reading the load cell
+pub const CAPABILITY_DEMO: u32 = 1 << 22;
+/// Capability bit: measurements are mirrored to the serial port
+pub const CAPABILITY_UART_STREAM: u32 = 1 << 23;
+/// Capability bit: the load cell is read with a NAU7802 instead of an HX711
+pub const CAPABILITY_NAU7802: u32 = 1 << 24;
+/// Capabilities supported by this firmware build, including the features it was built with
+pub const CAPABILITIES: u32 = CAPABILITY_RAW_REPORTING
+    | CAPABILITY_IMPULSE
+    | CAPABILITY_SLEW_RATE_LIMITER
+    | CAPABILITY_AUTO_STOP
+    | CAPABILITY_LOW_PASS_FILTER
+    | CAPABILITY_BODYWEIGHT_PERCENT
+    | CAPABILITY_REP_COUNTER
+    | CAPABILITY_REPORT_ON_CHANGE
+    | CAPABILITY_ENVELOPE
+    | CAPABILITY_SIGNED_MODE
+    | CAPABILITY_COALESCING
+    | CAPABILITY_GUIDED_CALIBRATION
+    | CAPABILITY_MEDIAN_FILTER
+    | CAPABILITY_POUNDS
+    | CAPABILITY_EMA_FILTER
+    | CAPABILITY_RAW_DUMP
+    | CAPABILITY_PROTOCOL_MODE
+    | CAPABILITY_CONFIG_TRANSFER
+    | CAPABILITY_SET_NAME
+    | CAPABILITY_HEARTBEAT
+    | CAPABILITY_FORCE_AND_RATE
+    | CAPABILITY_BASELINE_SUBTRACTION
+    | if cfg!(feature = "demo") {
+        CAPABILITY_DEMO
+    } else {
+        0
+    }
+    | if cfg!(feature = "uart-stream") {
+        CAPABILITY_UART_STREAM
+    } else {
+        0
+    }
+    | if cfg!(feature = "nau7802") {
+        CAPABILITY_NAU7802
+    } else {
+        0
+    };
+
+/// Default minimum rep duration in milliseconds
+const DEFAULT_REP_MIN_DURATION_MS: u16 = 500;
+
+/// Maximum number of samples averaged per measurement
+pub const MAX_AVERAGING_SAMPLES: u16 = 256;
 
-/// Number of bytes in the device ID
-const DEVICE_ID_SIZE: usize = 6;
+/// Longest time window in milliseconds measurements are averaged over
+pub const MAX_AVERAGING_WINDOW_MS: u16 = 1000;
+
+/// Maximum duration in seconds that can be set with `SetMeasureDuration`
+///
+/// Microsecond timestamps are sent as a `u32`, which wraps after ~71 minutes, so longer
+/// durations couldn't be told from the timestamps. Measurements without a duration run until
+/// stopped, their microsecond timestamps wrap and the on-device metrics only use wrapping
+/// differences.
+pub const MAX_MEASURE_DURATION_S: u16 = 3600;
 
 /// Status of the weight measurement task
 #[derive(Copy, Debug, Clone, PartialEq)]
@@ -30,12 +161,185 @@ pub enum MeasurementTaskStatus {
     Enabled,
     /// Measurements are disabled
     Disabled,
+    /// Measurements are paused, keeping the start time of the measurement
+    Paused,
     /// Device is in calibration mode with target weight
     Calibration(f32),
     /// Taring the scale (used in ClimbHarder App)
     Tare,
+    /// Taring the scale before starting a measurement
+    TareAndStart,
+    /// Taring the scale with fewer samples
+    QuickTare,
     /// Restores default calibration values
     DefaultCalibration,
+    /// Collecting a linearity check point with a known weight
+    LinearityPoint(f32),
+    /// Guided zero-and-span calibration in progress
+    GuidedCalibration(GuidedCalibration),
+}
+
+/// Phase of a guided zero-and-span calibration
+///
+/// 1. `StartGuidedCalibration` waits for the zero, with the load cell unloaded.
+/// 2. `GuidedCaptureZero` captures the zero, then waits for the span.
+/// 3. `GuidedCaptureSpan` captures the span under a known mass, then computes and stores the
+///    calibration.
+///
+/// A capture with unstable readings goes back to waiting, so it can be retried.
+///
+/// While waiting, live readings are streamed as `WeightWithRaw`, so the user can see when the
+/// load is stable. Every step is reported with `GuidedCalibration`. `StopMeasurement` cancels
+/// the calibration, keeping the previous one.
+#[derive(Copy, Debug, Clone, PartialEq)]
+pub enum GuidedCalibration {
+    /// Waiting for the zero to be captured, with the load cell unloaded
+    WaitingForZero,
+    /// Capturing the zero
+    CaptureZero,
+    /// Zero captured (average raw value), waiting for the span to be captured under a known
+    /// mass
+    WaitingForSpan { zero_raw: f32 },
+    /// Capturing the span under a known mass, in grams
+    CaptureSpan { zero_raw: f32, weight: f32 },
+}
+
+/// Step of a guided calibration, reported with `GuidedCalibration`
+#[repr(u8)]
+#[derive(Copy, Debug, Clone, PartialEq, Format)]
+pub enum GuidedCalibrationStep {
+    /// Calibration started, waiting for the zero capture
+    Started = 0,
+    /// Zero captured, waiting for the span capture
+    ZeroCaptured = 1,
+    /// Span captured, the calibration is computed and stored
+    Completed = 2,
+    /// The readings moved during a capture, it can be retried
+    Unstable = 3,
+    /// The calibration couldn't be computed or stored (e.g. zero and span too close), the
+    /// previous one is kept
+    Failed = 4,
+}
+
+/// Whether `StartMeasurement` tares the scale before measuring
+#[derive(Copy, Debug, Clone, PartialEq, Format)]
+pub enum AutoTareMode {
+    /// Never tare automatically, the app is responsible for sending `TareScale`
+    Never,
+    /// Tare only if the scale has not been tared yet
+    WhenUntared,
+    /// Tare on every start, removing any pre-load (e.g. bodyweight or forearm)
+    Always,
+}
+
+impl AutoTareMode {
+    /// Parse the mode from its wire value
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(AutoTareMode::Never),
+            1 => Some(AutoTareMode::WhenUntared),
+            2 => Some(AutoTareMode::Always),
+            _ => None,
+        }
+    }
+}
+
+/// Response codes a client has to understand
+#[derive(Copy, Debug, Clone, PartialEq, Format)]
+pub enum ProtocolMode {
+    /// Only the response codes of the stock Progressor are sent, for strict Tindeq apps
+    Tindeq,
+    /// Crimpdeq extension responses are sent too
+    Extended,
+}
+
+impl ProtocolMode {
+    /// Parse the mode from its wire value
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(ProtocolMode::Tindeq),
+            1 => Some(ProtocolMode::Extended),
+            _ => None,
+        }
+    }
+}
+
+/// Unit of the timestamps sent with weight measurements
+///
+/// Timestamps are sent as `u32`. Microseconds, as on the Progressor, wrap after ~71 minutes,
+/// while milliseconds last ~49 days at the cost of sub-millisecond precision. The elapsed time
+/// is kept in a `u64` and only narrowed once converted, so each unit wraps at its own range.
+/// Clients of measurements longer than ~71 minutes either use milliseconds or unwrap the
+/// timestamps themselves.
+#[derive(Copy, Debug, Clone, PartialEq, Format)]
+pub enum TimestampUnit {
+    /// Microseconds, compatible with Tindeq apps
+    Micros,
+    /// Milliseconds
+    Millis,
+}
+
+impl TimestampUnit {
+    /// Parse the unit from its wire value
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(TimestampUnit::Micros),
+            1 => Some(TimestampUnit::Millis),
+            _ => None,
+        }
+    }
+
+    /// Convert the time elapsed since the measurement started, in microseconds, to a
+    /// timestamp in this unit
+    pub fn encode(self, elapsed_us: u64) -> u32 {
+        match self {
+            TimestampUnit::Micros => elapsed_us as u32,
+            TimestampUnit::Millis => (elapsed_us / 1000) as u32,
+        }
+    }
+}
+
+/// Pounds in a kilogram
+pub const POUNDS_PER_KG: f32 = 2.204_622_6;
+
+/// Unit of the weights sent with measurements
+///
+/// Kilograms, as on the Progressor, by default. A percentage of the bodyweight, when one is
+/// set, takes precedence over either unit.
+#[derive(Copy, Debug, Clone, PartialEq, Format)]
+pub enum WeightUnit {
+    /// Kilograms, compatible with Tindeq apps
+    Kg,
+    /// Pounds
+    Lb,
+}
+
+impl WeightUnit {
+    /// Parse the unit from its wire value
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(WeightUnit::Kg),
+            1 => Some(WeightUnit::Lb),
+            _ => None,
+        }
+    }
+
+    /// Convert a weight in kg to this unit
+    pub fn convert(self, weight_kg: f32) -> f32 {
+        match self {
+            WeightUnit::Kg => weight_kg,
+            WeightUnit::Lb => weight_kg * POUNDS_PER_KG,
+        }
+    }
+}
+
+/// Calibration point collected with a known weight
+#[derive(Copy, Debug, Clone, PartialEq, Format)]
+pub struct CalibrationPoint {
+    /// Average raw value measured
+    pub raw: f32,
+    /// Known weight applied, in grams
+    pub weight: f32,
 }
 
 /// Device state management
@@ -45,54 +349,432 @@ pub struct DeviceState {
     pub measurement_status: MeasurementTaskStatus,
     /// Tared status
     pub tared: bool,
-    /// Start time of the measurement in microseconds
-    pub start_time: u32,
-    /// Calibration points [point1, point2]
-    pub calibration_points: [Option<f32>; 2],
+    /// Start time of the measurement in microseconds, on the device clock
+    pub start_time: u64,
+    /// Calibration points, the calibration is fitted to all of them
+    pub calibration_points: [Option<CalibrationPoint>; MAX_CALIBRATION_POINTS],
+    /// Points collected with known weights to check the linearity of the load cell
+    pub linearity_points: [Option<CalibrationPoint>; MAX_LINEARITY_POINTS],
+    /// Report the raw reading together with each weight measurement
+    pub report_raw: bool,
+    /// Number of samples between impulse reports, zero disables them
+    ///
+    /// `None` until set by the client, reporting about once per second at the current sample
+    /// rate.
+    pub impulse_report_interval: Option<u32>,
+    /// Maximum change in kg allowed between consecutive samples
+    pub max_weight_step: f32,
+    /// Duration in seconds after which a measurement stops itself, capped to
+    /// [`MAX_MEASURE_DURATION_S`], zero means no limit
+    ///
+    /// Time spent paused counts towards it.
+    pub measure_duration: u16,
+    /// A dump of the recent raw readings was requested
+    pub raw_dump_requested: bool,
+    /// Zero on the next reading of the running measurement, see `soft_tare`
+    pub soft_tare_requested: bool,
+    /// Auto-tare behavior of `StartMeasurement`
+    pub auto_tare: AutoTareMode,
+    /// Filter applied to the weight measurements
+    pub filter_mode: FilterMode,
+    /// Report whether the filter had settled with each weight measurement
+    pub report_settled: bool,
+    /// A software reset was requested
+    pub reboot_requested: bool,
+    /// Power off (deep sleep) was requested, see `power`
+    pub shutdown_requested: bool,
+    /// Bodyweight in kg, when set measurements are reported as a percentage of it
+    pub bodyweight: Option<f32>,
+    /// Fault detected by the load cell self test at startup
+    pub load_cell_fault: Option<LoadCellFault>,
+    /// Rep counter thresholds, `None` disables the rep counter
+    pub rep_thresholds: Option<RepThresholds>,
+    /// Minimum rep duration in milliseconds
+    pub rep_min_duration_ms: u16,
+    /// Only report measurements that changed significantly, `None` reports all of them
+    pub report_on_change: Option<ReportOnChange>,
+    /// A chip temperature reading was requested
+    pub temperature_requested: bool,
+    /// A battery voltage reading was requested
+    pub battery_requested: bool,
+    /// Plausible range of weight measurements in kg (min, max)
+    pub plausible_range: (f32, f32),
+    /// Number of samples per envelope report, zero disables them
+    pub envelope_window: u32,
+    /// Acknowledge mutating commands, off by default to match the stock Progressor
+    pub command_acks: bool,
+    /// A drift estimate was requested
+    pub drift_requested: bool,
+    /// The peak rate of force development was requested
+    pub peak_rfd_requested: bool,
+    /// Number of samples averaged per measurement, from 1 to [`MAX_AVERAGING_SAMPLES`]
+    ///
+    /// Averaging divides the sample rate, and intervals counted in samples (e.g. impulse
+    /// reports) count averaged measurements.
+    pub averaging_samples: u16,
+    /// Time window in milliseconds measurements are averaged over instead of
+    /// `averaging_samples`, up to [`MAX_AVERAGING_WINDOW_MS`], zero averages by number of samples
+    ///
+    /// Smoothing over a window doesn't depend on the sample rate.
+    pub averaging_window_ms: u16,
+    /// Interval between heartbeats in milliseconds while not measuring, zero disables them
+    pub heartbeat_interval_ms: u16,
+    /// An export of the configuration blob was requested
+    pub config_export_requested: bool,
+    /// Configuration blob being imported
+    pub config_import: ConfigImport,
+    /// Calibration imported from a configuration blob, to be applied
+    pub imported_calibration: Option<Calibration>,
+    /// Measure push (negative) as well as pull (positive) forces
+    ///
+    /// Weights are always reported signed. In signed mode, features meant for pulls (e.g. the
+    /// rep counter) work on the magnitude of the force, so pushes count too.
+    pub signed_mode: bool,
+    /// Response codes sent to the client
+    pub protocol_mode: ProtocolMode,
+    /// The client enabled notifications of the data point characteristic (CCCD), data points
+    /// are dropped otherwise
+    pub notifications_enabled: bool,
+    /// Cause of the last reset, read at boot
+    pub reset_reason: ResetReason,
+    /// Window in milliseconds over which weight measurements are coalesced into a single data
+    /// point, zero sends each one on its own
+    ///
+    /// Measurements are delayed by up to the window. Raw and settled reporting are not
+    /// coalesced.
+    pub coalesce_window_ms: u16,
+    /// Unit of the timestamps sent with weight measurements
+    pub timestamp_unit: TimestampUnit,
+    /// Unit of the weights sent with measurements
+    pub weight_unit: WeightUnit,
+    /// Window in milliseconds of the force derivative sent in `ForceAndRate`, zero sends
+    /// weight measurements instead
+    pub rate_window_ms: u16,
+    /// Subtract the force resting on the device before each pull, see `BaselineSubtractor`
+    pub baseline_subtraction: bool,
+    /// Device name to store, applied by rebooting once stored
+    pub pending_name: Option<DeviceName>,
+    /// Progressor ID to store, see `device_id`
+    pub pending_device_id: Option<[u8; DEVICE_ID_SIZE]>,
+    /// Change in kg between consecutive readings that snaps the filter to the new reading,
+    /// infinity disables it
+    pub filter_step_reset: f32,
+    /// Number of overload events over the lifetime of the device, kept up to date by the
+    /// measurement task, see `OverloadCounter`
+    pub overload_count: u32,
 }
 
 impl Default for DeviceState {
     fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DeviceState {
+    /// Create a new device state with default values
+    pub const fn new() -> Self {
         Self {
             measurement_status: MeasurementTaskStatus::Disabled,
             tared: false,
             start_time: 0,
-            calibration_points: [None, None],
+            calibration_points: [None; MAX_CALIBRATION_POINTS],
+            linearity_points: [None; MAX_LINEARITY_POINTS],
+            report_raw: false,
+            impulse_report_interval: None,
+            max_weight_step: f32::INFINITY,
+            measure_duration: 0,
+            raw_dump_requested: false,
+            soft_tare_requested: false,
+            auto_tare: AutoTareMode::Never,
+            filter_mode: FilterMode::None,
+            report_settled: false,
+            reboot_requested: false,
+            shutdown_requested: false,
+            bodyweight: None,
+            load_cell_fault: None,
+            rep_thresholds: None,
+            rep_min_duration_ms: DEFAULT_REP_MIN_DURATION_MS,
+            report_on_change: None,
+            temperature_requested: false,
+            battery_requested: false,
+            plausible_range: (f32::NEG_INFINITY, f32::INFINITY),
+            envelope_window: 0,
+            command_acks: false,
+            drift_requested: false,
+            peak_rfd_requested: false,
+            averaging_samples: 1,
+            averaging_window_ms: 0,
+            heartbeat_interval_ms: 0,
+            config_export_requested: false,
+            config_import: ConfigImport::new(),
+            imported_calibration: None,
+            signed_mode: false,
+            protocol_mode: ProtocolMode::Tindeq,
+            notifications_enabled: false,
+            reset_reason: ResetReason::Unknown,
+            coalesce_window_ms: 0,
+            timestamp_unit: TimestampUnit::Micros,
+            weight_unit: WeightUnit::Kg,
+            rate_window_ms: 0,
+            baseline_subtraction: false,
+            pending_name: None,
+            pending_device_id: None,
+            filter_step_reset: f32::INFINITY,
+            overload_count: 0,
         }
     }
-}
 
-impl DeviceState {
-    /// Create a new device state with default values
-    pub fn new() -> Self {
-        Self::default()
+    /// Handle a start request, taring first if required by the auto-tare mode
+    pub fn request_start(&mut self) {
+        let tare = match self.auto_tare {
+            AutoTareMode::Never => false,
+            AutoTareMode::WhenUntared => !self.tared,
+            AutoTareMode::Always => true,
+        };
+
+        if tare {
+            self.measurement_status = MeasurementTaskStatus::TareAndStart;
+        } else {
+            self.start_measurement();
+        }
     }
 
     /// Start a measurement
     pub fn start_measurement(&mut self) {
-        self.start_time = (time::Instant::now().duration_since_epoch()).as_micros() as u32;
+        self.start_measurement_at(Instant::now().as_micros());
+    }
+
+    /// Start a measurement at the given time in microseconds
+    ///
+    /// Does not depend on the hardware timer, so state transitions can be driven with a fake
+    /// time source.
+    pub fn start_measurement_at(&mut self, start_time: u64) {
+        self.start_time = start_time;
         self.measurement_status = MeasurementTaskStatus::Enabled;
     }
 
+    /// Reset the state of the session when a client disconnects
+    ///
+    /// Everything configured by the client (measurement status, reporting options, pending
+    /// requests and calibration points) is reset, so every connection starts clean. The tare,
+    /// the stored calibration, the load cell self test result, the reset reason and a pending
+    /// reboot or shutdown persist.
+    pub fn reset_session(&mut self) {
+        *self = Self {
+            tared: self.tared,
+            load_cell_fault: self.load_cell_fault,
+            reboot_requested: self.reboot_requested,
+            shutdown_requested: self.shutdown_requested,
+            reset_reason: self.reset_reason,
+            ..Self::new()
+        };
+    }
+
+    /// Check whether the device is about to reset or power off, commands are ignored from then on
+    pub fn shutting_down(&self) -> bool {
+        self.reboot_requested || self.shutdown_requested
+    }
+
+    /// Check whether a tare or calibration is in progress
+    pub fn operation_in_progress(&self) -> bool {
+        matches!(
+            self.measurement_status,
+            MeasurementTaskStatus::Calibration(_)
+                | MeasurementTaskStatus::Tare
+                | MeasurementTaskStatus::TareAndStart
+                | MeasurementTaskStatus::QuickTare
+                | MeasurementTaskStatus::DefaultCalibration
+                | MeasurementTaskStatus::LinearityPoint(_)
+                | MeasurementTaskStatus::GuidedCalibration(_)
+        )
+    }
+
     /// Stop the current measurement
     pub fn stop_measurement(&mut self) {
         self.measurement_status = MeasurementTaskStatus::Disabled;
     }
 
+    /// Pause the current measurement
+    pub fn pause_measurement(&mut self) {
+        if self.measurement_status == MeasurementTaskStatus::Enabled {
+            self.measurement_status = MeasurementTaskStatus::Paused;
+        }
+    }
+
+    /// Resume a paused measurement
+    ///
+    /// The start time is kept, so timestamps after resuming jump by the length of the pause
+    /// instead of restarting from zero.
+    pub fn resume_measurement(&mut self) {
+        if self.measurement_status == MeasurementTaskStatus::Paused {
+            self.measurement_status = MeasurementTaskStatus::Enabled;
+        }
+    }
+
     /// Start taring process
     pub fn tare(&mut self) {
         self.measurement_status = MeasurementTaskStatus::Tare;
     }
 
+    /// Start quick taring process
+    pub fn quick_tare(&mut self) {
+        self.measurement_status = MeasurementTaskStatus::QuickTare;
+    }
+
+    /// Zero on the current reading
+    ///
+    /// While measuring, the next reading becomes the zero reference without stopping the
+    /// measurement or taking extra samples. Otherwise, falls back to a quick tare.
+    pub fn soft_tare(&mut self) {
+        if self.measurement_status == MeasurementTaskStatus::Enabled {
+            self.soft_tare_requested = true;
+        } else {
+            self.quick_tare();
+        }
+    }
+
+    /// Start a zero-and-span calibration by capturing the unloaded point
+    ///
+    /// Discards the calibration points collected so far. The next calibration point, taken
+    /// under a known mass, completes the calibration.
+    pub fn capture_zero(&mut self) {
+        self.calibration_points = [None; MAX_CALIBRATION_POINTS];
+        self.calibrate(0.0);
+    }
+
     /// Set calibration mode with the given weight
     pub fn calibrate(&mut self, weight: f32) {
         self.measurement_status = MeasurementTaskStatus::Calibration(weight);
     }
 
-    /// Reset to default calibration
+    /// Collect a linearity check point with the given weight
+    pub fn add_linearity_point(&mut self, weight: f32) {
+        self.measurement_status = MeasurementTaskStatus::LinearityPoint(weight);
+    }
+
+    /// Check the linearity of the collected points, discarding them
+    ///
+    /// Returns the worst deviation in percent of the heaviest weight, and whether it is within
+    /// [`LINEARITY_TOLERANCE_PCT`], or `None` if the points are not enough to tell.
+    pub fn check_linearity(&mut self) -> Option<(f32, bool)> {
+        let mut points = [(0.0, 0.0); MAX_LINEARITY_POINTS];
+        let mut len = 0;
+        for point in self.linearity_points.iter().flatten() {
+            points[len] = (point.raw, point.weight);
+            len += 1;
+        }
+        self.linearity_points = [None; MAX_LINEARITY_POINTS];
+
+        let max_error_pct = linearity_error_pct(&points[..len])?;
+        Some((max_error_pct, max_error_pct <= LINEARITY_TOLERANCE_PCT))
+    }
+
+    /// Start a guided calibration, see `GuidedCalibration`
+    pub fn start_guided_calibration(&mut self) {
+        self.measurement_status =
+            MeasurementTaskStatus::GuidedCalibration(GuidedCalibration::WaitingForZero);
+    }
+
+    /// Capture the zero of a guided calibration, returning whether it was waiting for one
+    ///
+    /// The zero can be captured again while waiting for the span.
+    pub fn guided_capture_zero(&mut self) -> bool {
+        match self.measurement_status {
+            MeasurementTaskStatus::GuidedCalibration(
+                GuidedCalibration::WaitingForZero | GuidedCalibration::WaitingForSpan { .. },
+            ) => {
+                self.measurement_status =
+                    MeasurementTaskStatus::GuidedCalibration(GuidedCalibration::CaptureZero);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Capture the span of a guided calibration under a known weight in grams, returning
+    /// whether it was waiting for one
+    pub fn guided_capture_span(&mut self, weight: f32) -> bool {
+        match self.measurement_status {
+            MeasurementTaskStatus::GuidedCalibration(GuidedCalibration::WaitingForSpan {
+                zero_raw,
+            }) => {
+                self.measurement_status =
+                    MeasurementTaskStatus::GuidedCalibration(GuidedCalibration::CaptureSpan {
+                        zero_raw,
+                        weight,
+                    });
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Reset to default calibration, discarding the calibration points
     pub fn reset_calibration(&mut self) {
+        self.calibration_points = [None; MAX_CALIBRATION_POINTS];
         self.measurement_status = MeasurementTaskStatus::DefaultCalibration;
     }
+
+    /// Enable or disable reporting raw readings alongside weight measurements
+    pub fn set_report_raw(&mut self, enabled: bool) {
+        self.report_raw = enabled;
+    }
+
+    /// Set the number of samples between impulse reports
+    pub fn set_impulse_report_interval(&mut self, interval: u32) {
+        self.impulse_report_interval = Some(interval);
+    }
+
+    /// Set the maximum change in kg allowed between consecutive samples
+    pub fn set_max_weight_step(&mut self, max_step: f32) {
+        self.max_weight_step = max_step;
+    }
+
+    /// Set the duration in seconds after which a measurement stops itself
+    pub fn set_measure_duration(&mut self, seconds: u16) {
+        self.measure_duration = seconds;
+    }
+
+    /// Convert a weight in kg to the unit it is reported in
+    ///
+    /// Weights are reported in the weight unit (kg by default), or as a percentage of the
+    /// bodyweight when one is set. Everything reporting weights (measurements, envelopes, peak
+    /// RFD, ...) goes through here, so they all use the same unit.
+    pub fn reported_weight(&self, weight: f32) -> f32 {
+        match self.bodyweight {
+            Some(bodyweight) => weight / bodyweight * 100.0,
+            None => self.weight_unit.convert(weight),
+        }
+    }
+
+    /// Rate of measurements in Hz for a load cell sampling at `sample_rate_hz`
+    ///
+    /// Averaging over a window takes as many samples as arrive within it, at least two sample
+    /// periods long.
+    pub fn measurement_rate_hz(&self, sample_rate_hz: f32) -> f32 {
+        if self.averaging_window_ms == 0 {
+            return sample_rate_hz / self.averaging_samples as f32;
+        }
+        (1000.0 / self.averaging_window_ms as f32).min(sample_rate_hz / 2.0)
+    }
+
+    /// Force in kg seen by the features meant for pulls, the magnitude in signed mode
+    pub fn pull_force(&self, weight: f32) -> f32 {
+        if self.signed_mode {
+            weight.abs()
+        } else {
+            weight
+        }
+    }
+
+    /// Check whether a measurement running for `elapsed_us` microseconds has reached its
+    /// duration limit
+    pub fn measure_duration_elapsed(&self, elapsed_us: u64) -> bool {
+        match self.measure_duration {
+            0 => false,
+            duration => elapsed_us >= duration.min(MAX_MEASURE_DURATION_S) as u64 * 1_000_000,
+        }
+    }
 }
 
 /// Progressor Commands
@@ -104,84 +786,326 @@ pub enum ControlOpCode {
     StartMeasurement = 0x65,
     /// Stop weight measurement. This should be done before sampling the battery voltage
     StopMeasurement = 0x66,
-    /// Turn the Progressor off
+    /// Turn the Progressor off, it sleeps until woken up by the wake pin (see `power`)
     Shutdown = 0x6E,
-    /// Measures the battery voltage in millivolts
+    /// Measures the battery voltage in millivolts. Answered with 0 while measuring
     SampleBattery = 0x6F,
     /// Get the Progressor ID
     GetProgressorId = 0x70,
     /// Get the application version
     GetAppVersion = 0x6B,
-    /// Get the calibration values
+    /// Get the stored calibration values, answered with `CalibrationValues`
     GetCalibration = 0x72,
-    /// Adds a calibration point
+    /// Adds a calibration point with the weight in grams (f32), up to 8 points. Once a point
+    /// has a positive weight, the calibration is fitted to all the points (with least squares
+    /// for two or more, relative to the tare for a single one) and stored. `CaptureZero` and
+    /// `DefaultCalibration` discard the points
     AddCalibrationPoint = 0x73,
     /// Default calibration
     DefaultCalibration = 0x74,
+    /// Enable (non-zero) or disable (zero) reporting raw readings with each measurement
+    SetRawReporting = 0x75,
+    /// Set the number of samples (u16) between impulse reports, zero disables them
+    SetImpulseReportInterval = 0x76,
+    /// Set the maximum change in kg (f32) between consecutive samples, zero or less disables it
+    SetMaxWeightStep = 0x77,
+    /// Get the bitfield of capabilities supported by the firmware
+    GetCapabilities = 0x78,
+    /// Set the measurement duration in seconds (u16), capped to `MAX_MEASURE_DURATION_S`, zero
+    /// means no limit
+    SetMeasureDuration = 0x79,
+    /// Get the stored calibration points, one response per point
+    GetCalibrationPoints = 0x7A,
+    /// Stream the most recent raw readings
+    DumpRawBuffer = 0x7B,
+    /// Set the auto-tare mode (u8): 0 never, 1 when untared, 2 always
+    SetAutoTare = 0x7C,
+    /// Set the filter mode (u8): 0 none, 1 Butterworth low-pass, 2 median, 3 exponential moving
+    /// average, followed by its smoothing factor (f32) within (0, 1]
+    SetFilterMode = 0x7D,
+    /// Enable (non-zero) or disable (zero) reporting the filter settled flag with each measurement
+    SetSettledReporting = 0x7E,
+    /// Restart the device, which comes back up advertising
+    Reboot = 0x7F,
+    /// Set the bodyweight in kg (f32) to report measurements as a percentage of it,
+    /// zero or less reports them in kg. Non-finite values are rejected
+    SetBodyweight = 0x80,
+    /// Set the runtime log level (u8): 0 off, 1 error, 2 warn, 3 info, 4 debug, 5 trace
+    SetLogLevel = 0x81,
+    /// Pause the measurement, keeping its start time and accumulated metrics
+    PauseMeasurement = 0x82,
+    /// Resume a paused measurement
+    ResumeMeasurement = 0x83,
+    /// Set the rep counter on and off thresholds in kg (two f32), an on threshold of zero or
+    /// less disables the rep counter. Otherwise both must be finite, with off not above on
+    SetRepThresholds = 0x84,
+    /// Set the minimum rep duration in milliseconds (u16)
+    SetRepMinDuration = 0x85,
+    /// Only report measurements changing more than a delta in kg (f32), or after a heartbeat
+    /// interval in milliseconds (u16). A delta of zero or less reports every measurement, a
+    /// non-finite one is rejected
+    SetReportOnChange = 0x86,
+    /// Get the chip temperature in °C
+    GetTemperature = 0x87,
+    /// Set the plausible range of weight measurements in kg (two f32, min and max)
+    SetPlausibleRange = 0x88,
+    /// Zero weight with fewer samples, faster but less accurate than `TareScale`
+    QuickTare = 0x89,
+    /// Set the number of samples (u16) per min/max envelope report, zero disables them
+    SetEnvelopeWindow = 0x8A,
+    /// Enable (non-zero) or disable (zero) acknowledging mutating commands
+    SetCommandAcks = 0x8B,
+    /// Get the long-term drift estimate of the unloaded reading in kg
+    GetDriftEstimate = 0x8C,
+    /// Set the number of samples (u16) averaged per measurement, clamped to 1..=256
+    SetAveraging = 0x8D,
+    /// Get the number of samples averaged per measurement
+    GetAveraging = 0x8E,
+    /// Set the interval in milliseconds (u16) between heartbeats while not measuring, zero
+    /// disables them
+    SetHeartbeatInterval = 0x8F,
+    /// Capture the unloaded point of a zero-and-span calibration. Follow with
+    /// `AddCalibrationPoint` under a known mass (e.g. a kettlebell) to complete it.
+    ///
+    /// A single reference assumes the load cell is linear: errors in the known mass scale every
+    /// measurement, and they grow when measuring well above it, so use the heaviest reference
+    /// at hand.
+    CaptureZero = 0x90,
+    /// Stream the configuration blob (calibration, tare, device ID and name), see
+    /// `provisioning`
+    ExportConfig = 0x91,
+    /// Import a chunk of a configuration blob: offset (u8) followed by up to 8 bytes. The
+    /// calibration is applied once the whole blob is received and validated, and a name in it
+    /// is stored as with `SetName`
+    ImportConfig = 0x92,
+    /// Enable (non-zero) or disable (zero) signed mode, for setups measuring push forces
+    SetSignedMode = 0x93,
+    /// Set the protocol mode (u8): 0 Tindeq, only stock response codes, 1 extended. Clients
+    /// using extension commands have to switch to the extended mode to get their responses
+    SetProtocolMode = 0x94,
+    /// Get the uptime and the cause of the last reset
+    GetDiagnostics = 0x95,
+    /// Set the window in milliseconds (u16) over which weight measurements are coalesced into
+    /// `CoalescedWeights` data points, zero disables coalescing. Match it to the connection
+    /// interval to send one notification per connection event
+    SetCoalesceWindow = 0x96,
+    /// Collect a linearity check point with a known weight (f32, in grams), up to 6 points.
+    /// Unlike `AddCalibrationPoint`, the calibration is not changed
+    AddLinearityPoint = 0x97,
+    /// Check that the linearity points lie on a line, answered with `LinearityResult`. Needs
+    /// at least 3 points spread over the range trained in, and discards them
+    CheckLinearity = 0x98,
+    /// Set the unit (u8) of the timestamps of weight measurements: 0 microseconds, as on the
+    /// Progressor, 1 milliseconds
+    SetTimestampUnit = 0x99,
+    /// Get the configuration of the filters applied to the weight, see `FilterConfig`
+    GetFilterConfig = 0x9A,
+    /// Report `ForceAndRate` instead of weight measurements, with the derivative smoothed over
+    /// a window in milliseconds (u16, clamped to 250). Zero reports weight measurements
+    SetRateWindow = 0x9B,
+    /// Get whether the scale has been tared since boot
+    GetTareState = 0x9C,
+    /// Enable (non-zero) or disable (zero) subtracting the force resting on the device before
+    /// each pull, so pulls start at zero. Applied on top of the tare
+    SetBaselineSubtraction = 0x9D,
+    /// Set the device name (UTF-8, up to 26 bytes), answered with `NameSet`. The name is stored
+    /// and the device reboots to advertise it
+    SetName = 0x9E,
+    /// Set the change in kg (f32) between consecutive readings above which the filter snaps to
+    /// the new reading, keeping onsets sharp. Zero, negative or non-finite values disable it
+    SetFilterStepReset = 0x9F,
+    /// Get the number of overload events over the lifetime of the device
+    GetOverloadCount = 0xA0,
+    /// Start a guided zero-and-span calibration, streaming live readings. See
+    /// `GuidedCalibration` for the flow
+    StartGuidedCalibration = 0xA1,
+    /// Capture the zero of a guided calibration, with the load cell unloaded
+    GuidedCaptureZero = 0xA2,
+    /// Capture the span of a guided calibration under a known mass (f32, in grams). The
+    /// calibration is then computed and stored
+    GuidedCaptureSpan = 0xA3,
+    /// Get the peak rate of force development of the current or last measurement, answered
+    /// with `PeakRfd`
+    GetPeakRfd = 0xA4,
+    /// Set the Progressor ID (6 bytes, in the byte order of the `ProgressorId` response),
+    /// answered with `ProgressorIdSet`. The ID is stored and persists across reboots
+    SetProgressorId = 0xA5,
+    /// Zero weight on the current reading. While measuring, the measurement keeps running
+    SoftTare = 0xA6,
+    /// Set the unit (u8) of the weights of measurements: 0 kilograms, as on the Progressor,
+    /// 1 pounds
+    SetWeightUnit = 0xA7,
+    /// Set the time window in milliseconds (u16) measurements are averaged over instead of the
+    /// `SetAveraging` number of samples, clamped to 1000, zero averages by number of samples
+    SetAveragingWindow = 0xA8,
 }
 
 impl ControlOpCode {
     /// Process the control operation
+    ///
+    /// When command acknowledgements are enabled, mutating commands are acknowledged with
+    /// whether they were accepted. Commands completed by the measurement task (e.g. taring)
+    /// are acknowledged when accepted, not when done.
+    ///
+    /// Once the device is shutting down, commands are ignored and answered with
+    /// `ShuttingDown`, so the client doesn't wait for a response that never comes.
     pub fn process(
         self,
         data: &[u8],
         channel: &'static DataPointChannel,
         device_state: &mut DeviceState,
     ) {
+        if device_state.shutting_down() {
+            warn!("{:?} ignored: shutting down", self);
+            DataPoint::from(ResponseCode::ShuttingDown).send(channel);
+            return;
+        }
+
+        if device_state.operation_in_progress() && self.conflicts_with_operation() {
+            error!("{:?} rejected: operation in progress", self);
+            DataPoint::from(ResponseCode::CommandRejected(self as u8)).send(channel);
+            return;
+        }
+
+        let ok = self.execute(data, channel, device_state);
+        if device_state.command_acks && self.is_mutating() {
+            DataPoint::from(ResponseCode::CommandAck {
+                op_code: self as u8,
+                ok,
+            })
+            .send(channel);
+        }
+    }
+
+    /// Whether the command would interrupt a tare or calibration in progress
+    ///
+    /// `StopMeasurement` is still accepted, it cancels a pending start after a tare, and so is
+    /// `Reboot`, which waits for the operation to finish.
+    fn conflicts_with_operation(self) -> bool {
+        matches!(
+            self,
+            ControlOpCode::TareScale
+                | ControlOpCode::QuickTare
+                | ControlOpCode::SoftTare
+                | ControlOpCode::StartMeasurement
+                | ControlOpCode::PauseMeasurement
+                | ControlOpCode::ResumeMeasurement
+                | ControlOpCode::AddCalibrationPoint
+                | ControlOpCode::DefaultCalibration
+                | ControlOpCode::CaptureZero
+                | ControlOpCode::ImportConfig
+                | ControlOpCode::AddLinearityPoint
+                | ControlOpCode::StartGuidedCalibration
+        )
+    }
+
+    /// Whether the command changes the state of the device, queries don't
+    fn is_mutating(self) -> bool {
+        !matches!(
+            self,
+            ControlOpCode::SampleBattery
+                | ControlOpCode::GetProgressorId
+                | ControlOpCode::GetAppVersion
+                | ControlOpCode::GetCalibration
+                | ControlOpCode::GetCapabilities
+                | ControlOpCode::GetCalibrationPoints
+                | ControlOpCode::DumpRawBuffer
+                | ControlOpCode::GetTemperature
+                | ControlOpCode::GetDriftEstimate
+                | ControlOpCode::GetAveraging
+                | ControlOpCode::ExportConfig
+                | ControlOpCode::GetDiagnostics
+                | ControlOpCode::GetFilterConfig
+                | ControlOpCode::GetTareState
+                | ControlOpCode::GetOverloadCount
+                | ControlOpCode::GetPeakRfd
+        )
+    }
+
+    /// Execute the control operation, returning whether it was accepted
+    fn execute(
+        self,
+        data: &[u8],
+        channel: &'static DataPointChannel,
+        device_state: &mut DeviceState,
+    ) -> bool {
         match self {
             ControlOpCode::TareScale => {
                 device_state.tare();
             }
+            ControlOpCode::QuickTare => {
+                device_state.quick_tare();
+            }
+            ControlOpCode::SoftTare => {
+                device_state.soft_tare();
+            }
             ControlOpCode::StartMeasurement => {
-                device_state.start_measurement();
+                if !device_state.tared {
+                    // Sent before any auto-tare, so the app can tell the user to unload it
+                    DataPoint::from(ResponseCode::TareRequired).send(channel);
+                }
+                device_state.request_start();
+                // When taring first, the measurement task acknowledges the start
+                if device_state.measurement_status == MeasurementTaskStatus::Enabled {
+                    DataPoint::from(ResponseCode::MeasurementStarted(
+                        device_state.start_time as u32,
+                    ))
+                    .send(channel);
+                }
             }
             ControlOpCode::StopMeasurement => {
+                // The measurement task sends the pending data points, then `MeasurementStopped`
                 device_state.stop_measurement();
             }
+            ControlOpCode::PauseMeasurement => {
+                device_state.pause_measurement();
+            }
+            ControlOpCode::ResumeMeasurement => {
+                device_state.resume_measurement();
+            }
             ControlOpCode::GetAppVersion => {
                 let response = ResponseCode::AppVersion(env!("DEVICE_VERSION_NUMBER").as_bytes());
                 info!("AppVersion: {:#x}", response);
                 DataPoint::from(response).send(channel);
             }
             ControlOpCode::GetProgressorId => {
-                /// Number of hex characters needed per byte (2 hex chars = 1 byte)
-                const HEX_CHARS_PER_BYTE: usize = 2;
-                /// Hex radix for parsing hex strings
-                const HEX_RADIX: u32 = 16;
-
-                let device_id = env!("DEVICE_ID");
-                let mut bytes = [0u8; DEVICE_ID_SIZE];
-                for (i, byte) in bytes.iter_mut().enumerate() {
-                    let char_pos = i * HEX_CHARS_PER_BYTE;
-                    let next_char_pos = char_pos + HEX_CHARS_PER_BYTE;
-                    if next_char_pos <= device_id.len() {
-                        if let Ok(parsed_byte) =
-                            u8::from_str_radix(&device_id[char_pos..next_char_pos], HEX_RADIX)
-                        {
-                            *byte = parsed_byte;
-                        }
+                let id = match device_id() {
+                    Ok(id) => id,
+                    Err(e) => {
+                        error!("GetProgressorId: Invalid DEVICE_ID: {}", e);
+                        return false;
                     }
-                }
-                let response = ResponseCode::ProgressorId(bytes);
+                };
+                let response = ResponseCode::ProgressorId(id);
                 info!("ProgressorId: {:?}", response);
                 DataPoint::from(response).send(channel);
             }
-            ControlOpCode::GetCalibration => {
-                info!("GetCalibration: {:?}", Hx711::get_calibration().unwrap());
-            }
+            ControlOpCode::GetCalibration => match load_cell::stored_calibration() {
+                Ok(calibration) => {
+                    let response = ResponseCode::CalibrationValues {
+                        zero_raw: calibration.zero_raw(),
+                        scale: calibration.scale(),
+                    };
+                    info!("GetCalibration: {:?}", response);
+                    DataPoint::from(response).send(channel);
+                }
+                Err(e) => {
+                    error!("GetCalibration: {}", e);
+                    return false;
+                }
+            },
             ControlOpCode::AddCalibrationPoint => {
                 if data.len() < 5 {
                     error!("AddCalibrationPoint: Invalid data length");
-                    return;
+                    return false;
                 }
 
-                let weight = match data[1..5].try_into() {
-                    Ok(bytes) => f32::from_be_bytes(bytes),
-                    Err(e) => {
-                        error!("Failed to parse calibration point data: {:?}", e);
-                        return;
-                    }
-                };
+                if device_state.calibration_points.iter().all(Option::is_some) {
+                    error!("AddCalibrationPoint: Too many points");
+                    return false;
+                }
+
+                let weight = command_f32(data, 1);
 
                 device_state.calibrate(weight);
                 debug!(
@@ -192,99 +1116,779 @@ impl ControlOpCode {
             ControlOpCode::DefaultCalibration => {
                 device_state.reset_calibration();
             }
-            ControlOpCode::SampleBattery => {
-                // Hardcoded for now
-                let voltage = 4300;
-                let response = ResponseCode::SampleBatteryVoltage(voltage);
-                info!("SampleBattery: {:?}", response);
-                DataPoint::from(response).send(channel);
+            ControlOpCode::CaptureZero => {
+                device_state.capture_zero();
             }
-            // Currently unimplemented operations
-            ControlOpCode::Shutdown => {}
-        }
-    }
-}
+            ControlOpCode::AddLinearityPoint => {
+                if data.len() < 5 {
+                    error!("AddLinearityPoint: Invalid data length");
+                    return false;
+                }
+                if device_state.linearity_points.iter().all(Option::is_some) {
+                    error!("AddLinearityPoint: Too many points");
+                    return false;
+                }
 
-impl From<u8> for ControlOpCode {
-    fn from(op_code: u8) -> Self {
-        match op_code {
-            0x64 => ControlOpCode::TareScale,
-            0x65 => ControlOpCode::StartMeasurement,
-            0x66 => ControlOpCode::StopMeasurement,
-            0x6E => ControlOpCode::Shutdown,
-            0x6F => ControlOpCode::SampleBattery,
-            0x70 => ControlOpCode::GetProgressorId,
-            0x6B => ControlOpCode::GetAppVersion,
-            0x72 => ControlOpCode::GetCalibration,
-            0x73 => ControlOpCode::AddCalibrationPoint,
-            0x74 => ControlOpCode::DefaultCalibration,
-            _ => {
-                error!("Invalid OpCode received: {:#x}", op_code);
-                ControlOpCode::StopMeasurement
+                let weight = command_f32(data, 1);
+                device_state.add_linearity_point(weight);
+                debug!("Received AddLinearityPoint command with weight: {}", weight);
             }
-        }
-    }
-}
+            ControlOpCode::CheckLinearity => {
+                let Some((max_error_pct, passed)) = device_state.check_linearity() else {
+                    error!("CheckLinearity: Not enough points");
+                    return false;
+                };
 
-impl Format for ControlOpCode {
-    fn format(&self, fmt: defmt::Formatter) {
-        match self {
-            ControlOpCode::TareScale => defmt::write!(fmt, "TareScale"),
-            ControlOpCode::StartMeasurement => defmt::write!(fmt, "StartMeasurement"),
-            ControlOpCode::StopMeasurement => defmt::write!(fmt, "StopMeasurement"),
-            ControlOpCode::GetAppVersion => defmt::write!(fmt, "GetAppVersion"),
-            ControlOpCode::Shutdown => defmt::write!(fmt, "Shutdown"),
-            ControlOpCode::SampleBattery => defmt::write!(fmt, "SampleBattery"),
-            ControlOpCode::GetProgressorId => defmt::write!(fmt, "GetProgressorId"),
-            ControlOpCode::GetCalibration => defmt::write!(fmt, "GetCalibration"),
-            ControlOpCode::AddCalibrationPoint => defmt::write!(fmt, "AddCalibrationPoint"),
-            ControlOpCode::DefaultCalibration => defmt::write!(fmt, "DefaultCalibration"),
-        }
-    }
-}
+                let response = ResponseCode::LinearityResult {
+                    max_error_pct,
+                    passed,
+                };
+                info!("Linearity: {:?}", response);
+                DataPoint::from(response).send(channel);
+            }
+            ControlOpCode::ExportConfig => {
+                // The measurement task owns the load cell and its calibration
+                device_state.config_export_requested = true;
+            }
+            ControlOpCode::ImportConfig => {
+                if data.len() < 2 {
+                    error!("ImportConfig: Invalid data length");
+                    return false;
+                }
 
-/// Data point characteristic is where we receive data from the Progressor
-#[derive(Copy, Debug, Clone)]
-#[repr(C, packed)]
-pub struct DataPoint {
-    /// Response code
-    pub(crate) response_code: u8,
-    /// Length of the data
-    pub(crate) length: u8,
-    /// Data
-    pub(crate) value: [u8; MAX_PAYLOAD_SIZE],
-}
+                match device_state
+                    .config_import
+                    .add_chunk(data[1] as usize, &data[2..])
+                {
+                    None => {}
+                    Some(Ok(config)) => {
+                        debug!("Configuration imported: {:?}", config.calibration);
+                        device_state.imported_calibration = Some(config.calibration);
+                        if config.name.is_some() {
+                            device_state.pending_name = config.name;
+                        }
+                    }
+                    Some(Err(e)) => {
+                        error!("ImportConfig: {:?}", e);
+                        DataPoint::from(ResponseCode::ConfigImported(false)).send(channel);
+                        return false;
+                    }
+                }
+            }
+            ControlOpCode::SetRawReporting => {
+                if data.len() < 2 {
+                    error!("SetRawReporting: Invalid data length");
+                    return false;
+                }
 
-// Thread-local buffer for preparing GATT data
-struct SyncUnsafeCell<T>(UnsafeCell<T>);
+                let enabled = data[1] != 0;
+                device_state.set_report_raw(enabled);
+                debug!("Raw reporting enabled: {}", enabled);
+            }
+            ControlOpCode::SetImpulseReportInterval => {
+                if data.len() < 3 {
+                    error!("SetImpulseReportInterval: Invalid data length");
+                    return false;
+                }
 
-unsafe impl<T> Sync for SyncUnsafeCell<T> {}
+                let interval = command_u16(data, 1) as u32;
+                device_state.set_impulse_report_interval(interval);
+                debug!("Impulse report interval set to {} samples", interval);
+            }
+            ControlOpCode::SetMaxWeightStep => {
+                if data.len() < 5 {
+                    error!("SetMaxWeightStep: Invalid data length");
+                    return false;
+                }
 
-static GATT_BUFFER: SyncUnsafeCell<[u8; MAX_PAYLOAD_SIZE + 2]> =
-    SyncUnsafeCell(UnsafeCell::new([0; MAX_PAYLOAD_SIZE + 2]));
+                let max_step = command_f32(data, 1);
 
-impl AsGatt for DataPoint {
-    const MIN_SIZE: usize = 3;
-    const MAX_SIZE: usize = MAX_PAYLOAD_SIZE + 2; // +2 for response_code and length
+                // Non-positive (or NaN) values disable the limiter
+                let max_step = if max_step > 0.0 {
+                    max_step
+                } else {
+                    f32::INFINITY
+                };
+                device_state.set_max_weight_step(max_step);
+                debug!("Max weight step set to {}kg", max_step);
+            }
+            ControlOpCode::SetMeasureDuration => {
+                if data.len() < 3 {
+                    error!("SetMeasureDuration: Invalid data length");
+                    return false;
+                }
 
-    fn as_gatt(&self) -> &[u8] {
-        let buffer = unsafe { &mut *GATT_BUFFER.0.get() };
+                let seconds = command_u16(data, 1);
+                device_state.set_measure_duration(seconds);
+                debug!("Measure duration set to {}s", seconds);
+            }
+            ControlOpCode::GetCalibrationPoints => {
+                for (index, point) in device_state.calibration_points.iter().enumerate() {
+                    if let Some(point) = point {
+                        let response = ResponseCode::CalibrationPoint {
+                            index: index as u8,
+                            raw: point.raw,
+                            weight: point.weight,
+                        };
+                        info!("CalibrationPoint: {:?}", response);
+                        DataPoint::from(response).send(channel);
+                    }
+                }
+            }
+            ControlOpCode::DumpRawBuffer => {
+                device_state.raw_dump_requested = true;
+            }
+            ControlOpCode::SetAutoTare => {
+                let Some(mode) = data.get(1).copied().and_then(AutoTareMode::from_u8) else {
+                    error!("SetAutoTare: Invalid mode");
+                    return false;
+                };
 
-        buffer[0] = self.response_code;
-        buffer[1] = self.length;
+                device_state.auto_tare = mode;
+                debug!("Auto-tare mode set to {:?}", mode);
+            }
+            ControlOpCode::SetFilterMode => {
+                let mode = match data.get(1).copied() {
+                    Some(FILTER_MODE_EMA) if data.len() >= 6 => {
+                        FilterMode::ema(command_f32(data, 2))
+                    }
+                    value => value.and_then(FilterMode::from_u8),
+                };
+                let Some(mode) = mode else {
+                    error!("SetFilterMode: Invalid mode");
+                    return false;
+                };
 
-        if self.length > 0 {
-            buffer[2..2 + self.length as usize]
-                .copy_from_slice(&self.value[..self.length as usize]);
-        }
+                device_state.filter_mode = mode;
+                debug!("Filter mode set to {:?}", mode);
+            }
+            ControlOpCode::SetSettledReporting => {
+                if data.len() < 2 {
+                    error!("SetSettledReporting: Invalid data length");
+                    return false;
+                }
+
+                device_state.report_settled = data[1] != 0;
+                debug!("Settled reporting enabled: {}", device_state.report_settled);
+            }
+            ControlOpCode::Reboot => {
+                // The measurement task resets the device once no operation is in progress
+                device_state.stop_measurement();
+                device_state.reboot_requested = true;
+            }
+            ControlOpCode::Shutdown => {
+                // The measurement task powers off once no operation is in progress
+                device_state.stop_measurement();
+                device_state.shutdown_requested = true;
+            }
+            ControlOpCode::SetBodyweight => {
+                if data.len() < 5 {
+                    error!("SetBodyweight: Invalid data length");
+                    return false;
+                }
+
+                let bodyweight = command_f32(data, 1);
+                // Infinity would report every measurement as 0%
+                if !bodyweight.is_finite() {
+                    error!("SetBodyweight: Invalid bodyweight");
+                    return false;
+                }
+
+                device_state.bodyweight = (bodyweight > 0.0).then_some(bodyweight);
+                debug!("Bodyweight set to {:?}", device_state.bodyweight);
+            }
+            ControlOpCode::SetLogLevel => {
+                let Some(level) = data.get(1).copied().and_then(LogLevel::from_u8) else {
+                    error!("SetLogLevel: Invalid level");
+                    return false;
+                };
+
+                logging::set_log_level(level);
+                info!("Log level set to {:?}", level);
+            }
+            ControlOpCode::SetRepThresholds => {
+                if data.len() < 9 {
+                    error!("SetRepThresholds: Invalid data length");
+                    return false;
+                }
+
+                let on = command_f32(data, 1);
+                let off = command_f32(data, 5);
+                // Comparisons with NaN are false, so a NaN off threshold would never end a rep.
+                // A NaN on threshold doesn't disable the counter either, it is invalid
+                let enabled = on > 0.0 || on.is_nan();
+                if enabled && !(on.is_finite() && off.is_finite() && off <= on) {
+                    error!("SetRepThresholds: Invalid thresholds");
+                    return false;
+                }
+
+                device_state.rep_thresholds = enabled.then_some(RepThresholds {
+                    on,
+                    off,
+                    min_duration_ms: device_state.rep_min_duration_ms,
+                });
+                debug!("Rep thresholds set to on: {}kg, off: {}kg", on, off);
+            }
+            ControlOpCode::SetRepMinDuration => {
+                if data.len() < 3 {
+                    error!("SetRepMinDuration: Invalid data length");
+                    return false;
+                }
+
+                let min_duration_ms = command_u16(data, 1);
+                device_state.rep_min_duration_ms = min_duration_ms;
+                if let Some(thresholds) = device_state.rep_thresholds.as_mut() {
+                    thresholds.min_duration_ms = min_duration_ms;
+                }
+                debug!("Rep minimum duration set to {}ms", min_duration_ms);
+            }
+            ControlOpCode::SetReportOnChange => {
+                if data.len() < 7 {
+                    error!("SetReportOnChange: Invalid data length");
+                    return false;
+                }
+
+                let delta = command_f32(data, 1);
+                let heartbeat_ms = command_u16(data, 5);
+                // Infinity would only report the heartbeat samples
+                if !delta.is_finite() {
+                    error!("SetReportOnChange: Invalid delta");
+                    return false;
+                }
+                device_state.report_on_change = (delta > 0.0).then_some(ReportOnChange {
+                    delta,
+                    heartbeat_ms,
+                });
+                debug!(
+                    "Report on change set to delta: {}kg, heartbeat: {}ms",
+                    delta, heartbeat_ms
+                );
+            }
+            ControlOpCode::GetTemperature => {
+                // The measurement task owns the temperature sensor
+                device_state.temperature_requested = true;
+            }
+            ControlOpCode::GetPeakRfd => {
+                // The measurement task tracks the peak
+                device_state.peak_rfd_requested = true;
+            }
+            ControlOpCode::GetDriftEstimate => {
+                // The measurement task owns the drift estimator
+                device_state.drift_requested = true;
+            }
+            ControlOpCode::SetPlausibleRange => {
+                if data.len() < 9 {
+                    error!("SetPlausibleRange: Invalid data length");
+                    return false;
+                }
+
+                let min = command_f32(data, 1);
+                let max = command_f32(data, 5);
+                if min.is_nan() || max.is_nan() || min >= max {
+                    error!("SetPlausibleRange: Invalid range {}..{}", min, max);
+                    return false;
+                }
+
+                device_state.plausible_range = (min, max);
+                debug!("Plausible range set to {}kg..{}kg", min, max);
+            }
+            ControlOpCode::SetEnvelopeWindow => {
+                if data.len() < 3 {
+                    error!("SetEnvelopeWindow: Invalid data length");
+                    return false;
+                }
+
+                let window = command_u16(data, 1) as u32;
+                device_state.envelope_window = window;
+                debug!("Envelope window set to {} samples", window);
+            }
+            ControlOpCode::SetCommandAcks => {
+                if data.len() < 2 {
+                    error!("SetCommandAcks: Invalid data length");
+                    return false;
+                }
+
+                device_state.command_acks = data[1] != 0;
+                debug!(
+                    "Command acknowledgements enabled: {}",
+                    device_state.command_acks
+                );
+            }
+            ControlOpCode::SetAveraging => {
+                if data.len() < 3 {
+                    error!("SetAveraging: Invalid data length");
+                    return false;
+                }
+
+                let samples = command_u16(data, 1).clamp(1, MAX_AVERAGING_SAMPLES);
+                device_state.averaging_samples = samples;
+                debug!("Averaging set to {} samples", samples);
+            }
+            ControlOpCode::GetAveraging => {
+                let response = ResponseCode::Averaging(device_state.averaging_samples);
+                info!("Averaging: {:?}", response);
+                DataPoint::from(response).send(channel);
+            }
+            ControlOpCode::SetHeartbeatInterval => {
+                if data.len() < 3 {
+                    error!("SetHeartbeatInterval: Invalid data length");
+                    return false;
+                }
+
+                let interval_ms = command_u16(data, 1);
+                device_state.heartbeat_interval_ms = interval_ms;
+                debug!("Heartbeat interval set to {}ms", interval_ms);
+            }
+            ControlOpCode::SetProtocolMode => {
+                let Some(mode) = data.get(1).copied().and_then(ProtocolMode::from_u8) else {
+                    error!("SetProtocolMode: Invalid mode");
+                    return false;
+                };
+
+                device_state.protocol_mode = mode;
+                debug!("Protocol mode set to {:?}", mode);
+            }
+            ControlOpCode::SetRateWindow => {
+                if data.len() < 3 {
+                    error!("SetRateWindow: Invalid data length");
+                    return false;
+                }
+
+                let window_ms = command_u16(data, 1).min(MAX_RATE_WINDOW_MS);
+                device_state.rate_window_ms = window_ms;
+                debug!("Rate window set to {}ms", window_ms);
+            }
+            ControlOpCode::SetTimestampUnit => {
+                let Some(unit) = data.get(1).copied().and_then(TimestampUnit::from_u8) else {
+                    error!("SetTimestampUnit: Invalid unit");
+                    return false;
+                };
+
+                device_state.timestamp_unit = unit;
+                debug!("Timestamp unit set to {:?}", unit);
+            }
+            ControlOpCode::SetWeightUnit => {
+                let Some(unit) = data.get(1).copied().and_then(WeightUnit::from_u8) else {
+                    error!("SetWeightUnit: Invalid unit");
+                    return false;
+                };
+
+                device_state.weight_unit = unit;
+                debug!("Weight unit set to {:?}", unit);
+            }
+            ControlOpCode::SetAveragingWindow => {
+                if data.len() < 3 {
+                    error!("SetAveragingWindow: Invalid data length");
+                    return false;
+                }
+
+                let window_ms = command_u16(data, 1).min(MAX_AVERAGING_WINDOW_MS);
+                device_state.averaging_window_ms = window_ms;
+                debug!("Averaging window set to {}ms", window_ms);
+            }
+            ControlOpCode::SetFilterStepReset => {
+                if data.len() < 5 {
+                    error!("SetFilterStepReset: Invalid data length");
+                    return false;
+                }
+
+                let threshold = command_f32(data, 1);
+                device_state.filter_step_reset = if threshold.is_finite() && threshold > 0.0 {
+                    threshold
+                } else {
+                    f32::INFINITY
+                };
+                debug!(
+                    "Filter step reset threshold set to {}kg",
+                    device_state.filter_step_reset
+                );
+            }
+            ControlOpCode::SetName => {
+                match DeviceName::new(data.get(1..).unwrap_or_default()) {
+                    Ok(name) => {
+                        // The measurement task stores it and reboots
+                        device_state.pending_name = Some(name);
+                        debug!("Device name set to {}", name.as_str());
+                    }
+                    Err(e) => {
+                        error!("SetName: {}", e);
+                        DataPoint::from(ResponseCode::NameSet(false)).send(channel);
+                        return false;
+                    }
+                }
+            }
+            ControlOpCode::SetProgressorId => {
+                if data.len() < 1 + DEVICE_ID_SIZE {
+                    error!("SetProgressorId: Invalid data length");
+                    DataPoint::from(ResponseCode::ProgressorIdSet(false)).send(channel);
+                    return false;
+                }
+                // Reverse the bytes as they are LE, like in `ProgressorId`
+                let mut id = [0u8; DEVICE_ID_SIZE];
+                id.copy_from_slice(&data[1..1 + DEVICE_ID_SIZE]);
+                id.reverse();
+                // The measurement task stores it
+                device_state.pending_device_id = Some(id);
+                debug!("Progressor ID set to {:x}", id);
+            }
+            ControlOpCode::SetBaselineSubtraction => {
+                if data.len() < 2 {
+                    error!("SetBaselineSubtraction: Invalid data length");
+                    return false;
+                }
+
+                device_state.baseline_subtraction = data[1] != 0;
+                debug!(
+                    "Baseline subtraction enabled: {}",
+                    device_state.baseline_subtraction
+                );
+            }
+            ControlOpCode::SetSignedMode => {
+                if data.len() < 2 {
+                    error!("SetSignedMode: Invalid data length");
+                    return false;
+                }
+
+                device_state.signed_mode = data[1] != 0;
+                debug!("Signed mode enabled: {}", device_state.signed_mode);
+            }
+            ControlOpCode::GetDiagnostics => {
+                let response = ResponseCode::Diagnostics {
+                    uptime_ms: Instant::now().as_millis() as u32,
+                    reset_reason: device_state.reset_reason,
+                };
+                info!("Diagnostics: {:?}", response);
+                DataPoint::from(response).send(channel);
+            }
+            ControlOpCode::SetCoalesceWindow => {
+                if data.len() < 3 {
+                    error!("SetCoalesceWindow: Invalid data length");
+                    return false;
+                }
+
+                let window_ms = command_u16(data, 1);
+                device_state.coalesce_window_ms = window_ms;
+                debug!("Coalesce window set to {}ms", window_ms);
+            }
+            ControlOpCode::StartGuidedCalibration => {
+                device_state.start_guided_calibration();
+                DataPoint::from(ResponseCode::GuidedCalibration(
+                    GuidedCalibrationStep::Started,
+                ))
+                .send(channel);
+            }
+            ControlOpCode::GuidedCaptureZero => {
+                if !device_state.guided_capture_zero() {
+                    error!("GuidedCaptureZero: No guided calibration waiting for a capture");
+                    return false;
+                }
+            }
+            ControlOpCode::GuidedCaptureSpan => {
+                if data.len() < 5 {
+                    error!("GuidedCaptureSpan: Invalid data length");
+                    return false;
+                }
+
+                let weight = command_f32(data, 1);
+                if !(weight.is_finite() && weight > 0.0) {
+                    error!("GuidedCaptureSpan: Invalid weight {}", weight);
+                    return false;
+                }
+                if !device_state.guided_capture_span(weight) {
+                    error!("GuidedCaptureSpan: No guided calibration waiting for the span");
+                    return false;
+                }
+                debug!("Capturing the span with {}g", weight);
+            }
+            ControlOpCode::GetOverloadCount => {
+                let response = ResponseCode::OverloadCount(device_state.overload_count);
+                info!("OverloadCount: {:?}", response);
+                DataPoint::from(response).send(channel);
+            }
+            ControlOpCode::GetTareState => {
+                let response = ResponseCode::TareState(device_state.tared);
+                info!("TareState: {:?}", response);
+                DataPoint::from(response).send(channel);
+            }
+            ControlOpCode::GetFilterConfig => {
+                // The measurement task applies the device state to the load cell every loop
+                let response = ResponseCode::FilterConfig {
+                    filter_mode: device_state.filter_mode,
+                    averaging_samples: device_state.averaging_samples,
+                    max_weight_step: device_state.max_weight_step,
+                    report_on_change: device_state.report_on_change,
+                };
+                info!("FilterConfig: {:?}", response);
+                DataPoint::from(response).send(channel);
+            }
+            ControlOpCode::GetCapabilities => {
+                let response = ResponseCode::Capabilities(CAPABILITIES);
+                info!("Capabilities: {:?}", response);
+                DataPoint::from(response).send(channel);
+            }
+            ControlOpCode::SampleBattery => {
+                // The load cell draws current while measuring, which skews the reading
+                if device_state.measurement_status != MeasurementTaskStatus::Disabled {
+                    error!("SampleBattery: Measurement not stopped");
+                    DataPoint::from(ResponseCode::SampleBatteryVoltage(0)).send(channel);
+                    return false;
+                }
+
+                // The measurement task owns the ADC
+                device_state.battery_requested = true;
+            }
+        }
+        true
+    }
+}
+
+/// Get the device ID: the one set with `SetProgressorId`, or else the one built in
+pub fn device_id() -> Result<[u8; DEVICE_ID_SIZE], Error> {
+    match crate::device_id::load() {
+        Some(id) => Ok(id),
+        None => build_device_id(),
+    }
+}
+
+/// Get the device ID the firmware was built with, parsed from the `DEVICE_ID` hex string
+///
+/// IDs shorter than [`DEVICE_ID_SIZE`] bytes are padded with zeros. Fails if the string is not
+/// valid hex.
+fn build_device_id() -> Result<[u8; DEVICE_ID_SIZE], Error> {
+    /// Number of hex characters needed per byte (2 hex chars = 1 byte)
+    const HEX_CHARS_PER_BYTE: usize = 2;
+    /// Hex radix for parsing hex strings
+    const HEX_RADIX: u32 = 16;
+
+    let device_id = env!("DEVICE_ID");
+    let mut bytes = [0u8; DEVICE_ID_SIZE];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        let char_pos = i * HEX_CHARS_PER_BYTE;
+        let next_char_pos = char_pos + HEX_CHARS_PER_BYTE;
+        if next_char_pos <= device_id.len() {
+            *byte = u8::from_str_radix(&device_id[char_pos..next_char_pos], HEX_RADIX)
+                .map_err(|_| Error::Config)?;
+        }
+    }
+    Ok(bytes)
+}
+
+/// Decode a big-endian `f32` command argument starting at `offset`
+///
+/// The caller checks the length of the command first.
+fn command_f32(data: &[u8], offset: usize) -> f32 {
+    f32::from_be_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+/// Decode a big-endian `u16` command argument starting at `offset`
+///
+/// The caller checks the length of the command first.
+fn command_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([data[offset], data[offset + 1]])
+}
+
+impl From<u8> for ControlOpCode {
+    fn from(op_code: u8) -> Self {
+        match op_code {
+            0x64 => ControlOpCode::TareScale,
+            0x65 => ControlOpCode::StartMeasurement,
+            0x66 => ControlOpCode::StopMeasurement,
+            0x6E => ControlOpCode::Shutdown,
+            0x6F => ControlOpCode::SampleBattery,
+            0x70 => ControlOpCode::GetProgressorId,
+            0x6B => ControlOpCode::GetAppVersion,
+            0x72 => ControlOpCode::GetCalibration,
+            0x73 => ControlOpCode::AddCalibrationPoint,
+            0x74 => ControlOpCode::DefaultCalibration,
+            0x75 => ControlOpCode::SetRawReporting,
+            0x76 => ControlOpCode::SetImpulseReportInterval,
+            0x77 => ControlOpCode::SetMaxWeightStep,
+            0x78 => ControlOpCode::GetCapabilities,
+            0x79 => ControlOpCode::SetMeasureDuration,
+            0x7A => ControlOpCode::GetCalibrationPoints,
+            0x7B => ControlOpCode::DumpRawBuffer,
+            0x7C => ControlOpCode::SetAutoTare,
+            0x7D => ControlOpCode::SetFilterMode,
+            0x7E => ControlOpCode::SetSettledReporting,
+            0x7F => ControlOpCode::Reboot,
+            0x80 => ControlOpCode::SetBodyweight,
+            0x81 => ControlOpCode::SetLogLevel,
+            0x82 => ControlOpCode::PauseMeasurement,
+            0x83 => ControlOpCode::ResumeMeasurement,
+            0x84 => ControlOpCode::SetRepThresholds,
+            0x85 => ControlOpCode::SetRepMinDuration,
+            0x86 => ControlOpCode::SetReportOnChange,
+            0x87 => ControlOpCode::GetTemperature,
+            0x88 => ControlOpCode::SetPlausibleRange,
+            0x89 => ControlOpCode::QuickTare,
+            0x8A => ControlOpCode::SetEnvelopeWindow,
+            0x8B => ControlOpCode::SetCommandAcks,
+            0x8C => ControlOpCode::GetDriftEstimate,
+            0x8D => ControlOpCode::SetAveraging,
+            0x8E => ControlOpCode::GetAveraging,
+            0x8F => ControlOpCode::SetHeartbeatInterval,
+            0x90 => ControlOpCode::CaptureZero,
+            0x91 => ControlOpCode::ExportConfig,
+            0x92 => ControlOpCode::ImportConfig,
+            0x93 => ControlOpCode::SetSignedMode,
+            0x94 => ControlOpCode::SetProtocolMode,
+            0x95 => ControlOpCode::GetDiagnostics,
+            0x96 => ControlOpCode::SetCoalesceWindow,
+            0x97 => ControlOpCode::AddLinearityPoint,
+            0x98 => ControlOpCode::CheckLinearity,
+            0x99 => ControlOpCode::SetTimestampUnit,
+            0x9A => ControlOpCode::GetFilterConfig,
+            0x9B => ControlOpCode::SetRateWindow,
+            0x9C => ControlOpCode::GetTareState,
+            0x9D => ControlOpCode::SetBaselineSubtraction,
+            0x9E => ControlOpCode::SetName,
+            0x9F => ControlOpCode::SetFilterStepReset,
+            0xA0 => ControlOpCode::GetOverloadCount,
+            0xA1 => ControlOpCode::StartGuidedCalibration,
+            0xA2 => ControlOpCode::GuidedCaptureZero,
+            0xA3 => ControlOpCode::GuidedCaptureSpan,
+            0xA4 => ControlOpCode::GetPeakRfd,
+            0xA5 => ControlOpCode::SetProgressorId,
+            0xA6 => ControlOpCode::SoftTare,
+            0xA7 => ControlOpCode::SetWeightUnit,
+            0xA8 => ControlOpCode::SetAveragingWindow,
+            _ => {
+                error!("Invalid OpCode received: {:#x}", op_code);
+                ControlOpCode::StopMeasurement
+            }
+        }
+    }
+}
+
+impl Format for ControlOpCode {
+    fn format(&self, fmt: defmt::Formatter) {
+        match self {
+            ControlOpCode::TareScale => defmt::write!(fmt, "TareScale"),
+            ControlOpCode::StartMeasurement => defmt::write!(fmt, "StartMeasurement"),
+            ControlOpCode::StopMeasurement => defmt::write!(fmt, "StopMeasurement"),
+            ControlOpCode::GetAppVersion => defmt::write!(fmt, "GetAppVersion"),
+            ControlOpCode::Shutdown => defmt::write!(fmt, "Shutdown"),
+            ControlOpCode::SampleBattery => defmt::write!(fmt, "SampleBattery"),
+            ControlOpCode::GetProgressorId => defmt::write!(fmt, "GetProgressorId"),
+            ControlOpCode::GetCalibration => defmt::write!(fmt, "GetCalibration"),
+            ControlOpCode::AddCalibrationPoint => defmt::write!(fmt, "AddCalibrationPoint"),
+            ControlOpCode::DefaultCalibration => defmt::write!(fmt, "DefaultCalibration"),
+            ControlOpCode::SetRawReporting => defmt::write!(fmt, "SetRawReporting"),
+            ControlOpCode::SetImpulseReportInterval => {
+                defmt::write!(fmt, "SetImpulseReportInterval")
+            }
+            ControlOpCode::SetMaxWeightStep => defmt::write!(fmt, "SetMaxWeightStep"),
+            ControlOpCode::GetCapabilities => defmt::write!(fmt, "GetCapabilities"),
+            ControlOpCode::SetMeasureDuration => defmt::write!(fmt, "SetMeasureDuration"),
+            ControlOpCode::GetCalibrationPoints => defmt::write!(fmt, "GetCalibrationPoints"),
+            ControlOpCode::DumpRawBuffer => defmt::write!(fmt, "DumpRawBuffer"),
+            ControlOpCode::SetAutoTare => defmt::write!(fmt, "SetAutoTare"),
+            ControlOpCode::SetFilterMode => defmt::write!(fmt, "SetFilterMode"),
+            ControlOpCode::SetSettledReporting => defmt::write!(fmt, "SetSettledReporting"),
+            ControlOpCode::Reboot => defmt::write!(fmt, "Reboot"),
+            ControlOpCode::SetBodyweight => defmt::write!(fmt, "SetBodyweight"),
+            ControlOpCode::SetLogLevel => defmt::write!(fmt, "SetLogLevel"),
+            ControlOpCode::PauseMeasurement => defmt::write!(fmt, "PauseMeasurement"),
+            ControlOpCode::ResumeMeasurement => defmt::write!(fmt, "ResumeMeasurement"),
+            ControlOpCode::SetRepThresholds => defmt::write!(fmt, "SetRepThresholds"),
+            ControlOpCode::SetRepMinDuration => defmt::write!(fmt, "SetRepMinDuration"),
+            ControlOpCode::SetReportOnChange => defmt::write!(fmt, "SetReportOnChange"),
+            ControlOpCode::GetTemperature => defmt::write!(fmt, "GetTemperature"),
+            ControlOpCode::SetPlausibleRange => defmt::write!(fmt, "SetPlausibleRange"),
+            ControlOpCode::QuickTare => defmt::write!(fmt, "QuickTare"),
+            ControlOpCode::SetEnvelopeWindow => defmt::write!(fmt, "SetEnvelopeWindow"),
+            ControlOpCode::SetCommandAcks => defmt::write!(fmt, "SetCommandAcks"),
+            ControlOpCode::GetDriftEstimate => defmt::write!(fmt, "GetDriftEstimate"),
+            ControlOpCode::SetAveraging => defmt::write!(fmt, "SetAveraging"),
+            ControlOpCode::GetAveraging => defmt::write!(fmt, "GetAveraging"),
+            ControlOpCode::SetHeartbeatInterval => defmt::write!(fmt, "SetHeartbeatInterval"),
+            ControlOpCode::CaptureZero => defmt::write!(fmt, "CaptureZero"),
+            ControlOpCode::ExportConfig => defmt::write!(fmt, "ExportConfig"),
+            ControlOpCode::ImportConfig => defmt::write!(fmt, "ImportConfig"),
+            ControlOpCode::SetSignedMode => defmt::write!(fmt, "SetSignedMode"),
+            ControlOpCode::SetProtocolMode => defmt::write!(fmt, "SetProtocolMode"),
+            ControlOpCode::GetDiagnostics => defmt::write!(fmt, "GetDiagnostics"),
+            ControlOpCode::SetCoalesceWindow => defmt::write!(fmt, "SetCoalesceWindow"),
+            ControlOpCode::AddLinearityPoint => defmt::write!(fmt, "AddLinearityPoint"),
+            ControlOpCode::CheckLinearity => defmt::write!(fmt, "CheckLinearity"),
+            ControlOpCode::SetTimestampUnit => defmt::write!(fmt, "SetTimestampUnit"),
+            ControlOpCode::GetFilterConfig => defmt::write!(fmt, "GetFilterConfig"),
+            ControlOpCode::SetRateWindow => defmt::write!(fmt, "SetRateWindow"),
+            ControlOpCode::GetTareState => defmt::write!(fmt, "GetTareState"),
+            ControlOpCode::SetBaselineSubtraction => {
+                defmt::write!(fmt, "SetBaselineSubtraction")
+            }
+            ControlOpCode::SetName => defmt::write!(fmt, "SetName"),
+            ControlOpCode::SetFilterStepReset => defmt::write!(fmt, "SetFilterStepReset"),
+            ControlOpCode::GetOverloadCount => defmt::write!(fmt, "GetOverloadCount"),
+            ControlOpCode::StartGuidedCalibration => defmt::write!(fmt, "StartGuidedCalibration"),
+            ControlOpCode::GuidedCaptureZero => defmt::write!(fmt, "GuidedCaptureZero"),
+            ControlOpCode::GuidedCaptureSpan => defmt::write!(fmt, "GuidedCaptureSpan"),
+            ControlOpCode::GetPeakRfd => defmt::write!(fmt, "GetPeakRfd"),
+            ControlOpCode::SetProgressorId => defmt::write!(fmt, "SetProgressorId"),
+            ControlOpCode::SoftTare => defmt::write!(fmt, "SoftTare"),
+            ControlOpCode::SetWeightUnit => defmt::write!(fmt, "SetWeightUnit"),
+            ControlOpCode::SetAveragingWindow => defmt::write!(fmt, "SetAveragingWindow"),
+        }
+    }
+}
+
+/// Data point characteristic is where we receive data from the Progressor
+#[derive(Copy, Debug, Clone)]
+#[repr(C, packed)]
+pub struct DataPoint {
+    /// Response code
+    pub(crate) response_code: u8,
+    /// Length of the data
+    pub(crate) length: u8,
+    /// Data
+    pub(crate) value: [u8; MAX_PAYLOAD_SIZE],
+}
+
+// Thread-local buffer for preparing GATT data
+#[cfg(target_arch = "riscv32")]
+struct SyncUnsafeCell<T>(UnsafeCell<T>);
+
+#[cfg(target_arch = "riscv32")]
+unsafe impl<T> Sync for SyncUnsafeCell<T> {}
+
+#[cfg(target_arch = "riscv32")]
+static GATT_BUFFER: SyncUnsafeCell<[u8; MAX_PAYLOAD_SIZE + 2]> =
+    SyncUnsafeCell(UnsafeCell::new([0; MAX_PAYLOAD_SIZE + 2]));
+
+#[cfg(target_arch = "riscv32")]
+impl AsGatt for DataPoint {
+    const MIN_SIZE: usize = 2; // Responses without data, e.g. LowPowerWarning
+    const MAX_SIZE: usize = MAX_PAYLOAD_SIZE + 2; // +2 for response_code and length
+
+    /// Only the bytes that fit in the payload are copied, and the returned slice covers exactly
+    /// the bytes written.
+    fn as_gatt(&self) -> &[u8] {
+        let buffer = unsafe { &mut *GATT_BUFFER.0.get() };
+        let len = (self.length as usize).min(MAX_PAYLOAD_SIZE);
+
+        buffer[0] = self.response_code;
+        buffer[1] = len as u8;
+        buffer[2..2 + len].copy_from_slice(&self.value[..len]);
 
-        unsafe { core::slice::from_raw_parts(buffer.as_ptr(), 2 + self.length as usize) }
+        &buffer[..2 + len]
     }
 }
 
+#[cfg(target_arch = "riscv32")]
 impl FromGatt for DataPoint {
     fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
-        Ok(DataPoint::new(data[0], data[1], &data[2..]))
+        match data {
+            [response_code, length, value @ ..] => {
+                Ok(DataPoint::new(*response_code, *length, value))
+            }
+            _ => Err(FromGattError::InvalidLength),
+        }
     }
 }
 
@@ -300,27 +1904,43 @@ impl Default for DataPoint {
 
 impl DataPoint {
     /// Create a new data point with specified response code, length and data
+    ///
+    /// The length is truncated to the data available and to [`MAX_PAYLOAD_SIZE`].
     pub fn new(response_code: u8, length: u8, data: &[u8]) -> Self {
         let mut value = [0; MAX_PAYLOAD_SIZE];
-        let len = length.min(MAX_PAYLOAD_SIZE as u8) as usize;
-        if len > 0 && !data.is_empty() {
-            value[..len.min(data.len())].copy_from_slice(&data[..len.min(data.len())]);
-        }
+        let len = (length as usize).min(MAX_PAYLOAD_SIZE).min(data.len());
+        value[..len].copy_from_slice(&data[..len]);
 
         Self {
             response_code,
-            length,
+            length: len as u8,
             value,
         }
     }
 
-    /// Send data point to the channel
-    pub fn send(&self, channel: &'static DataPointChannel) {
+    /// Send data point to the channel, without waiting. Returns whether it was queued
+    pub fn send(&self, channel: &'static DataPointChannel) -> bool {
         if channel.try_send(*self).is_err() {
             error!("Failed to send data point: channel full or receiver dropped");
-        } else {
+            return false;
+        }
+        if logging::enabled(LogLevel::Trace) {
             trace!("Sent data point successfully");
         }
+        true
+    }
+
+    /// Check whether the data point can be sent in the given protocol mode
+    ///
+    /// Extension responses use their own response codes, except the responses to extension
+    /// queries, which share the command response code and are only sent when asked for.
+    pub fn allowed_in(&self, mode: ProtocolMode) -> bool {
+        mode == ProtocolMode::Extended || matches!(self.response_code, 0x00 | 0x01 | 0x04)
+    }
+
+    /// Get the data bytes of the data point
+    pub fn payload(&self) -> &[u8] {
+        &self.value[..self.length as usize]
     }
 
     /// Create a weight measurement data point
@@ -358,6 +1978,11 @@ pub enum ResponseCode {
     /// Response to battery voltage sampling command
     SampleBatteryVoltage(u32),
     /// Each measurement is sent together with a timestamp where the timestamp is the number of microseconds since the measurement was started
+    ///
+    /// Milliseconds are sent instead when set with `SetTimestampUnit`, and pounds instead of
+    /// kilograms when set with `SetWeightUnit`.
+    ///
+    /// The weight is signed: pushes are negative, as are small readings around the tare.
     WeightMeasurement(f32, u32),
     /// Low power warning indicating that the battery is empty. The Progressor will turn itself off after sending this warning
     LowPowerWarning,
@@ -365,6 +1990,140 @@ pub enum ResponseCode {
     AppVersion(&'static [u8]),
     /// Response to progressor ID request command
     ProgressorId([u8; DEVICE_ID_SIZE]),
+    /// Weight measurement (kg) together with the raw reading it was computed from
+    WeightWithRaw { weight: f32, raw: i32 },
+    /// Impulse (kg·s) accumulated since the measurement was started
+    Impulse(f32),
+    /// Response to capabilities request command, see the `CAPABILITY_*` bits
+    Capabilities(u32),
+    /// Response to calibration points request command
+    CalibrationPoint { index: u8, raw: f32, weight: f32 },
+    /// Raw reading streamed by a raw buffer dump, oldest first
+    RawValue(i32),
+    /// Response to chip temperature request command, in °C
+    ///
+    /// The internal sensor measures the die temperature, which runs above ambient, and is only
+    /// accurate to a few °C. It is meant to correlate drift with warm-up, not as a thermometer.
+    Temperature(f32),
+    /// Response to drift estimate request command, in kg, NaN until an unloaded reading is seen
+    ///
+    /// Only meaningful when the load cell has been left unloaded for minutes after a tare.
+    DriftEstimate(f32),
+    /// Response to averaging request command, in samples per measurement
+    Averaging(u16),
+    /// Response to calibration request command, with the stored zero reference (raw reading
+    /// with no load) and scale (kg per raw count)
+    CalibrationValues { zero_raw: i32, scale: f32 },
+    /// Sent periodically while connected but not measuring, with the uptime in milliseconds.
+    /// Suppressed during measurements, where the data stream shows the device is alive
+    Heartbeat(u32),
+    /// Sent when the second calibration point is captured, with whether the calibration
+    /// computed from both points was applied
+    CalibrationResult(bool),
+    /// Chunk of an exported configuration blob: its offset in the blob and `len` bytes
+    ConfigChunk {
+        offset: u8,
+        len: u8,
+        data: [u8; CONFIG_CHUNK_SIZE],
+    },
+    /// Sent when a configuration import ends, with whether it was applied
+    ConfigImported(bool),
+    /// Command (op code) rejected because a tare or calibration is in progress
+    CommandRejected(u8),
+    /// Measurement rate in Hz, sent when it stays below the expected rate, see `RateMonitor`
+    RateWarning(f32),
+    /// Response to diagnostics request command, with the milliseconds since boot and the cause
+    /// of the last reset
+    Diagnostics {
+        uptime_ms: u32,
+        reset_reason: ResetReason,
+    },
+    /// Weight measurements coalesced over a window: the timestamp of the first one followed by
+    /// `count` weights (f32), in the order they were measured
+    ///
+    /// Timestamps of the following weights are not sent, they are spaced by the sample period.
+    CoalescedWeights {
+        timestamp: u32,
+        count: u8,
+        weights: [f32; MAX_COALESCED_SAMPLES],
+    },
+    /// Weight measurement (kg) together with its rate of change (kg/s), smoothed over the rate
+    /// window. Both are percentages of the bodyweight when one is set
+    ForceAndRate { force: f32, rate: f32 },
+    /// Result of a linearity check: the worst deviation from a straight line, in percent of
+    /// the heaviest mass, and whether it is within the tolerance
+    LinearityResult { max_error_pct: f32, passed: bool },
+    /// Response to filter configuration request command, packed as:
+    ///
+    /// | Offset | Size | Field                                                     |
+    /// |--------|------|-----------------------------------------------------------|
+    /// | 0      | 1    | Filter mode, see `FilterMode`                             |
+    /// | 1      | 2    | Samples averaged per measurement (u16)                    |
+    /// | 3      | 4    | Slew-rate limiter max step in kg (f32), infinity when off |
+    /// | 7      | 4    | Report-on-change delta in kg (f32), zero when off         |
+    /// | 11     | 2    | Report-on-change heartbeat in ms (u16), zero when off     |
+    /// | 13     | 2    | EMA smoothing factor (u16, 65535 is one), zero when off   |
+    FilterConfig {
+        filter_mode: FilterMode,
+        averaging_samples: u16,
+        max_weight_step: f32,
+        report_on_change: Option<ReportOnChange>,
+    },
+    /// Progress of a guided calibration, sent after each step
+    GuidedCalibration(GuidedCalibrationStep),
+    /// Peak rate of force development of a measurement (kg/s, or percentage of the bodyweight
+    /// per second when one is set), see `PeakRfd`. Sent when requested and when the
+    /// measurement stops, before its checksum
+    PeakRfd(f32),
+    /// Sent when a new device name is rejected, or once it is stored, with whether it was.
+    /// The device then reboots to advertise it
+    NameSet(bool),
+    /// Sent when a new Progressor ID is rejected, or once it is stored, with whether it was
+    ProgressorIdSet(bool),
+    /// Sent when readings start hitting the ADC limits, the load is beyond what the load cell
+    /// can measure. No weight measurements are sent until the readings are back in range
+    LoadCellSaturated,
+    /// Sent before the device resets or powers off, and in response to commands received
+    /// meanwhile
+    ShuttingDown,
+    /// Response to tare state request command, with whether the scale has been tared
+    TareState(bool),
+    /// Response to overload count request command, with the number of overload events over
+    /// the lifetime of the device
+    OverloadCount(u32),
+    /// Sent when a measurement is started before the scale has been tared, before it is
+    /// auto-tared (depending on the auto-tare mode). Any load at that point is measured, or
+    /// tared away
+    TareRequired,
+    /// Sent when weights start being non-finite (NaN or infinite), which are replaced by zero.
+    /// Points to a broken calibration
+    InvalidWeight,
+    /// Weight (kg) out of the plausible range, sent when readings start being clamped
+    WeightOutOfRange(f32),
+    /// Acknowledges a measurement start, with the start time (device clock, in microseconds,
+    /// truncated to a `u32`) that the timestamps of the measurement are relative to
+    MeasurementStarted(u32),
+    /// Sent when a measurement stops, after its last weight data point and its checksum, with
+    /// the number of samples measured (including unreported ones) and the timestamp of the
+    /// last one in milliseconds
+    MeasurementStopped { samples: u32, duration_ms: u32 },
+    /// CRC-32 of the weight data points sent during a measurement, sent when it stops.
+    /// See `SessionChecksum` for the algorithm
+    SessionChecksum(u32),
+    /// Number of reps counted since the measurement was started
+    RepCount(u16),
+    /// Load cell fault detected at startup, sent when a client connects
+    LoadCellFault(LoadCellFault),
+    /// Acknowledges a command with whether it was accepted
+    CommandAck { op_code: u8, ok: bool },
+    /// Mean, min and max weight over an envelope window
+    WeightEnvelope { mean: f32, min: f32, max: f32 },
+    /// Weight measurement with a flag telling whether the filter had settled
+    WeightWithSettled {
+        weight: f32,
+        timestamp: u32,
+        settled: bool,
+    },
 }
 
 impl Format for ResponseCode {
@@ -384,55 +2143,1315 @@ impl Format for ResponseCode {
             ResponseCode::LowPowerWarning => defmt::write!(fmt, "LowPowerWarning"),
             ResponseCode::AppVersion(version) => defmt::write!(fmt, "AppVersion: {:x}", version),
             ResponseCode::ProgressorId(id) => defmt::write!(fmt, "ProgressorId: {:x}", id),
-        }
-    }
-}
-
-impl ResponseCode {
-    /// Get the operation code for this response
-    fn op_code(&self) -> u8 {
-        match self {
-            ResponseCode::SampleBatteryVoltage(..)
-            | ResponseCode::AppVersion(..)
-            | ResponseCode::ProgressorId(..) => 0x00,
-            ResponseCode::WeightMeasurement(..) => 0x01,
-            ResponseCode::LowPowerWarning => 0x04,
-        }
-    }
-
-    /// Get the length of the data for this response
-    fn length(&self) -> u8 {
-        match self {
-            ResponseCode::SampleBatteryVoltage(..) => 4,
-            ResponseCode::WeightMeasurement(..) => 8,
-            ResponseCode::LowPowerWarning => 0,
-            ResponseCode::AppVersion(version) => version.len() as u8,
-            ResponseCode::ProgressorId(..) => DEVICE_ID_SIZE as u8,
-        }
-    }
-
-    /// Get the value bytes for this response
-    fn value(&self) -> [u8; MAX_PAYLOAD_SIZE] {
-        let mut value = [0; MAX_PAYLOAD_SIZE];
-        match self {
-            ResponseCode::SampleBatteryVoltage(voltage) => {
-                value[0..4].copy_from_slice(&voltage.to_le_bytes());
+            ResponseCode::WeightWithRaw { weight, raw } => {
+                defmt::write!(fmt, "WeightWithRaw: Weight: {}, Raw: {}", weight, raw)
             }
-            ResponseCode::WeightMeasurement(weight, timestamp) => {
-                value[0..4].copy_from_slice(&weight.to_le_bytes());
-                value[4..8].copy_from_slice(&timestamp.to_le_bytes());
+            ResponseCode::Impulse(impulse) => defmt::write!(fmt, "Impulse: {}", impulse),
+            ResponseCode::Capabilities(capabilities) => {
+                defmt::write!(fmt, "Capabilities: {:#b}", capabilities)
             }
-            ResponseCode::LowPowerWarning => (),
-            ResponseCode::ProgressorId(id) => {
-                // Reverse the bytes as they are LE
-                let mut reversed = *id;
-                reversed.reverse();
-                value[..DEVICE_ID_SIZE].copy_from_slice(&reversed);
+            ResponseCode::CalibrationPoint { index, raw, weight } => {
+                defmt::write!(
+                    fmt,
+                    "CalibrationPoint: Index: {}, Raw: {}, Weight: {}",
+                    index,
+                    raw,
+                    weight
+                )
+            }
+            ResponseCode::RawValue(raw) => defmt::write!(fmt, "RawValue: {}", raw),
+            ResponseCode::LoadCellFault(fault) => defmt::write!(fmt, "LoadCellFault: {}", fault),
+            ResponseCode::RepCount(count) => defmt::write!(fmt, "RepCount: {}", count),
+            ResponseCode::SessionChecksum(checksum) => {
+                defmt::write!(fmt, "SessionChecksum: {:#x}", checksum)
+            }
+            ResponseCode::MeasurementStarted(start_time) => {
+                defmt::write!(fmt, "MeasurementStarted: {}", start_time)
+            }
+            ResponseCode::WeightOutOfRange(weight) => {
+                defmt::write!(fmt, "WeightOutOfRange: {}", weight)
+            }
+            ResponseCode::Temperature(temperature) => {
+                defmt::write!(fmt, "Temperature: {}", temperature)
+            }
+            ResponseCode::DriftEstimate(drift) => defmt::write!(fmt, "DriftEstimate: {}", drift),
+            ResponseCode::Averaging(samples) => defmt::write!(fmt, "Averaging: {}", samples),
+            ResponseCode::CalibrationValues { zero_raw, scale } => {
+                defmt::write!(
+                    fmt,
+                    "CalibrationValues: Zero: {}, Scale: {}",
+                    zero_raw,
+                    scale
+                )
+            }
+            ResponseCode::Heartbeat(uptime_ms) => defmt::write!(fmt, "Heartbeat: {}", uptime_ms),
+            ResponseCode::CalibrationResult(ok) => defmt::write!(fmt, "CalibrationResult: {}", ok),
+            ResponseCode::ConfigChunk { offset, len, data } => {
+                defmt::write!(
+                    fmt,
+                    "ConfigChunk: Offset: {}, Data: {:x}",
+                    offset,
+                    &data[..*len as usize]
+                )
+            }
+            ResponseCode::ConfigImported(ok) => defmt::write!(fmt, "ConfigImported: {}", ok),
+            ResponseCode::CommandRejected(op_code) => {
+                defmt::write!(fmt, "CommandRejected: {:#x}", op_code)
+            }
+            ResponseCode::RateWarning(actual_hz) => {
+                defmt::write!(fmt, "RateWarning: {}", actual_hz)
+            }
+            ResponseCode::Diagnostics {
+                uptime_ms,
+                reset_reason,
+            } => {
+                defmt::write!(
+                    fmt,
+                    "Diagnostics: Uptime: {}ms, Reset reason: {}",
+                    uptime_ms,
+                    reset_reason
+                )
+            }
+            ResponseCode::CoalescedWeights {
+                timestamp,
+                count,
+                weights,
+            } => {
+                defmt::write!(
+                    fmt,
+                    "CoalescedWeights: Timestamp: {}, Weights: {}",
+                    timestamp,
+                    &weights[..*count as usize]
+                )
+            }
+            ResponseCode::LinearityResult {
+                max_error_pct,
+                passed,
+            } => {
+                defmt::write!(
+                    fmt,
+                    "LinearityResult: Max error: {}%, Passed: {}",
+                    max_error_pct,
+                    passed
+                )
+            }
+            ResponseCode::FilterConfig {
+                filter_mode,
+                averaging_samples,
+                max_weight_step,
+                report_on_change,
+            } => {
+                defmt::write!(
+                    fmt,
+                    "FilterConfig: Mode: {}, Averaging: {}, Max step: {}kg, Report on change: {}",
+                    filter_mode,
+                    averaging_samples,
+                    max_weight_step,
+                    report_on_change
+                )
+            }
+            ResponseCode::MeasurementStopped {
+                samples,
+                duration_ms,
+            } => {
+                defmt::write!(
+                    fmt,
+                    "MeasurementStopped: Samples: {}, Duration: {}ms",
+                    samples,
+                    duration_ms
+                )
+            }
+            ResponseCode::TareState(tared) => defmt::write!(fmt, "TareState: {}", tared),
+            ResponseCode::OverloadCount(count) => defmt::write!(fmt, "OverloadCount: {}", count),
+            ResponseCode::TareRequired => defmt::write!(fmt, "TareRequired"),
+            ResponseCode::ShuttingDown => defmt::write!(fmt, "ShuttingDown"),
+            ResponseCode::NameSet(ok) => defmt::write!(fmt, "NameSet: {}", ok),
+            ResponseCode::ProgressorIdSet(ok) => defmt::write!(fmt, "ProgressorIdSet: {}", ok),
+            ResponseCode::LoadCellSaturated => defmt::write!(fmt, "LoadCellSaturated"),
+            ResponseCode::GuidedCalibration(step) => {
+                defmt::write!(fmt, "GuidedCalibration: {:?}", step)
+            }
+            ResponseCode::PeakRfd(rfd) => defmt::write!(fmt, "PeakRfd: {}", rfd),
+            ResponseCode::InvalidWeight => defmt::write!(fmt, "InvalidWeight"),
+            ResponseCode::ForceAndRate { force, rate } => {
+                defmt::write!(fmt, "ForceAndRate: Force: {}, Rate: {}", force, rate)
+            }
+            ResponseCode::CommandAck { op_code, ok } => {
+                defmt::write!(fmt, "CommandAck: OpCode: {:#x}, Ok: {}", op_code, ok)
+            }
+            ResponseCode::WeightEnvelope { mean, min, max } => {
+                defmt::write!(
+                    fmt,
+                    "WeightEnvelope: Mean: {}, Min: {}, Max: {}",
+                    mean,
+                    min,
+                    max
+                )
+            }
+            ResponseCode::WeightWithSettled {
+                weight,
+                timestamp,
+                settled,
+            } => {
+                defmt::write!(
+                    fmt,
+                    "WeightWithSettled: Weight: {}, Timestamp: {}, Settled: {}",
+                    weight,
+                    timestamp,
+                    settled
+                )
+            }
+        }
+    }
+}
+
+impl ResponseCode {
+    /// Get the operation code for this response
+    fn op_code(&self) -> u8 {
+        match self {
+            ResponseCode::SampleBatteryVoltage(..)
+            | ResponseCode::AppVersion(..)
+            | ResponseCode::ProgressorId(..)
+            | ResponseCode::Capabilities(..)
+            | ResponseCode::CalibrationPoint { .. }
+            | ResponseCode::Temperature(..)
+            | ResponseCode::DriftEstimate(..)
+            | ResponseCode::Averaging(..)
+            | ResponseCode::CalibrationValues { .. }
+            | ResponseCode::Diagnostics { .. }
+            | ResponseCode::FilterConfig { .. }
+            | ResponseCode::TareState(..)
+            | ResponseCode::OverloadCount(..) => 0x00,
+            ResponseCode::WeightMeasurement(..) => 0x01,
+            ResponseCode::LowPowerWarning => 0x04,
+            ResponseCode::WeightWithRaw { .. } => 0x05,
+            ResponseCode::Impulse(..) => 0x06,
+            ResponseCode::RawValue(..) => 0x07,
+            ResponseCode::WeightWithSettled { .. } => 0x08,
+            ResponseCode::LoadCellFault(..) => 0x09,
+            ResponseCode::RepCount(..) => 0x0A,
+            ResponseCode::WeightOutOfRange(..) => 0x0B,
+            ResponseCode::MeasurementStarted(..) => 0x0C,
+            ResponseCode::SessionChecksum(..) => 0x0D,
+            ResponseCode::WeightEnvelope { .. } => 0x0E,
+            ResponseCode::CommandAck { .. } => 0x0F,
+            ResponseCode::Heartbeat(..) => 0x10,
+            ResponseCode::CalibrationResult(..) => 0x11,
+            ResponseCode::ConfigChunk { .. } => 0x12,
+            ResponseCode::ConfigImported(..) => 0x13,
+            ResponseCode::CommandRejected(..) => 0x14,
+            ResponseCode::RateWarning(..) => 0x15,
+            ResponseCode::CoalescedWeights { .. } => 0x16,
+            ResponseCode::LinearityResult { .. } => 0x17,
+            ResponseCode::MeasurementStopped { .. } => 0x18,
+            ResponseCode::ForceAndRate { .. } => 0x19,
+            ResponseCode::InvalidWeight => 0x1A,
+            ResponseCode::TareRequired => 0x1B,
+            ResponseCode::ShuttingDown => 0x1C,
+            ResponseCode::NameSet(..) => 0x1D,
+            ResponseCode::GuidedCalibration(..) => 0x1E,
+            ResponseCode::PeakRfd(..) => 0x1F,
+            ResponseCode::ProgressorIdSet(..) => 0x20,
+            ResponseCode::LoadCellSaturated => 0x21,
+        }
+    }
+
+    /// Get the length of the data for this response
+    fn length(&self) -> u8 {
+        match self {
+            ResponseCode::SampleBatteryVoltage(..) => 4,
+            ResponseCode::WeightMeasurement(..) => 8,
+            ResponseCode::LowPowerWarning => 0,
+            ResponseCode::AppVersion(version) => version.len() as u8,
+            ResponseCode::ProgressorId(..) => DEVICE_ID_SIZE as u8,
+            ResponseCode::WeightWithRaw { .. } => 8,
+            ResponseCode::Impulse(..) => 4,
+            ResponseCode::Capabilities(..) => 4,
+            ResponseCode::CalibrationPoint { .. } => 9,
+            ResponseCode::RawValue(..) => 4,
+            ResponseCode::WeightWithSettled { .. } => 9,
+            ResponseCode::LoadCellFault(..) => 1,
+            ResponseCode::RepCount(..) => 2,
+            ResponseCode::Temperature(..) => 4,
+            ResponseCode::WeightOutOfRange(..) => 4,
+            ResponseCode::MeasurementStarted(..) => 4,
+            ResponseCode::SessionChecksum(..) => 4,
+            ResponseCode::WeightEnvelope { .. } => 12,
+            ResponseCode::CommandAck { .. } => 2,
+            ResponseCode::DriftEstimate(..) => 4,
+            ResponseCode::Averaging(..) => 2,
+            ResponseCode::CalibrationValues { .. } => 8,
+            ResponseCode::Heartbeat(..) => 4,
+            ResponseCode::CalibrationResult(..) => 1,
+            ResponseCode::ConfigChunk { len, .. } => 1 + len,
+            ResponseCode::ConfigImported(..) => 1,
+            ResponseCode::CommandRejected(..) => 1,
+            ResponseCode::RateWarning(..) => 4,
+            ResponseCode::Diagnostics { .. } => 5,
+            ResponseCode::CoalescedWeights { count, .. } => 4 + 4 * count,
+            ResponseCode::LinearityResult { .. } => 5,
+            ResponseCode::FilterConfig { .. } => 15,
+            ResponseCode::MeasurementStopped { .. } => 8,
+            ResponseCode::ForceAndRate { .. } => 8,
+            ResponseCode::InvalidWeight => 0,
+            ResponseCode::TareState(..) => 1,
+            ResponseCode::OverloadCount(..) => 4,
+            ResponseCode::TareRequired => 0,
+            ResponseCode::ShuttingDown => 0,
+            ResponseCode::NameSet(..) => 1,
+            ResponseCode::ProgressorIdSet(..) => 1,
+            ResponseCode::LoadCellSaturated => 0,
+            ResponseCode::GuidedCalibration(..) => 1,
+            ResponseCode::PeakRfd(..) => 4,
+        }
+    }
+
+    /// Get the value bytes for this response
+    fn value(&self) -> [u8; MAX_PAYLOAD_SIZE] {
+        let mut value = [0; MAX_PAYLOAD_SIZE];
+        match self {
+            ResponseCode::SampleBatteryVoltage(voltage) => {
+                value[0..4].copy_from_slice(&voltage.to_le_bytes());
+            }
+            ResponseCode::WeightMeasurement(weight, timestamp) => {
+                value[0..4].copy_from_slice(&weight.to_le_bytes());
+                value[4..8].copy_from_slice(&timestamp.to_le_bytes());
+            }
+            ResponseCode::LowPowerWarning
+            | ResponseCode::InvalidWeight
+            | ResponseCode::LoadCellSaturated
+            | ResponseCode::TareRequired
+            | ResponseCode::ShuttingDown => (),
+            ResponseCode::TareState(tared) => {
+                value[0] = *tared as u8;
+            }
+            ResponseCode::OverloadCount(count) => {
+                value[0..4].copy_from_slice(&count.to_le_bytes());
+            }
+            ResponseCode::NameSet(ok) | ResponseCode::ProgressorIdSet(ok) => {
+                value[0] = *ok as u8;
+            }
+            ResponseCode::GuidedCalibration(step) => {
+                value[0] = *step as u8;
+            }
+            ResponseCode::ProgressorId(id) => {
+                // Reverse the bytes as they are LE
+                let mut reversed = *id;
+                reversed.reverse();
+                value[..DEVICE_ID_SIZE].copy_from_slice(&reversed);
             }
             ResponseCode::AppVersion(version) => {
                 value[0..version.len()].copy_from_slice(version);
             }
+            ResponseCode::WeightWithRaw { weight, raw } => {
+                value[0..4].copy_from_slice(&weight.to_le_bytes());
+                value[4..8].copy_from_slice(&raw.to_le_bytes());
+            }
+            ResponseCode::Impulse(impulse) => {
+                value[0..4].copy_from_slice(&impulse.to_le_bytes());
+            }
+            ResponseCode::Capabilities(capabilities) => {
+                value[0..4].copy_from_slice(&capabilities.to_le_bytes());
+            }
+            ResponseCode::CalibrationPoint { index, raw, weight } => {
+                value[0] = *index;
+                value[1..5].copy_from_slice(&raw.to_le_bytes());
+                value[5..9].copy_from_slice(&weight.to_le_bytes());
+            }
+            ResponseCode::RawValue(raw) => {
+                value[0..4].copy_from_slice(&raw.to_le_bytes());
+            }
+            ResponseCode::WeightWithSettled {
+                weight,
+                timestamp,
+                settled,
+            } => {
+                value[0..4].copy_from_slice(&weight.to_le_bytes());
+                value[4..8].copy_from_slice(&timestamp.to_le_bytes());
+                value[8] = *settled as u8;
+            }
+            ResponseCode::LoadCellFault(fault) => {
+                value[0] = *fault as u8;
+            }
+            ResponseCode::RepCount(count) => {
+                value[0..2].copy_from_slice(&count.to_le_bytes());
+            }
+            ResponseCode::Temperature(temperature) => {
+                value[0..4].copy_from_slice(&temperature.to_le_bytes());
+            }
+            ResponseCode::WeightOutOfRange(weight) => {
+                value[0..4].copy_from_slice(&weight.to_le_bytes());
+            }
+            ResponseCode::MeasurementStarted(start_time) => {
+                value[0..4].copy_from_slice(&start_time.to_le_bytes());
+            }
+            ResponseCode::SessionChecksum(checksum) => {
+                value[0..4].copy_from_slice(&checksum.to_le_bytes());
+            }
+            ResponseCode::WeightEnvelope { mean, min, max } => {
+                value[0..4].copy_from_slice(&mean.to_le_bytes());
+                value[4..8].copy_from_slice(&min.to_le_bytes());
+                value[8..12].copy_from_slice(&max.to_le_bytes());
+            }
+            ResponseCode::CommandAck { op_code, ok } => {
+                value[0] = *op_code;
+                value[1] = *ok as u8;
+            }
+            ResponseCode::PeakRfd(rfd) => {
+                value[0..4].copy_from_slice(&rfd.to_le_bytes());
+            }
+            ResponseCode::DriftEstimate(drift) => {
+                value[0..4].copy_from_slice(&drift.to_le_bytes());
+            }
+            ResponseCode::CalibrationValues { zero_raw, scale } => {
+                value[0..4].copy_from_slice(&zero_raw.to_le_bytes());
+                value[4..8].copy_from_slice(&scale.to_le_bytes());
+            }
+            ResponseCode::Averaging(samples) => {
+                value[0..2].copy_from_slice(&samples.to_le_bytes());
+            }
+            ResponseCode::Heartbeat(uptime_ms) => {
+                value[0..4].copy_from_slice(&uptime_ms.to_le_bytes());
+            }
+            ResponseCode::CalibrationResult(ok) => {
+                value[0] = *ok as u8;
+            }
+            ResponseCode::ConfigChunk { offset, len, data } => {
+                value[0] = *offset;
+                value[1..1 + *len as usize].copy_from_slice(&data[..*len as usize]);
+            }
+            ResponseCode::ConfigImported(ok) => {
+                value[0] = *ok as u8;
+            }
+            ResponseCode::CommandRejected(op_code) => {
+                value[0] = *op_code;
+            }
+            ResponseCode::RateWarning(actual_hz) => {
+                value[0..4].copy_from_slice(&actual_hz.to_le_bytes());
+            }
+            ResponseCode::Diagnostics {
+                uptime_ms,
+                reset_reason,
+            } => {
+                value[0..4].copy_from_slice(&uptime_ms.to_le_bytes());
+                value[4] = *reset_reason as u8;
+            }
+            ResponseCode::CoalescedWeights {
+                timestamp,
+                count,
+                weights,
+            } => {
+                value[0..4].copy_from_slice(&timestamp.to_le_bytes());
+                for (i, weight) in weights[..*count as usize].iter().enumerate() {
+                    value[4 + 4 * i..8 + 4 * i].copy_from_slice(&weight.to_le_bytes());
+                }
+            }
+            ResponseCode::LinearityResult {
+                max_error_pct,
+                passed,
+            } => {
+                value[0..4].copy_from_slice(&max_error_pct.to_le_bytes());
+                value[4] = *passed as u8;
+            }
+            ResponseCode::ForceAndRate { force, rate } => {
+                value[0..4].copy_from_slice(&force.to_le_bytes());
+                value[4..8].copy_from_slice(&rate.to_le_bytes());
+            }
+            ResponseCode::MeasurementStopped {
+                samples,
+                duration_ms,
+            } => {
+                value[0..4].copy_from_slice(&samples.to_le_bytes());
+                value[4..8].copy_from_slice(&duration_ms.to_le_bytes());
+            }
+            ResponseCode::FilterConfig {
+                filter_mode,
+                averaging_samples,
+                max_weight_step,
+                report_on_change,
+            } => {
+                let (delta, heartbeat_ms) = report_on_change
+                    .map(|config| (config.delta, config.heartbeat_ms))
+                    .unwrap_or((0.0, 0));
+                value[0] = filter_mode.to_u8();
+                value[1..3].copy_from_slice(&averaging_samples.to_le_bytes());
+                value[3..7].copy_from_slice(&max_weight_step.to_le_bytes());
+                value[7..11].copy_from_slice(&delta.to_le_bytes());
+                value[11..13].copy_from_slice(&heartbeat_ms.to_le_bytes());
+                value[13..15].copy_from_slice(&filter_mode.alpha_u16().to_le_bytes());
+            }
         };
         value
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use embassy_time::{Duration, MockDriver};
+
+    use super::*;
+
+    /// Process a command, op code included, and collect the data points sent in response
+    fn process(device_state: &mut DeviceState, command: &[u8]) -> Vec<DataPoint> {
+        let channel: &'static DataPointChannel = Box::leak(Box::new(DataPointChannel::new()));
+        ControlOpCode::from(command[0]).process(command, channel, device_state);
+        core::iter::from_fn(|| channel.try_receive().ok()).collect()
+    }
+
+    /// Response codes of the data points
+    fn codes(data_points: &[DataPoint]) -> Vec<u8> {
+        data_points.iter().map(|d| d.response_code).collect()
+    }
+
+    fn tared() -> DeviceState {
+        DeviceState {
+            tared: true,
+            ..DeviceState::new()
+        }
+    }
+
+    #[test]
+    fn command_arguments_are_big_endian() {
+        let data = [0x76, 0x01, 0x02, 0x42, 0x8C, 0x00, 0x00];
+
+        assert_eq!(command_u16(&data, 1), 0x0102);
+        assert_eq!(command_f32(&data, 3), 70.0);
+    }
+
+    #[test]
+    fn responses_are_little_endian() {
+        let data_point = DataPoint::from(ResponseCode::WeightMeasurement(70.0, 0x0102_0304));
+
+        assert_eq!(data_point.payload()[0..4], 70.0f32.to_le_bytes());
+        assert_eq!(data_point.payload()[4..8], [0x04, 0x03, 0x02, 0x01]);
+    }
+
+    #[test]
+    fn start_takes_the_start_time_from_the_clock() {
+        let mut state = tared();
+        // The mock clock is shared by the tests running in parallel, only this one moves it
+        MockDriver::get().advance(Duration::from_secs(2));
+        let start_time = Instant::now().as_micros();
+
+        let sent = process(&mut state, &[0x65]);
+
+        assert_eq!(state.measurement_status, MeasurementTaskStatus::Enabled);
+        assert_eq!(state.start_time, start_time);
+        assert_eq!(codes(&sent), [0x0C]);
+        assert_eq!(sent[0].payload(), (start_time as u32).to_le_bytes());
+        assert!(!sent[0].allowed_in(ProtocolMode::Tindeq));
+    }
+
+    #[test]
+    fn measurement_stopped_reports_samples_and_duration() {
+        let data_point = DataPoint::from(ResponseCode::MeasurementStopped {
+            samples: 800,
+            duration_ms: 10_012,
+        });
+
+        assert_eq!(data_point.response_code, 0x18);
+        assert_eq!(data_point.payload()[0..4], 800u32.to_le_bytes());
+        assert_eq!(data_point.payload()[4..8], 10_012u32.to_le_bytes());
+        assert!(!data_point.allowed_in(ProtocolMode::Tindeq));
+    }
+
+    #[test]
+    fn stop_leaves_the_summary_to_the_measurement_task() {
+        let mut state = tared();
+        process(&mut state, &[0x65]);
+
+        let sent = process(&mut state, &[0x66]);
+
+        assert!(sent.is_empty());
+        assert_eq!(state.measurement_status, MeasurementTaskStatus::Disabled);
+    }
+
+    #[test]
+    fn invalid_weight_is_an_empty_notification() {
+        let data_point = DataPoint::from(ResponseCode::InvalidWeight);
+
+        assert_eq!(data_point.response_code, 0x1A);
+        assert!(data_point.payload().is_empty());
+        assert!(!data_point.allowed_in(ProtocolMode::Tindeq));
+    }
+
+    #[test]
+    fn start_untared_warns_before_starting() {
+        let mut state = DeviceState::new();
+
+        let sent = process(&mut state, &[0x65]);
+
+        assert_eq!(state.measurement_status, MeasurementTaskStatus::Enabled);
+        assert_eq!(codes(&sent), [0x1B, 0x0C]);
+    }
+
+    #[test]
+    fn start_tared_doesnt_warn() {
+        let mut state = tared();
+
+        let sent = process(&mut state, &[0x65]);
+
+        assert!(!codes(&sent).contains(&0x1B));
+    }
+
+    #[test]
+    fn start_untared_warns_every_time() {
+        let mut state = DeviceState::new();
+        process(&mut state, &[0x65]);
+        process(&mut state, &[0x66]);
+
+        let sent = process(&mut state, &[0x65]);
+
+        assert_eq!(codes(&sent), [0x1B, 0x0C]);
+        assert!(sent[0].payload().is_empty());
+    }
+
+    #[test]
+    fn baseline_subtraction_is_toggled() {
+        let mut state = DeviceState::new();
+        assert!(!state.baseline_subtraction);
+
+        process(&mut state, &[0x9D, 1]);
+        assert!(state.baseline_subtraction);
+
+        process(&mut state, &[0x9D]);
+        assert!(state.baseline_subtraction);
+        process(&mut state, &[0x9D, 0]);
+        assert!(!state.baseline_subtraction);
+    }
+
+    #[test]
+    fn set_name_leaves_the_name_to_the_measurement_task() {
+        let mut state = DeviceState::new();
+
+        let sent = process(&mut state, b"\x9eBlock");
+
+        assert!(sent.is_empty());
+        assert_eq!(state.pending_name, DeviceName::new(b"Block").ok());
+    }
+
+    #[test]
+    fn invalid_names_are_rejected() {
+        let mut state = DeviceState::new();
+
+        for command in [&[0x9E][..], &[0x9E, 0xFF]] {
+            let sent = process(&mut state, command);
+
+            assert_eq!(codes(&sent), [0x1D]);
+            assert_eq!(sent[0].payload(), [0]);
+            assert_eq!(state.pending_name, None);
+        }
+    }
+
+    #[test]
+    fn filter_step_reset_is_disabled_by_invalid_thresholds() {
+        let mut state = DeviceState::new();
+
+        for threshold in [0.0, -5.0, f32::NAN] {
+            process(&mut state, &[0x9F, 0x40, 0xA0, 0x00, 0x00]);
+            assert_eq!(state.filter_step_reset, 5.0);
+
+            let mut command = vec![0x9F];
+            command.extend(threshold.to_be_bytes());
+            process(&mut state, &command);
+
+            assert_eq!(state.filter_step_reset, f32::INFINITY);
+        }
+    }
+
+    #[test]
+    fn battery_is_sampled_by_the_measurement_task() {
+        let mut state = DeviceState::new();
+
+        let sent = process(&mut state, &[0x6F]);
+
+        assert!(sent.is_empty());
+        assert!(state.battery_requested);
+    }
+
+    #[test]
+    fn battery_is_not_sampled_while_measuring() {
+        let mut state = tared();
+        process(&mut state, &[0x65]);
+
+        let sent = process(&mut state, &[0x6F]);
+
+        assert_eq!(codes(&sent), [0x00]);
+        assert_eq!(sent[0].payload(), 0u32.to_le_bytes());
+        assert!(!state.battery_requested);
+    }
+
+    #[test]
+    fn progressor_id_is_sent_whole() {
+        let id = [0x12, 0x34, 0x56, 0x78, 0x9A, 0x00];
+
+        let data_point = DataPoint::from(ResponseCode::ProgressorId(id));
+
+        assert_eq!(data_point.payload(), [0x00, 0x9A, 0x78, 0x56, 0x34, 0x12]);
+    }
+
+    #[test]
+    fn progressor_id_roundtrips_through_its_commands() {
+        let id = [0x12, 0x34, 0x56, 0x78, 0x9A, 0xBC];
+        crate::device_id::store(id).unwrap();
+        let mut state = DeviceState::new();
+
+        let sent = process(&mut state, &[0x70]);
+        assert_eq!(codes(&sent), [0x00]);
+        let mut command = vec![0xA5];
+        command.extend(sent[0].payload());
+        process(&mut state, &command);
+
+        assert_eq!(state.pending_device_id, Some(id));
+    }
+
+    #[test]
+    fn rate_window_is_clamped() {
+        let mut state = DeviceState::new();
+
+        process(&mut state, &[0x9B, 0x00, 0x32]);
+        assert_eq!(state.rate_window_ms, 50);
+
+        process(&mut state, &[0x9B, 0xFF, 0xFF]);
+        assert_eq!(state.rate_window_ms, MAX_RATE_WINDOW_MS);
+    }
+
+    #[test]
+    fn force_and_rate_are_packed_in_order() {
+        let data_point = DataPoint::from(ResponseCode::ForceAndRate {
+            force: 42.5,
+            rate: -120.0,
+        });
+
+        assert_eq!(data_point.response_code, 0x19);
+        assert_eq!(data_point.payload()[0..4], 42.5f32.to_le_bytes());
+        assert_eq!(data_point.payload()[4..8], (-120.0f32).to_le_bytes());
+    }
+
+    #[test]
+    fn auto_tare_tares_before_starting() {
+        let mut state = DeviceState::new();
+        process(&mut state, &[0x7C, 1]);
+
+        let sent = process(&mut state, &[0x65]);
+        assert_eq!(
+            state.measurement_status,
+            MeasurementTaskStatus::TareAndStart
+        );
+        // The measurement task reports the start once tared
+        assert_eq!(codes(&sent), [0x1B]);
+
+        process(&mut state, &[0x66]);
+        assert_eq!(state.measurement_status, MeasurementTaskStatus::Disabled);
+    }
+
+    #[test]
+    fn auto_tare_when_untared_skips_a_tared_scale() {
+        let mut state = tared();
+        process(&mut state, &[0x7C, 1]);
+
+        process(&mut state, &[0x65]);
+
+        assert_eq!(state.measurement_status, MeasurementTaskStatus::Enabled);
+    }
+
+    #[test]
+    fn auto_tare_always_tares_a_tared_scale() {
+        let mut state = tared();
+        process(&mut state, &[0x7C, 2]);
+
+        process(&mut state, &[0x65]);
+
+        assert_eq!(
+            state.measurement_status,
+            MeasurementTaskStatus::TareAndStart
+        );
+    }
+
+    #[test]
+    fn invalid_auto_tare_mode_is_ignored() {
+        let mut state = DeviceState::new();
+        process(&mut state, &[0x7C, 2]);
+
+        process(&mut state, &[0x7C, 3]);
+        process(&mut state, &[0x7C]);
+
+        assert_eq!(state.auto_tare, AutoTareMode::Always);
+    }
+
+    #[test]
+    fn commands_conflicting_with_a_tare_are_rejected() {
+        let mut state = DeviceState::new();
+        process(&mut state, &[0x64]);
+        assert_eq!(state.measurement_status, MeasurementTaskStatus::Tare);
+
+        let sent = process(&mut state, &[0x65]);
+
+        assert_eq!(state.measurement_status, MeasurementTaskStatus::Tare);
+        assert_eq!(codes(&sent), [0x14]);
+        assert_eq!(sent[0].payload(), [0x65]);
+    }
+
+    #[test]
+    fn commands_not_interrupting_a_calibration_are_served() {
+        let mut state = tared();
+        // 20kg point
+        process(&mut state, &[0x73, 0x46, 0x9C, 0x40, 0x00]);
+        assert_eq!(
+            state.measurement_status,
+            MeasurementTaskStatus::Calibration(20_000.0)
+        );
+
+        let sent = process(&mut state, &[0x64]);
+        assert_eq!(codes(&sent), [0x14]);
+        assert_eq!(sent[0].payload(), [0x64]);
+
+        let sent = process(&mut state, &[0x8E]);
+        assert_eq!(codes(&sent), [0x00]);
+        process(&mut state, &[0x75, 1]);
+        assert!(state.report_raw);
+    }
+
+    #[test]
+    fn pause_and_resume_keep_the_start_time() {
+        let mut state = tared();
+        state.start_measurement_at(1234);
+
+        process(&mut state, &[0x82]);
+        assert_eq!(state.measurement_status, MeasurementTaskStatus::Paused);
+        process(&mut state, &[0x83]);
+
+        assert_eq!(state.measurement_status, MeasurementTaskStatus::Enabled);
+        assert_eq!(state.start_time, 1234);
+    }
+
+    #[test]
+    fn pause_and_resume_need_a_measurement() {
+        let mut state = DeviceState::new();
+
+        process(&mut state, &[0x82]);
+        assert_eq!(state.measurement_status, MeasurementTaskStatus::Disabled);
+        process(&mut state, &[0x83]);
+        assert_eq!(state.measurement_status, MeasurementTaskStatus::Disabled);
+    }
+
+    #[test]
+    fn commands_are_ignored_while_shutting_down() {
+        let mut state = tared();
+        state.reboot_requested = true;
+
+        let sent = process(&mut state, &[0x65]);
+
+        assert_eq!(state.measurement_status, MeasurementTaskStatus::Disabled);
+        assert_eq!(codes(&sent), [0x1C]);
+    }
+
+    #[test]
+    fn mutating_commands_are_acknowledged() {
+        let mut state = DeviceState::new();
+        process(&mut state, &[0x8B, 1]);
+
+        let sent = process(&mut state, &[0x75, 1]);
+        assert!(state.report_raw);
+        assert_eq!(codes(&sent), [0x0F]);
+        assert_eq!(sent[0].payload(), [0x75, 1]);
+
+        let sent = process(&mut state, &[0x75]);
+        assert_eq!(sent[0].payload(), [0x75, 0]);
+
+        let sent = process(&mut state, &[0x78]);
+        assert_eq!(codes(&sent), [0x00]);
+    }
+
+    #[test]
+    fn commands_are_not_acknowledged_by_default() {
+        let mut state = DeviceState::new();
+
+        let sent = process(&mut state, &[0x75, 1]);
+
+        assert!(state.report_raw);
+        assert!(sent.is_empty());
+    }
+
+    #[test]
+    fn tare_is_acknowledged_when_accepted() {
+        let mut state = DeviceState::new();
+        process(&mut state, &[0x8B, 1]);
+
+        let sent = process(&mut state, &[0x64]);
+
+        assert_eq!(state.measurement_status, MeasurementTaskStatus::Tare);
+        assert_eq!(codes(&sent), [0x0F]);
+        assert_eq!(sent[0].payload(), [0x64, 1]);
+    }
+
+    #[test]
+    fn max_weight_step_is_disabled_by_zero() {
+        let mut state = DeviceState::new();
+        assert_eq!(state.max_weight_step, f32::INFINITY);
+
+        process(&mut state, &[0x77, 0x40, 0xA0, 0x00, 0x00]);
+        assert_eq!(state.max_weight_step, 5.0);
+
+        process(&mut state, &[0x77, 0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(state.max_weight_step, f32::INFINITY);
+    }
+
+    #[test]
+    fn bodyweight_reports_weights_as_a_percentage() {
+        let mut state = DeviceState::new();
+        assert_eq!(state.reported_weight(35.0), 35.0);
+
+        // 70kg
+        process(&mut state, &[0x80, 0x42, 0x8C, 0x00, 0x00]);
+        assert_eq!(state.bodyweight, Some(70.0));
+        assert_eq!(state.reported_weight(35.0), 50.0);
+
+        process(&mut state, &[0x80, 0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(state.bodyweight, None);
+        assert_eq!(state.reported_weight(35.0), 35.0);
+    }
+
+    #[test]
+    fn bodyweight_needs_a_value() {
+        let mut state = DeviceState::new();
+        process(&mut state, &[0x80, 0x42, 0x8C, 0x00, 0x00]);
+        process(&mut state, &[0x8B, 1]);
+
+        let sent = process(&mut state, &[0x80, 0x00]);
+
+        assert_eq!(state.bodyweight, Some(70.0));
+        assert_eq!(sent[0].payload(), [0x80, 0]);
+    }
+
+    /// SetRepThresholds command with the given on and off thresholds
+    fn rep_thresholds(on: f32, off: f32) -> Vec<u8> {
+        let mut command = vec![0x84];
+        command.extend(on.to_be_bytes());
+        command.extend(off.to_be_bytes());
+        command
+    }
+
+    #[test]
+    fn rep_thresholds_are_set_and_disabled() {
+        let mut state = DeviceState::new();
+
+        process(&mut state, &rep_thresholds(20.0, 10.0));
+        let thresholds = state.rep_thresholds.unwrap();
+        assert_eq!((thresholds.on, thresholds.off), (20.0, 10.0));
+
+        process(&mut state, &rep_thresholds(0.0, f32::NAN));
+        assert_eq!(state.rep_thresholds, None);
+    }
+
+    #[test]
+    fn invalid_rep_thresholds_are_rejected() {
+        let mut state = DeviceState::new();
+        process(&mut state, &rep_thresholds(20.0, 10.0));
+        let set = state.rep_thresholds;
+
+        for (on, off) in [
+            (20.0, 30.0),
+            (20.0, f32::NAN),
+            (20.0, f32::NEG_INFINITY),
+            (f32::INFINITY, 10.0),
+            (f32::NAN, 10.0),
+        ] {
+            process(&mut state, &rep_thresholds(on, off));
+
+            assert_eq!(state.rep_thresholds, set, "on: {on}, off: {off}");
+        }
+    }
+
+    #[test]
+    fn non_finite_bodyweight_is_rejected() {
+        let mut state = DeviceState::new();
+        process(&mut state, &[0x80, 0x42, 0x8C, 0x00, 0x00]);
+        process(&mut state, &[0x8B, 1]);
+
+        for bodyweight in [f32::INFINITY, f32::NEG_INFINITY, f32::NAN] {
+            let mut command = vec![0x80];
+            command.extend(bodyweight.to_be_bytes());
+            let sent = process(&mut state, &command);
+
+            assert_eq!(state.bodyweight, Some(70.0));
+            assert_eq!(sent[0].payload(), [0x80, 0]);
+        }
+    }
+
+    #[test]
+    fn report_on_change_needs_a_finite_delta() {
+        let mut state = DeviceState::new();
+        let report_on_change = |delta: f32| {
+            let mut command = vec![0x86];
+            command.extend(delta.to_be_bytes());
+            command.extend(500u16.to_be_bytes());
+            command
+        };
+
+        process(&mut state, &report_on_change(0.5));
+        let set = Some(ReportOnChange {
+            delta: 0.5,
+            heartbeat_ms: 500,
+        });
+        assert_eq!(state.report_on_change, set);
+
+        for delta in [f32::INFINITY, f32::NAN] {
+            process(&mut state, &report_on_change(delta));
+            assert_eq!(state.report_on_change, set);
+        }
+
+        process(&mut state, &report_on_change(0.0));
+        assert_eq!(state.report_on_change, None);
+    }
+
+    #[test]
+    fn plausible_range_must_not_be_empty() {
+        let mut state = DeviceState::new();
+        // -10kg..200kg
+        process(
+            &mut state,
+            &[0x88, 0xC1, 0x20, 0x00, 0x00, 0x43, 0x48, 0x00, 0x00],
+        );
+        assert_eq!(state.plausible_range, (-10.0, 200.0));
+
+        // 200kg..-10kg
+        process(
+            &mut state,
+            &[0x88, 0x43, 0x48, 0x00, 0x00, 0xC1, 0x20, 0x00, 0x00],
+        );
+        // NaN..200kg
+        process(
+            &mut state,
+            &[0x88, 0x7F, 0xC0, 0x00, 0x00, 0x43, 0x48, 0x00, 0x00],
+        );
+        assert_eq!(state.plausible_range, (-10.0, 200.0));
+    }
+
+    #[test]
+    fn averaging_is_clamped_and_reported() {
+        let mut state = DeviceState::new();
+        let sent = process(&mut state, &[0x8E]);
+        assert_eq!(sent[0].payload(), 1u16.to_le_bytes());
+
+        process(&mut state, &[0x8D, 0x00, 0x10]);
+        let sent = process(&mut state, &[0x8E]);
+        assert_eq!(sent[0].payload(), 16u16.to_le_bytes());
+
+        process(&mut state, &[0x8D, 0x00, 0x00]);
+        assert_eq!(state.averaging_samples, 1);
+        process(&mut state, &[0x8D, 0x10, 0x00]);
+        assert_eq!(state.averaging_samples, MAX_AVERAGING_SAMPLES);
+    }
+
+    #[test]
+    fn averaging_window_is_clamped() {
+        let mut state = DeviceState::new();
+        process(&mut state, &[0xA8, 0x00, 0x64]);
+        assert_eq!(state.averaging_window_ms, 100);
+
+        process(&mut state, &[0xA8, 0xFF, 0xFF]);
+        assert_eq!(state.averaging_window_ms, MAX_AVERAGING_WINDOW_MS);
+        process(&mut state, &[0xA8, 0x00, 0x00]);
+        assert_eq!(state.averaging_window_ms, 0);
+
+        // Too short
+        process(&mut state, &[0xA8, 0x01]);
+        assert_eq!(state.averaging_window_ms, 0);
+    }
+
+    #[test]
+    fn measurement_rate_follows_the_averaging() {
+        let mut state = DeviceState::new();
+        state.averaging_samples = 4;
+        assert_eq!(state.measurement_rate_hz(80.0), 20.0);
+
+        // The window takes over from the number of samples
+        state.averaging_window_ms = 100;
+        assert_eq!(state.measurement_rate_hz(80.0), 10.0);
+        assert_eq!(state.measurement_rate_hz(320.0), 10.0);
+
+        // Windows are at least two sample periods
+        state.averaging_window_ms = 1;
+        assert_eq!(state.measurement_rate_hz(80.0), 40.0);
+    }
+
+    #[test]
+    fn signed_mode_counts_pushes_as_pulls() {
+        let mut state = DeviceState::new();
+        assert_eq!(state.pull_force(-20.0), -20.0);
+
+        process(&mut state, &[0x93, 1]);
+        assert!(state.signed_mode);
+        assert_eq!(state.pull_force(-20.0), 20.0);
+        assert_eq!(state.pull_force(20.0), 20.0);
+
+        process(&mut state, &[0x93, 0]);
+        assert!(!state.signed_mode);
+    }
+
+    #[test]
+    fn pushes_are_reported_as_negative_weights() {
+        let data_point = DataPoint::weight_measurement(-20.0, 0);
+
+        assert_eq!(data_point.payload()[0..4], (-20.0f32).to_le_bytes());
+    }
+
+    #[test]
+    fn measure_duration_stops_the_measurement() {
+        let mut state = tared();
+        process(&mut state, &[0x79, 0x00, 0x0A]);
+
+        assert!(!state.measure_duration_elapsed(9_999_999));
+        assert!(state.measure_duration_elapsed(10_000_000));
+
+        process(&mut state, &[0x79, 0xFF, 0xFF]);
+        let cap = MAX_MEASURE_DURATION_S as u64 * 1_000_000;
+        assert!(!state.measure_duration_elapsed(cap - 1));
+        assert!(state.measure_duration_elapsed(cap));
+    }
+
+    #[test]
+    fn measurements_without_a_duration_run_until_stopped() {
+        let state = tared();
+
+        assert!(!state.measure_duration_elapsed(MAX_MEASURE_DURATION_S as u64 * 1_000_000));
+        assert!(!state.measure_duration_elapsed(u32::MAX as u64 + 1));
+    }
+
+    #[test]
+    fn filter_mode_is_set_from_its_wire_value() {
+        let mut state = DeviceState::new();
+
+        process(&mut state, &[0x7D, 1]);
+        assert_eq!(state.filter_mode, FilterMode::Butterworth);
+
+        process(&mut state, &[0x7D, 9]);
+        assert_eq!(state.filter_mode, FilterMode::Butterworth);
+        process(&mut state, &[0x7D, 0]);
+        assert_eq!(state.filter_mode, FilterMode::None);
+    }
+
+    #[test]
+    fn filter_config_is_packed_in_order() {
+        let data_point = DataPoint::from(ResponseCode::FilterConfig {
+            filter_mode: FilterMode::Median,
+            averaging_samples: 4,
+            max_weight_step: 2.5,
+            report_on_change: Some(ReportOnChange {
+                delta: 0.1,
+                heartbeat_ms: 500,
+            }),
+        });
+
+        let payload = data_point.payload();
+        assert_eq!(payload.len(), 15);
+        assert_eq!(payload[0], FilterMode::Median.to_u8());
+        assert_eq!(payload[1..3], 4u16.to_le_bytes());
+        assert_eq!(payload[3..7], 2.5f32.to_le_bytes());
+        assert_eq!(payload[7..11], 0.1f32.to_le_bytes());
+        assert_eq!(payload[11..13], 500u16.to_le_bytes());
+    }
+
+    #[test]
+    fn filter_config_reports_disabled_filters() {
+        let mut state = DeviceState::new();
+        process(&mut state, &[0x94, 1]);
+
+        let sent = process(&mut state, &[0x9A]);
+
+        assert_eq!(codes(&sent), [0x00]);
+        let payload = sent[0].payload();
+        assert_eq!(payload[3..7], f32::INFINITY.to_le_bytes());
+        assert_eq!(payload[7..13], [0; 6]);
+    }
+
+    #[test]
+    fn filter_config_reports_the_ema_factor() {
+        let mut state = DeviceState::new();
+
+        let sent = process(&mut state, &[0x9A]);
+        assert_eq!(sent[0].payload()[13..], [0, 0]);
+
+        process(&mut state, &[0x7D, FILTER_MODE_EMA, 0x3E, 0x80, 0x00, 0x00]);
+        let sent = process(&mut state, &[0x9A]);
+        assert_eq!(sent[0].payload()[0], FILTER_MODE_EMA);
+        assert_eq!(sent[0].payload()[13..], 16384u16.to_le_bytes());
+    }
+
+    #[test]
+    fn capabilities_report_the_build_features() {
+        let mut state = DeviceState::new();
+
+        let sent = process(&mut state, &[0x78]);
+
+        let capabilities = u32::from_le_bytes(sent[0].payload().try_into().unwrap());
+        assert_eq!(capabilities, CAPABILITIES);
+        assert_eq!(capabilities & CAPABILITY_DEMO != 0, cfg!(feature = "demo"));
+        assert_eq!(
+            capabilities & CAPABILITY_UART_STREAM != 0,
+            cfg!(feature = "uart-stream")
+        );
+        assert_eq!(
+            capabilities & CAPABILITY_NAU7802 != 0,
+            cfg!(feature = "nau7802")
+        );
+        assert_ne!(capabilities & CAPABILITY_EMA_FILTER, 0);
+    }
+
+    #[test]
+    fn tindeq_mode_only_sends_stock_responses() {
+        let stock = [
+            ResponseCode::SampleBatteryVoltage(3700),
+            ResponseCode::WeightMeasurement(1.0, 0),
+            ResponseCode::LowPowerWarning,
+        ];
+        let extensions = [
+            ResponseCode::Impulse(1.0),
+            ResponseCode::TareRequired,
+            ResponseCode::MeasurementStarted(0),
+        ];
+
+        for response in stock {
+            assert!(DataPoint::from(response).allowed_in(ProtocolMode::Tindeq));
+        }
+        for response in extensions {
+            let data_point = DataPoint::from(response);
+            assert!(!data_point.allowed_in(ProtocolMode::Tindeq));
+            assert!(data_point.allowed_in(ProtocolMode::Extended));
+        }
+    }
+
+    #[test]
+    fn protocol_mode_is_set_from_its_wire_value() {
+        let mut state = DeviceState::new();
+        assert_eq!(state.protocol_mode, ProtocolMode::Tindeq);
+
+        process(&mut state, &[0x94, 1]);
+        assert_eq!(state.protocol_mode, ProtocolMode::Extended);
+
+        process(&mut state, &[0x94, 2]);
+        assert_eq!(state.protocol_mode, ProtocolMode::Extended);
+        process(&mut state, &[0x94, 0]);
+        assert_eq!(state.protocol_mode, ProtocolMode::Tindeq);
+    }
+
+    #[test]
+    fn capabilities_advertise_every_firmware_feature() {
+        // Bits below the build features are always supported, and assigned without gaps
+        let firmware_features = CAPABILITY_DEMO - 1;
+
+        assert_eq!(CAPABILITIES & firmware_features, firmware_features);
+        assert_eq!(CAPABILITY_BASELINE_SUBTRACTION << 1, CAPABILITY_DEMO);
+    }
+
+    #[test]
+    fn capabilities_are_reported_in_the_tindeq_mode() {
+        let mut state = DeviceState::new();
+        process(&mut state, &[0x8B, 1]);
+
+        let sent = process(&mut state, &[0x78]);
+
+        assert_eq!(codes(&sent), [0x00]);
+        assert!(sent[0].allowed_in(ProtocolMode::Tindeq));
+    }
+
+    #[test]
+    fn reset_session_keeps_the_tare() {
+        let mut state = tared();
+        state.start_measurement_at(1234);
+        state.report_raw = true;
+
+        state.reset_session();
+
+        assert!(state.tared);
+        assert!(!state.report_raw);
+        assert_eq!(state.measurement_status, MeasurementTaskStatus::Disabled);
+    }
+
+    #[test]
+    fn linearity_points_are_limited() {
+        let mut state = DeviceState::new();
+        state.linearity_points = [Some(CalibrationPoint {
+            raw: 0.0,
+            weight: 0.0,
+        }); MAX_LINEARITY_POINTS];
+
+        process(&mut state, &[0x97, 0x42, 0x8C, 0x00, 0x00]);
+        assert_eq!(state.measurement_status, MeasurementTaskStatus::Disabled);
+
+        state.linearity_points[0] = None;
+        process(&mut state, &[0x97, 0x42, 0x8C, 0x00, 0x00]);
+        assert_eq!(
+            state.measurement_status,
+            MeasurementTaskStatus::LinearityPoint(70.0)
+        );
+    }
+
+    #[test]
+    fn check_linearity_reports_and_discards_the_points() {
+        let mut state = DeviceState::new();
+        process(&mut state, &[0x94, 1]);
+        for (i, (raw, weight)) in [(0.0, 0.0), (1000.0, 10_000.0), (2000.0, 20_000.0)]
+            .into_iter()
+            .enumerate()
+        {
+            state.linearity_points[i] = Some(CalibrationPoint { raw, weight });
+        }
+
+        let sent = process(&mut state, &[0x98]);
+        assert_eq!(codes(&sent), [0x17]);
+        assert_eq!(&sent[0].payload()[0..4], 0.0f32.to_le_bytes());
+        assert_eq!(sent[0].payload()[4], 1);
+        assert!(state.linearity_points.iter().all(Option::is_none));
+
+        // Nothing left to check
+        assert!(process(&mut state, &[0x98]).is_empty());
+    }
+
+    #[test]
+    fn timestamps_are_encoded_in_their_unit() {
+        assert_eq!(TimestampUnit::Micros.encode(1_234_567), 1_234_567);
+        assert_eq!(TimestampUnit::Millis.encode(1_234_567), 1234);
+    }
+
+    #[test]
+    fn millisecond_timestamps_outlast_the_microsecond_wrap() {
+        // Five hours, well past the ~71.6 minutes of microseconds in a u32
+        let elapsed_us = 5 * 3600 * 1_000_000;
+
+        assert_eq!(TimestampUnit::Millis.encode(elapsed_us), 18_000_000);
+        assert_eq!(TimestampUnit::Micros.encode(elapsed_us), elapsed_us as u32);
+        assert_eq!(TimestampUnit::Micros.encode(u32::MAX as u64 + 1), 0);
+    }
+
+    #[test]
+    fn timestamp_unit_is_set_from_its_wire_value() {
+        let mut state = DeviceState::new();
+        assert_eq!(state.timestamp_unit, TimestampUnit::Micros);
+
+        process(&mut state, &[0x99, 1]);
+        assert_eq!(state.timestamp_unit, TimestampUnit::Millis);
+
+        process(&mut state, &[0x99, 2]);
+        assert_eq!(state.timestamp_unit, TimestampUnit::Millis);
+        process(&mut state, &[0x99]);
+        assert_eq!(state.timestamp_unit, TimestampUnit::Millis);
+        process(&mut state, &[0x99, 0]);
+        assert_eq!(state.timestamp_unit, TimestampUnit::Micros);
+    }
+
+    #[test]
+    fn get_calibration_reads_the_stored_calibration() {
+        let mut state = DeviceState::new();
+        let sent = process(&mut state, &[0x72]);
+        assert_eq!(codes(&sent), [0x00]);
+        assert_eq!(&sent[0].payload()[0..4], 0i32.to_le_bytes());
+
+        Calibration::new(-1200, 0.5).write_to_flash().unwrap();
+
+        let sent = process(&mut state, &[0x72]);
+        assert_eq!(&sent[0].payload()[0..4], (-1200i32).to_le_bytes());
+        assert_eq!(&sent[0].payload()[4..8], 0.5f32.to_le_bytes());
+    }
+}
@@ -0,0 +1,321 @@
+/// Provisioning
+///
+/// Configuration blob exported from one device and imported on others, to provision a fleet
+/// identically.
+///
+/// The blob is little endian, with the following layout:
+///
+/// | Offset | Size | Field                                                  |
+/// |--------|------|--------------------------------------------------------|
+/// | 0      | 1    | Layout version                                         |
+/// | 1      | 6    | Device ID of the exporting device, not imported        |
+/// | 7      | 4    | Zero reference as a raw reading (i32), tare included   |
+/// | 11     | 4    | Scale in kg per raw count (f32)                        |
+/// | 15     | 1    | Length of the device name, zero keeps the current name |
+/// | 16     | 26   | Device name (UTF-8), zero padded                       |
+/// | 42     | 4    | CRC-32 of the previous bytes, see `SessionChecksum`    |
+///
+/// The device ID identifies a unit, so importing it would give the whole fleet the same one.
+/// The name is imported, and applied by rebooting like `SetName`. Devices without a stored name
+/// export an empty one, the name built into the firmware isn't exported.
+///
+/// It is streamed in chunks of up to [`CONFIG_CHUNK_SIZE`] bytes, each prefixed with its
+/// offset in the blob.
+use defmt::Format;
+
+use crate::{
+    device_id::DEVICE_ID_SIZE,
+    device_name::{DeviceName, MAX_NAME_LEN},
+    hx711::Calibration,
+    metrics::SessionChecksum,
+};
+
+/// Version of the blob layout
+const CONFIG_BLOB_VERSION: u8 = 2;
+/// Size of the configuration blob in bytes
+pub const CONFIG_BLOB_SIZE: usize = 16 + MAX_NAME_LEN + 4;
+/// Maximum number of blob bytes per chunk
+pub const CONFIG_CHUNK_SIZE: usize = 8;
+/// Offset of the name length in the blob, followed by the name
+const NAME_OFFSET: usize = 15;
+/// Offset of the CRC in the blob
+const CRC_OFFSET: usize = CONFIG_BLOB_SIZE - 4;
+
+/// Errors importing a configuration blob
+#[derive(Debug, Clone, Copy, PartialEq, Format)]
+pub enum ConfigError {
+    /// A chunk doesn't continue the blob received so far
+    UnexpectedChunk,
+    /// The CRC doesn't match the blob
+    Crc,
+    /// The blob has an unknown layout version
+    Version,
+    /// The blob holds an invalid calibration
+    InvalidCalibration,
+    /// The blob holds an invalid device name
+    InvalidName,
+}
+
+/// Settings imported from a configuration blob
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImportedConfig {
+    /// Calibration to apply
+    pub calibration: Calibration,
+    /// Name to store, `None` keeps the current one
+    pub name: Option<DeviceName>,
+}
+
+/// Build the configuration blob of a device
+pub fn export(
+    calibration: Calibration,
+    device_id: [u8; DEVICE_ID_SIZE],
+    name: Option<DeviceName>,
+) -> [u8; CONFIG_BLOB_SIZE] {
+    let mut blob = [0u8; CONFIG_BLOB_SIZE];
+    blob[0] = CONFIG_BLOB_VERSION;
+    blob[1..7].copy_from_slice(&device_id);
+    blob[7..11].copy_from_slice(&calibration.zero_raw().to_le_bytes());
+    blob[11..15].copy_from_slice(&calibration.scale().to_le_bytes());
+    if let Some(name) = name {
+        let name = name.as_str().as_bytes();
+        blob[NAME_OFFSET] = name.len() as u8;
+        blob[NAME_OFFSET + 1..NAME_OFFSET + 1 + name.len()].copy_from_slice(name);
+    }
+
+    let mut crc = SessionChecksum::new();
+    crc.update(&blob[..CRC_OFFSET]);
+    blob[CRC_OFFSET..].copy_from_slice(&crc.value().to_le_bytes());
+    blob
+}
+
+/// Validate a configuration blob and get the settings it holds
+pub fn import(blob: &[u8; CONFIG_BLOB_SIZE]) -> Result<ImportedConfig, ConfigError> {
+    let mut crc = SessionChecksum::new();
+    crc.update(&blob[..CRC_OFFSET]);
+    let expected = u32::from_le_bytes(blob[CRC_OFFSET..].try_into().unwrap());
+    if crc.value() != expected {
+        return Err(ConfigError::Crc);
+    }
+
+    if blob[0] != CONFIG_BLOB_VERSION {
+        return Err(ConfigError::Version);
+    }
+
+    let zero_raw = i32::from_le_bytes([blob[7], blob[8], blob[9], blob[10]]);
+    let scale = f32::from_le_bytes([blob[11], blob[12], blob[13], blob[14]]);
+    let calibration = Calibration::new(zero_raw, scale);
+    if !calibration.is_valid() {
+        return Err(ConfigError::InvalidCalibration);
+    }
+
+    let name = match blob[NAME_OFFSET] as usize {
+        0 => None,
+        len if len <= MAX_NAME_LEN => Some(
+            DeviceName::new(&blob[NAME_OFFSET + 1..NAME_OFFSET + 1 + len])
+                .map_err(|_| ConfigError::InvalidName)?,
+        ),
+        _ => return Err(ConfigError::InvalidName),
+    };
+
+    Ok(ImportedConfig { calibration, name })
+}
+
+/// Configuration blob export in progress
+#[derive(Debug, Clone, Copy)]
+pub struct ConfigExport {
+    /// Blob being streamed
+    blob: [u8; CONFIG_BLOB_SIZE],
+    /// Offset of the next chunk to send
+    next: usize,
+}
+
+impl ConfigExport {
+    /// Start streaming a blob built with [`export`]
+    pub fn new(blob: [u8; CONFIG_BLOB_SIZE]) -> Self {
+        Self { blob, next: 0 }
+    }
+
+    /// Send the remaining chunks along with their offset in the blob
+    ///
+    /// Stops at the first chunk `send` doesn't accept (e.g. the channel is full), which is sent
+    /// first on the next call.
+    pub fn send_chunks(&mut self, mut send: impl FnMut(u8, &[u8]) -> bool) {
+        while self.next < CONFIG_BLOB_SIZE {
+            let end = (self.next + CONFIG_CHUNK_SIZE).min(CONFIG_BLOB_SIZE);
+            if !send(self.next as u8, &self.blob[self.next..end]) {
+                return;
+            }
+            self.next = end;
+        }
+    }
+
+    /// Check if the whole blob has been sent
+    pub fn is_done(&self) -> bool {
+        self.next >= CONFIG_BLOB_SIZE
+    }
+}
+
+/// Reassembles a configuration blob from chunks sent in order
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConfigImport {
+    /// Blob received so far
+    blob: [u8; CONFIG_BLOB_SIZE],
+    /// Number of bytes received
+    received: usize,
+}
+
+impl Default for ConfigImport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ConfigImport {
+    /// Create an empty import
+    pub const fn new() -> Self {
+        Self {
+            blob: [0; CONFIG_BLOB_SIZE],
+            received: 0,
+        }
+    }
+
+    /// Add a chunk at `offset` in the blob
+    ///
+    /// A chunk at offset zero restarts the import. Returns the imported settings once the
+    /// whole blob has been received, and `None` while more chunks are expected.
+    pub fn add_chunk(
+        &mut self,
+        offset: usize,
+        data: &[u8],
+    ) -> Option<Result<ImportedConfig, ConfigError>> {
+        if offset == 0 {
+            *self = Self::new();
+        }
+
+        let end = offset + data.len();
+        if offset != self.received || data.len() > CONFIG_CHUNK_SIZE || end > CONFIG_BLOB_SIZE {
+            *self = Self::new();
+            return Some(Err(ConfigError::UnexpectedChunk));
+        }
+
+        self.blob[offset..end].copy_from_slice(data);
+        self.received = end;
+        if self.received < CONFIG_BLOB_SIZE {
+            return None;
+        }
+
+        let result = import(&self.blob);
+        *self = Self::new();
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: [u8; DEVICE_ID_SIZE] = *b"AAAAAA";
+
+    fn calibration() -> Calibration {
+        Calibration::new(-1200, 0.000066)
+    }
+
+    /// Feed a blob to an import in chunks, as sent by `ConfigExport`
+    fn import_in_chunks(blob: [u8; CONFIG_BLOB_SIZE]) -> Result<ImportedConfig, ConfigError> {
+        let mut import = ConfigImport::new();
+        let mut export = ConfigExport::new(blob);
+        let mut result = None;
+        export.send_chunks(|offset, chunk| {
+            result = import.add_chunk(offset as usize, chunk);
+            true
+        });
+        result.unwrap()
+    }
+
+    #[test]
+    fn export_roundtrips_with_a_name() {
+        let name = DeviceName::new(b"Gym wall").unwrap();
+        let blob = export(calibration(), ID, Some(name));
+
+        let config = import_in_chunks(blob).unwrap();
+
+        assert_eq!(config.calibration, calibration());
+        assert_eq!(config.name, Some(name));
+    }
+
+    #[test]
+    fn empty_name_keeps_the_current_one() {
+        let config = import_in_chunks(export(calibration(), ID, None)).unwrap();
+
+        assert_eq!(config.name, None);
+    }
+
+    #[test]
+    fn corrupted_blob_is_rejected() {
+        let mut blob = export(calibration(), ID, None);
+        blob[8] ^= 0x01;
+
+        assert_eq!(import(&blob), Err(ConfigError::Crc));
+    }
+
+    #[test]
+    fn unknown_version_is_rejected() {
+        let mut blob = export(calibration(), ID, None);
+        blob[0] = 1;
+        let mut crc = SessionChecksum::new();
+        crc.update(&blob[..CRC_OFFSET]);
+        blob[CRC_OFFSET..].copy_from_slice(&crc.value().to_le_bytes());
+
+        assert_eq!(import(&blob), Err(ConfigError::Version));
+    }
+
+    #[test]
+    fn invalid_name_is_rejected() {
+        let mut blob = export(calibration(), ID, None);
+        blob[NAME_OFFSET] = MAX_NAME_LEN as u8 + 1;
+        let mut crc = SessionChecksum::new();
+        crc.update(&blob[..CRC_OFFSET]);
+        blob[CRC_OFFSET..].copy_from_slice(&crc.value().to_le_bytes());
+
+        assert_eq!(import(&blob), Err(ConfigError::InvalidName));
+    }
+
+    #[test]
+    fn out_of_order_chunk_restarts_the_import() {
+        let blob = export(calibration(), ID, None);
+        let mut import = ConfigImport::new();
+
+        assert_eq!(import.add_chunk(0, &blob[..8]), None);
+        assert_eq!(
+            import.add_chunk(16, &blob[16..24]),
+            Some(Err(ConfigError::UnexpectedChunk))
+        );
+        assert_eq!(
+            import.add_chunk(8, &blob[8..16]),
+            Some(Err(ConfigError::UnexpectedChunk))
+        );
+    }
+
+    #[test]
+    fn export_resumes_after_a_full_channel() {
+        let blob = export(calibration(), ID, None);
+        let mut export = ConfigExport::new(blob);
+        let mut offsets = Vec::new();
+
+        export.send_chunks(|offset, _| {
+            if offsets.len() == 2 {
+                return false;
+            }
+            offsets.push(offset);
+            true
+        });
+        assert!(!export.is_done());
+
+        export.send_chunks(|offset, _| {
+            offsets.push(offset);
+            true
+        });
+        assert_eq!(offsets, [0, 8, 16, 24, 32, 40]);
+        assert!(export.is_done());
+    }
+}
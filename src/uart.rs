@@ -0,0 +1,60 @@
+/// UART streaming
+///
+/// With the `uart-stream` feature, every weight measurement sent over BLE is mirrored to UART0
+/// as a CSV line, for wired setups that need a complete dataset:
+///
+/// ```text
+/// <timestamp in µs since the measurement started>,<weight>\n
+/// ```
+///
+/// The weight is in the reported unit (kg, or percentage of the bodyweight). UART0 transmits
+/// on GPIO21 at 115200 baud, 8N1, the default serial port of ESP32-C3 boards. Lines are queued
+/// to a separate task, so a slow reader drops lines instead of stalling measurements.
+use core::fmt::Write;
+
+use arrayvec::ArrayString;
+use defmt::warn;
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, channel::Channel};
+use esp_hal::{uart::UartTx, Async};
+
+/// Baud rate of the UART stream
+pub const UART_BAUDRATE: u32 = 115_200;
+/// Number of lines queued for the UART task, 400ms of measurements at 80Hz
+const UART_CHANNEL_SIZE: usize = 32;
+
+/// A CSV line, long enough for any timestamp and weight
+type UartLine = ArrayString<32>;
+
+/// Lines waiting to be written to the UART
+static UART_LINES: Channel<CriticalSectionRawMutex, UartLine, UART_CHANNEL_SIZE> = Channel::new();
+
+/// Queue a weight measurement to be written to the UART
+///
+/// Never blocks: the line is dropped if the queue is full.
+pub fn mirror(timestamp: u64, weight: f32) {
+    let mut line = UartLine::new();
+    if writeln!(line, "{},{:.3}", timestamp, weight).is_err() {
+        return;
+    }
+    if UART_LINES.try_send(line).is_err() {
+        warn!("UART stream queue full, line dropped");
+    }
+}
+
+/// Write the queued lines to the UART
+#[embassy_executor::task]
+pub async fn uart_task(mut tx: UartTx<'static, Async>) {
+    loop {
+        let line = UART_LINES.receive().await;
+        let mut bytes = line.as_bytes();
+        while !bytes.is_empty() {
+            match tx.write_async(bytes).await {
+                Ok(written) => bytes = &bytes[written..],
+                Err(e) => {
+                    warn!("UART write error: {:?}", e);
+                    break;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,130 @@
+/// Watchdog
+///
+/// Resets the device when the firmware stops making progress, instead of leaving it advertising
+/// without streaming. The MWDT of timer group 1 is fed by `watchdog_task`, which runs on the same
+/// executor as the BLE stack and the measurement task, so it stops feeding when any of them
+/// blocks the executor.
+///
+/// The measurement task also checks in on every iteration of its loop, and the watchdog is no
+/// longer fed once it hasn't for [`WATCHDOG_TIMEOUT_S`], e.g. when it waits forever on the load
+/// cell. The BLE side is monitored through the operations it expects to complete: starting to
+/// advertise, replying to a GATT event and sending a notification. Waiting for a central or for
+/// the client is idle time and can last forever, but an operation pending for
+/// [`BLE_OPERATION_TIMEOUT_S`] means the BLE host is wedged.
+///
+/// The reset is reported as `ResetReason::Watchdog` on the next boot.
+use core::cell::Cell;
+
+use critical_section::Mutex;
+use defmt::{error, info};
+use embassy_time::{Duration, Instant, Timer};
+use esp_hal::{
+    peripherals::TIMG1,
+    time,
+    timer::timg::{MwdtStage, Wdt},
+};
+
+/// Time in seconds without progress after which the device is reset
+///
+/// Covers the slowest iteration of the measurement task: 256 averaged samples, or a 100 sample
+/// calibration point, at the 10Hz rate of the HX711 take ~26s and ~10s.
+pub const WATCHDOG_TIMEOUT_S: u64 = 30;
+/// Time in seconds a BLE operation can stay pending before the device is reset
+///
+/// Generous, as a notification waits for the client to make room in the connection, and a slow
+/// or distant client can hold it for a while.
+pub const BLE_OPERATION_TIMEOUT_S: u64 = 60;
+/// Interval in seconds between feeds of the hardware watchdog
+const WATCHDOG_FEED_INTERVAL_S: u64 = 1;
+/// Number of [`BleOperation`] variants
+const BLE_OPERATIONS: usize = 3;
+
+/// Time the measurement task last checked in, boot until it does
+static LAST_CHECK_IN: Mutex<Cell<Instant>> = Mutex::new(Cell::new(Instant::from_ticks(0)));
+/// Start time of the pending BLE operations, indexed by [`BleOperation`]
+static BLE_PENDING: Mutex<Cell<[Option<Instant>; BLE_OPERATIONS]>> =
+    Mutex::new(Cell::new([None; BLE_OPERATIONS]));
+
+/// BLE operations expected to complete, tracked separately as they run concurrently
+#[derive(Clone, Copy)]
+pub enum BleOperation {
+    /// Starting to advertise
+    Advertise = 0,
+    /// Replying to a GATT event
+    GattReply = 1,
+    /// Sending a notification
+    Notify = 2,
+}
+
+/// A pending BLE operation, done when dropped
+///
+/// Dropping also covers operations cancelled with their connection.
+pub struct PendingBleOperation(BleOperation);
+
+impl Drop for PendingBleOperation {
+    fn drop(&mut self) {
+        set_ble_pending(self.0, None);
+    }
+}
+
+/// Record that the measurement task is making progress
+pub fn check_in() {
+    critical_section::with(|cs| LAST_CHECK_IN.borrow(cs).set(Instant::now()));
+}
+
+/// Record the start of a BLE operation, which has to be done within [`BLE_OPERATION_TIMEOUT_S`]
+pub fn ble_operation(operation: BleOperation) -> PendingBleOperation {
+    set_ble_pending(operation, Some(Instant::now()));
+    PendingBleOperation(operation)
+}
+
+/// Set or clear the start time of a pending BLE operation
+fn set_ble_pending(operation: BleOperation, start: Option<Instant>) {
+    critical_section::with(|cs| {
+        let pending = BLE_PENDING.borrow(cs);
+        let mut operations = pending.get();
+        operations[operation as usize] = start;
+        pending.set(operations);
+    });
+}
+
+/// Check whether a BLE operation has been pending for longer than the timeout
+fn ble_stalled() -> bool {
+    let timeout = Duration::from_secs(BLE_OPERATION_TIMEOUT_S);
+    critical_section::with(|cs| BLE_PENDING.borrow(cs).get())
+        .iter()
+        .flatten()
+        .any(|start| start.elapsed() >= timeout)
+}
+
+/// Feed the hardware watchdog while the measurement task keeps checking in and no BLE operation
+/// is stalled
+#[embassy_executor::task]
+pub async fn watchdog_task(mut wdt: Wdt<TIMG1<'static>>) {
+    wdt.set_timeout(
+        MwdtStage::Stage0,
+        time::Duration::from_secs(WATCHDOG_TIMEOUT_S),
+    );
+    wdt.enable();
+    info!("Watchdog enabled, {}s timeout", WATCHDOG_TIMEOUT_S);
+
+    let timeout = Duration::from_secs(WATCHDOG_TIMEOUT_S);
+    let mut stalled = false;
+    loop {
+        let last_check_in = critical_section::with(|cs| LAST_CHECK_IN.borrow(cs).get());
+        let measurement_stalled = last_check_in.elapsed() >= timeout;
+        let ble_stalled = ble_stalled();
+        if !measurement_stalled && !ble_stalled {
+            wdt.feed();
+            stalled = false;
+        } else if !stalled {
+            if measurement_stalled {
+                error!("Measurement task stalled, resetting on watchdog timeout");
+            } else {
+                error!("BLE operation stalled, resetting on watchdog timeout");
+            }
+            stalled = true;
+        }
+        Timer::after(Duration::from_secs(WATCHDOG_FEED_INTERVAL_S)).await;
+    }
+}